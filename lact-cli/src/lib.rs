@@ -1,6 +1,15 @@
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use lact_client::DaemonClient;
-use lact_schema::args::{CliArgs, CliCommand};
+use lact_schema::{
+    args::{
+        CliArgs, CliCommand, ClocksCommand, ConfigCommand, FanCommand, PmfwCommand, PowerCapCommand,
+    },
+    request::{ConfirmCommand, SetClocksCommand},
+    FanControlMode, FanOptions, FanSetting, PmfwOptions, TemperatureSmoothing,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{fs, path::Path};
 
 pub fn run(args: CliArgs) -> Result<()> {
     let rt = tokio::runtime::Builder::new_current_thread()
@@ -13,13 +22,53 @@ pub fn run(args: CliArgs) -> Result<()> {
         match args.subcommand {
             CliCommand::ListGpus => list_gpus(&args, &client).await,
             CliCommand::Info => info(&args, &client).await,
-            CliCommand::Snapshot => snapshot(&client).await,
+            CliCommand::Snapshot => snapshot(&args, &client).await,
+            CliCommand::PowerCap(ref command) => power_cap(&args, &client, command).await,
+            CliCommand::PerformanceLevel { level } => {
+                let id = single_gpu_id(&args, &client).await?;
+                let delay = client.set_performance_level(&id, level.into()).await?;
+                handle_pending_config(&args, &client, &id, delay).await
+            }
+            CliCommand::Clocks(ref command) => clocks(&args, &client, command).await,
+            CliCommand::Fan(ref command) => fan(&args, &client, command).await,
+            CliCommand::Pmfw(ref command) => pmfw(&args, &client, command).await,
+            CliCommand::PowerStates { kind, ref states } => {
+                let id = single_gpu_id(&args, &client).await?;
+                let delay = client
+                    .set_enabled_power_states(&id, kind.into(), states.clone())
+                    .await?;
+                handle_pending_config(&args, &client, &id, delay).await
+            }
+            CliCommand::PowerProfileMode {
+                index,
+                ref custom_heuristics,
+            } => {
+                let id = single_gpu_id(&args, &client).await?;
+                let custom_heuristics = custom_heuristics
+                    .iter()
+                    .map(|heuristics| heuristics.0.clone())
+                    .collect();
+                let delay = client
+                    .set_power_profile_mode(&id, index, custom_heuristics)
+                    .await?;
+                handle_pending_config(&args, &client, &id, delay).await
+            }
+            CliCommand::Pending => pending(&args, &client).await,
+            CliCommand::Confirm => confirm(&args, &client, ConfirmCommand::Confirm).await,
+            CliCommand::Revert => confirm(&args, &client, ConfirmCommand::Revert).await,
+            CliCommand::Config(ConfigCommand::Check { ref file }) => {
+                check_config(&args, &client, file).await
+            }
         }
     })
 }
 
-async fn list_gpus(_: &CliArgs, client: &DaemonClient) -> Result<()> {
+async fn list_gpus(args: &CliArgs, client: &DaemonClient) -> Result<()> {
     let entries = client.list_devices().await?;
+    if args.json {
+        return print_json(&entries);
+    }
+
     for entry in entries {
         let id = entry.id;
         if let Some(name) = entry.name {
@@ -32,6 +81,15 @@ async fn list_gpus(_: &CliArgs, client: &DaemonClient) -> Result<()> {
 }
 
 async fn info(args: &CliArgs, client: &DaemonClient) -> Result<()> {
+    if args.json {
+        let mut infos = Vec::new();
+        for id in extract_gpu_ids(args, client).await {
+            let info = client.get_device_info(&id).await?;
+            infos.push(json!({ "id": id, "info": info }));
+        }
+        return print_json(&infos);
+    }
+
     for id in extract_gpu_ids(args, client).await {
         let info = client.get_device_info(&id).await?;
         let pci_info = info.pci_info.context("GPU reports no pci info")?;
@@ -64,8 +122,273 @@ async fn extract_gpu_ids(args: &CliArgs, client: &DaemonClient) -> Vec<String> {
     }
 }
 
-async fn snapshot(client: &DaemonClient) -> Result<()> {
+/// Setting changes apply to a single GPU, which can only be omitted if there is just one
+async fn single_gpu_id(args: &CliArgs, client: &DaemonClient) -> Result<String> {
+    match args.gpu_id {
+        Some(ref id) => Ok(id.clone()),
+        None => {
+            let mut entries = client.list_devices().await?;
+            match entries.len() {
+                0 => Err(anyhow!("No GPUs found")),
+                1 => Ok(entries.remove(0).id),
+                _ => Err(anyhow!(
+                    "Multiple GPUs found, please specify one with --gpu-id"
+                )),
+            }
+        }
+    }
+}
+
+async fn snapshot(args: &CliArgs, client: &DaemonClient) -> Result<()> {
     let path = client.generate_debug_snapshot().await?;
+    if args.json {
+        return print_json(&json!({ "path": path }));
+    }
     println!("Generated debug snapshot in {path}");
     Ok(())
 }
+
+async fn power_cap(args: &CliArgs, client: &DaemonClient, command: &PowerCapCommand) -> Result<()> {
+    let id = single_gpu_id(args, client).await?;
+    let cap = match command {
+        PowerCapCommand::Set { value } => Some(*value),
+        PowerCapCommand::Reset => None,
+    };
+    let delay = client.set_power_cap(&id, cap).await?;
+    handle_pending_config(args, client, &id, delay).await
+}
+
+async fn clocks(args: &CliArgs, client: &DaemonClient, command: &ClocksCommand) -> Result<()> {
+    let id = single_gpu_id(args, client).await?;
+    let delay = match command {
+        ClocksCommand::Set {
+            kind,
+            value,
+            pstate,
+        } => {
+            let command = SetClocksCommand {
+                r#type: kind.with_pstate(*pstate),
+                value: Some(*value),
+            };
+            client.set_clocks_value(&id, command).await?
+        }
+        ClocksCommand::Unset { kind, pstate } => {
+            let command = SetClocksCommand {
+                r#type: kind.with_pstate(*pstate),
+                value: None,
+            };
+            client.set_clocks_value(&id, command).await?
+        }
+        ClocksCommand::Batch { values } => {
+            let commands = values
+                .iter()
+                .map(|assignment| SetClocksCommand {
+                    r#type: assignment.kind.with_pstate(assignment.pstate),
+                    value: Some(assignment.value),
+                })
+                .collect();
+            client.batch_set_clocks_value(&id, commands).await?
+        }
+        ClocksCommand::Reset => {
+            client
+                .set_clocks_value(&id, SetClocksCommand::reset())
+                .await?
+        }
+    };
+    handle_pending_config(args, client, &id, delay).await
+}
+
+async fn fan(args: &CliArgs, client: &DaemonClient, command: &FanCommand) -> Result<()> {
+    let id = single_gpu_id(args, client).await?;
+    // The daemon replaces the PMFW options, so the configured ones are sent back as-is
+    let pmfw = client.get_device_stats(&id).await?.fan.pmfw_options;
+
+    let opts = match command {
+        FanCommand::Auto => FanOptions {
+            id: &id,
+            enabled: false,
+            pmfw,
+            ..Default::default()
+        },
+        FanCommand::Static { speed } => FanOptions {
+            id: &id,
+            enabled: true,
+            mode: Some(FanControlMode::Static),
+            static_speed: Some(f64::from(*speed) / 100.0),
+            pmfw,
+            ..Default::default()
+        },
+        FanCommand::Curve {
+            points,
+            spindown_delay_ms,
+            change_threshold,
+            ema_alpha,
+            moving_average_samples,
+            hysteresis_up,
+            hysteresis_down,
+            max_pwm_slew_rate,
+            clear,
+        } => FanOptions {
+            id: &id,
+            enabled: true,
+            mode: Some(FanControlMode::Curve),
+            curve: (!points.is_empty()).then(|| points.iter().copied().collect()),
+            pmfw,
+            spindown_delay_ms: *spindown_delay_ms,
+            change_threshold: *change_threshold,
+            temperature_smoothing: ema_alpha
+                .map(|alpha| TemperatureSmoothing::Ema { alpha })
+                .or(moving_average_samples
+                    .map(|samples| TemperatureSmoothing::MovingAverage { samples })),
+            hysteresis_up: *hysteresis_up,
+            hysteresis_down: *hysteresis_down,
+            max_pwm_slew_rate: *max_pwm_slew_rate,
+            clear: clear.iter().copied().map(FanSetting::from).collect(),
+            ..Default::default()
+        },
+    };
+
+    let delay = client.set_fan_control(opts).await?;
+    handle_pending_config(args, client, &id, delay).await
+}
+
+async fn pmfw(args: &CliArgs, client: &DaemonClient, command: &PmfwCommand) -> Result<()> {
+    let id = single_gpu_id(args, client).await?;
+
+    let delay = match command {
+        PmfwCommand::Set {
+            acoustic_limit,
+            acoustic_target,
+            minimum_pwm,
+            target_temperature,
+            zero_rpm,
+            zero_rpm_threshold,
+        } => {
+            let stats = client.get_device_stats(&id).await?;
+
+            // Only the given options are changed, the rest keep their configured values
+            let mut pmfw = stats.fan.pmfw_options;
+            pmfw.merge(PmfwOptions {
+                acoustic_limit: *acoustic_limit,
+                acoustic_target: *acoustic_target,
+                minimum_pwm: *minimum_pwm,
+                target_temperature: *target_temperature,
+                zero_rpm: *zero_rpm,
+                zero_rpm_threshold: *zero_rpm_threshold,
+            });
+
+            // Fan control mode is left as-is, only the PMFW options are changed
+            let opts = FanOptions {
+                id: &id,
+                enabled: stats.fan.control_enabled,
+                pmfw,
+                ..Default::default()
+            };
+            client.set_fan_control(opts).await?
+        }
+        PmfwCommand::Reset => client.reset_pmfw(&id).await?,
+    };
+    handle_pending_config(args, client, &id, delay).await
+}
+
+async fn handle_pending_config(
+    args: &CliArgs,
+    client: &DaemonClient,
+    id: &str,
+    delay: u64,
+) -> Result<()> {
+    if args.confirm {
+        client
+            .confirm_pending_config(Some(id), ConfirmCommand::Confirm)
+            .await?;
+    }
+
+    if args.json {
+        return print_json(&json!({
+            "confirmed": args.confirm,
+            "confirm_timeout_secs": delay,
+        }));
+    }
+
+    if args.confirm {
+        println!("Settings applied and confirmed");
+    } else {
+        println!("Settings applied, run `lact cli confirm` within {delay} seconds to keep them");
+    }
+    Ok(())
+}
+
+async fn pending(args: &CliArgs, client: &DaemonClient) -> Result<()> {
+    let pending_configs = client.list_pending_configs().await?;
+    if args.json {
+        return print_json(&pending_configs);
+    }
+
+    if pending_configs.is_empty() {
+        println!("No pending configuration changes");
+    }
+    for pending in pending_configs {
+        println!(
+            "{}: reverted in {} seconds",
+            pending.id, pending.seconds_left
+        );
+    }
+    Ok(())
+}
+
+async fn confirm(args: &CliArgs, client: &DaemonClient, command: ConfirmCommand) -> Result<()> {
+    let confirmed = matches!(command, ConfirmCommand::Confirm);
+    client
+        .confirm_pending_config(args.gpu_id.as_deref(), command)
+        .await?;
+
+    if args.json {
+        return print_json(&json!({ "confirmed": confirmed }));
+    }
+
+    if confirmed {
+        println!("Pending configuration confirmed");
+    } else {
+        println!("Pending configuration reverted");
+    }
+    Ok(())
+}
+
+/// Checks every GPU of a full config file, or the settings of a single GPU, against the GPU limits
+async fn check_config(args: &CliArgs, client: &DaemonClient, file: &Path) -> Result<()> {
+    let raw_config =
+        fs::read_to_string(file).with_context(|| format!("Could not read '{}'", file.display()))?;
+    let config: Value = serde_yaml::from_str(&raw_config).context("Could not parse config file")?;
+
+    // Full configs are resolved by the daemon, so that the settings inherited by profiles are checked too
+    let problems = if config.get("gpus").is_some() || config.get("profiles").is_some() {
+        client.validate_config(config, args.gpu_id.clone()).await?
+    } else {
+        let id = single_gpu_id(args, client).await?;
+        client.validate_gpu_config(&id, config).await?
+    };
+
+    if args.json {
+        print_json(&problems)?;
+    } else {
+        for problem in &problems {
+            println!("{}: {}", problem.field, problem.message);
+        }
+    }
+
+    match problems.len() {
+        0 => {
+            if !args.json {
+                println!("No problems found");
+            }
+            Ok(())
+        }
+        count => Err(anyhow!("Found {count} problems in the config")),
+    }
+}
+
+fn print_json<T: Serialize + ?Sized>(value: &T) -> Result<()> {
+    let output = serde_json::to_string_pretty(value)?;
+    println!("{output}");
+    Ok(())
+}
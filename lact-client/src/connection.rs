@@ -8,6 +8,10 @@ use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader
 pub trait DaemonConnection {
     fn request<'a>(&'a mut self, payload: &'a str) -> BoxFuture<'a, anyhow::Result<String>>;
 
+    /// Read the next line sent by the service without making a request, used for streamed responses.
+    /// Returns an empty string if the connection was closed.
+    fn read_line(&mut self) -> BoxFuture<'_, anyhow::Result<String>>;
+
     /// Establish a new connection to the same service
     fn new_connection(&self) -> BoxFuture<'_, anyhow::Result<Box<dyn DaemonConnection>>>;
 }
@@ -28,3 +32,11 @@ async fn request(
 
     Ok(response_payload)
 }
+
+async fn read_line(
+    socket: &mut BufReader<impl AsyncRead + AsyncWrite + Unpin>,
+) -> anyhow::Result<String> {
+    let mut payload = String::new();
+    socket.read_line(&mut payload).await?;
+    Ok(payload)
+}
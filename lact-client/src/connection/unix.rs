@@ -1,4 +1,4 @@
-use super::{request, DaemonConnection};
+use super::{read_line, request, DaemonConnection};
 use anyhow::Context;
 use futures::future::BoxFuture;
 use std::os::unix::net::UnixStream as StdUnixStream;
@@ -41,6 +41,10 @@ impl DaemonConnection for UnixConnection {
         Box::pin(async { request(&mut self.inner, payload).await })
     }
 
+    fn read_line(&mut self) -> BoxFuture<'_, anyhow::Result<String>> {
+        Box::pin(async { read_line(&mut self.inner).await })
+    }
+
     fn new_connection(&self) -> BoxFuture<'_, anyhow::Result<Box<dyn DaemonConnection>>> {
         Box::pin(async {
             let peer_addr = self
@@ -10,11 +10,13 @@ use amdgpu_sysfs::gpu_handle::{
 };
 use anyhow::Context;
 use connection::{tcp::TcpConnection, unix::UnixConnection, DaemonConnection};
+use futures::stream::{self, LocalBoxStream, StreamExt};
 use nix::unistd::getuid;
 use schema::{
     request::{ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, FanOptions, PowerStates, ProfilesInfo,
-    Request, Response, SystemInfo,
+    ClocksInfo, ConfigProblem, CrashRecoveryInfo, DeviceInfo, DeviceListEntry, DeviceStats,
+    FanOptions, GpuResetEvent, PendingConfig, PowerStates, ProfilesInfo, Request, Response,
+    SettingsDriftInfo, StatsHistorySample, SystemInfo,
 };
 use serde::de::DeserializeOwned;
 use std::{
@@ -81,15 +83,7 @@ impl DaemonClient {
 
             let request_payload = serde_json::to_string(&request)?;
             match stream.request(&request_payload).await {
-                Ok(response_payload) => {
-                    let response: Response<T> = serde_json::from_str(&response_payload)
-                        .context("Could not deserialize response from daemon")?;
-                    match response {
-                        Response::Ok(data) => Ok(data),
-                        Response::Error(err) => Err(anyhow::Error::new(err)
-                            .context("Got error from daemon, end of client boundary")),
-                    }
-                }
+                Ok(response_payload) => parse_response(&response_payload),
                 Err(err) => {
                     error!("Could not make request: {err}, reconnecting to socket");
                     let _ = self.status_tx.send(ConnectionStatusMsg::Disconnected);
@@ -117,6 +111,37 @@ impl DaemonClient {
         })
     }
 
+    /// Subscribe to stats updates of a GPU, sent by the daemon every `interval_ms`.
+    ///
+    /// The subscription uses a separate connection to the daemon, which is closed when the stream is dropped.
+    pub async fn subscribe_stats(
+        &self,
+        id: &str,
+        interval_ms: u64,
+    ) -> anyhow::Result<LocalBoxStream<'static, anyhow::Result<DeviceStats>>> {
+        let mut connection = self
+            .stream
+            .lock()
+            .await
+            .new_connection()
+            .await
+            .context("Could not open connection for stats subscription")?;
+
+        let request_payload = serde_json::to_string(&Request::SubscribeStats { id, interval_ms })?;
+        let response_payload = connection.request(&request_payload).await?;
+        parse_response::<()>(&response_payload)?;
+
+        let stream = stream::unfold(Some(connection), |connection| async move {
+            let mut connection = connection?;
+            match connection.read_line().await {
+                Ok(payload) if payload.is_empty() => None,
+                Ok(payload) => Some((parse_response(&payload), Some(connection))),
+                Err(err) => Some((Err(err), None)),
+            }
+        });
+        Ok(stream.boxed_local())
+    }
+
     pub async fn list_devices(&self) -> anyhow::Result<Vec<DeviceListEntry>> {
         self.make_request(Request::ListDevices).await
     }
@@ -146,14 +171,47 @@ impl DaemonClient {
     request_with_id!(reset_pmfw, ResetPmfw, u64);
     request_with_id!(dump_vbios, VbiosDump, Vec<u8>);
 
+    /// Checks the settings of a GPU against its limits without applying them
+    pub async fn validate_gpu_config(
+        &self,
+        id: &str,
+        config: serde_json::Value,
+    ) -> anyhow::Result<Vec<ConfigProblem>> {
+        self.make_request(Request::ValidateGpuConfig { id, config })
+            .await
+    }
+
+    /// Checks the settings of every GPU in a full config, including its profiles, without applying them
+    pub async fn validate_config(
+        &self,
+        config: serde_json::Value,
+        gpu_id: Option<String>,
+    ) -> anyhow::Result<Vec<ConfigProblem>> {
+        self.make_request(Request::ValidateConfig { config, gpu_id })
+            .await
+    }
+
     pub async fn list_profiles(&self, include_state: bool) -> anyhow::Result<ProfilesInfo> {
         self.make_request(Request::ListProfiles { include_state })
             .await
     }
 
     pub async fn set_profile(&self, name: Option<String>, auto_switch: bool) -> anyhow::Result<()> {
-        self.make_request(Request::SetProfile { name, auto_switch })
-            .await
+        self.make_request(Request::SetProfile {
+            name,
+            auto_switch,
+            gpu_id: None,
+        })
+        .await
+    }
+
+    pub async fn set_gpu_profile(&self, id: &str, name: Option<String>) -> anyhow::Result<()> {
+        self.make_request(Request::SetProfile {
+            name,
+            auto_switch: false,
+            gpu_id: Some(id.to_owned()),
+        })
+        .await
     }
 
     pub async fn create_profile(&self, name: String, base: ProfileBase) -> anyhow::Result<()> {
@@ -238,9 +296,60 @@ impl DaemonClient {
         .await
     }
 
-    pub async fn confirm_pending_config(&self, command: ConfirmCommand) -> anyhow::Result<()> {
-        self.make_request(Request::ConfirmPendingConfig(command))
-            .await
+    pub async fn get_stats_history(
+        &self,
+        id: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        resolution: Option<u64>,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        self.make_request(Request::GetStatsHistory {
+            id,
+            since,
+            until,
+            resolution,
+        })
+        .await
+    }
+
+    pub async fn get_crash_recovery_info(&self) -> anyhow::Result<Option<CrashRecoveryInfo>> {
+        self.make_request(Request::CrashRecoveryInfo).await
+    }
+
+    pub async fn list_gpu_resets(&self) -> anyhow::Result<Vec<GpuResetEvent>> {
+        self.make_request(Request::ListGpuResets).await
+    }
+
+    pub async fn list_settings_drift(&self) -> anyhow::Result<Vec<SettingsDriftInfo>> {
+        self.make_request(Request::ListSettingsDrift).await
+    }
+
+    pub async fn list_pending_configs(&self) -> anyhow::Result<Vec<PendingConfig>> {
+        self.make_request(Request::ListPendingConfigs).await
+    }
+
+    /// Confirms or reverts the pending change of the given GPU, or of all GPUs if `id` is not specified
+    pub async fn confirm_pending_config(
+        &self,
+        id: Option<&str>,
+        command: ConfirmCommand,
+    ) -> anyhow::Result<()> {
+        self.make_request(Request::ConfirmPendingConfig {
+            command,
+            id: id.map(str::to_owned),
+        })
+        .await
+    }
+}
+
+fn parse_response<T: DeserializeOwned>(payload: &str) -> anyhow::Result<T> {
+    let response: Response<T> =
+        serde_json::from_str(payload).context("Could not deserialize response from daemon")?;
+    match response {
+        Response::Ok(data) => Ok(data),
+        Response::Error(err) => {
+            Err(anyhow::Error::new(err).context("Got error from daemon, end of client boundary"))
+        }
     }
 }
 
@@ -1,19 +1,22 @@
 use crate::server::gpu_controller::{fan_control::FanCurve, VENDOR_NVIDIA};
 use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
-use anyhow::Context;
+use anyhow::{bail, Context};
 use indexmap::IndexMap;
 use lact_schema::{
     default_fan_curve,
     request::{ClockspeedType, SetClocksCommand},
-    FanControlMode, PmfwOptions, ProfileRule,
+    FanControlMode, FanOverride, FanSetting, PmfwOptions, ProfileRule, SensorCombination,
+    SensorCurve, TemperatureSmoothing,
 };
 use nix::unistd::getuid;
 use notify::{RecommendedWatcher, Watcher};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
+use serde_yaml::{Mapping, Value};
 use std::{
-    cell::Cell,
-    env, fs,
+    borrow::Cow,
+    cell::{Cell, OnceCell},
+    env, fmt, fs, iter,
     path::PathBuf,
     rc::Rc,
     time::{Duration, Instant},
@@ -27,6 +30,8 @@ const DEFAULT_ADMIN_GROUPS: [&str; 2] = ["wheel", "sudo"];
 const CONFIG_RELOAD_INTERVAL_MILLIS: u64 = 50;
 /// Period when config changes are ignored after LACT itself has edited the config
 const SELF_CONFIG_EDIT_PERIOD_MILLIS: u64 = 1000;
+/// GPU settings which are overridden field by field instead of being replaced as a whole
+const MERGED_GPU_SETTINGS: [&str; 2] = ["fan_control_settings", "pmfw_options"];
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Config {
@@ -38,11 +43,28 @@ pub struct Config {
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     gpus: IndexMap<String, Gpu>,
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
-    pub profiles: IndexMap<Rc<str>, Profile>,
+    profiles: IndexMap<Rc<str>, Profile>,
     #[serde(default)]
-    pub current_profile: Option<Rc<str>>,
+    current_profile: Option<Rc<str>>,
+    /// GPUs which use a different profile than `current_profile`, by GPU id.
+    /// `None` means the default profile.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    gpu_profiles: IndexMap<String, Option<Rc<str>>>,
     #[serde(default)]
     pub auto_switch_profiles: bool,
+    #[serde(skip)]
+    resolved_gpus: ResolvedGpus,
+}
+
+/// GPU configs of the active profiles with inherited settings, resolved on first use.
+/// It is derived from the rest of the config, so it is ignored when comparing configs.
+#[derive(Debug, Clone, Default)]
+struct ResolvedGpus(OnceCell<IndexMap<String, Gpu>>);
+
+impl PartialEq for ResolvedGpus {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
 impl Default for Config {
@@ -53,8 +75,10 @@ impl Default for Config {
             gpus: IndexMap::new(),
             profiles: IndexMap::new(),
             current_profile: None,
+            gpu_profiles: IndexMap::new(),
             auto_switch_profiles: false,
             version: 0,
+            resolved_gpus: ResolvedGpus::default(),
         }
     }
 }
@@ -67,6 +91,11 @@ pub struct Daemon {
     #[serde(default)]
     pub disable_clocks_cleanup: bool,
     pub tcp_listen_address: Option<String>,
+    pub metrics_listen_address: Option<String>,
+    pub stats_history: Option<StatsHistory>,
+    /// How often the applied settings are compared with the hardware state, 0 disables the check
+    #[serde(default = "default_settings_check_interval_secs")]
+    pub settings_check_interval_secs: u64,
 }
 
 impl Default for Daemon {
@@ -76,6 +105,35 @@ impl Default for Daemon {
             admin_groups: DEFAULT_ADMIN_GROUPS.map(str::to_owned).to_vec(),
             disable_clocks_cleanup: false,
             tcp_listen_address: None,
+            metrics_listen_address: None,
+            stats_history: None,
+            settings_check_interval_secs: default_settings_check_interval_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct StatsHistory {
+    #[serde(default = "default_stats_history_path")]
+    pub path: PathBuf,
+    #[serde(default = "default_stats_history_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_stats_history_raw_retention_secs")]
+    pub raw_retention_secs: u64,
+    #[serde(default = "default_stats_history_downsample_interval_secs")]
+    pub downsample_interval_secs: u64,
+    #[serde(default = "default_stats_history_retention_secs")]
+    pub retention_secs: u64,
+}
+
+impl Default for StatsHistory {
+    fn default() -> Self {
+        Self {
+            path: default_stats_history_path(),
+            interval_ms: default_stats_history_interval_ms(),
+            raw_retention_secs: default_stats_history_raw_retention_secs(),
+            downsample_interval_secs: default_stats_history_downsample_interval_secs(),
+            retention_secs: default_stats_history_retention_secs(),
         }
     }
 }
@@ -85,7 +143,119 @@ impl Default for Daemon {
 pub struct Profile {
     #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
     pub gpus: IndexMap<String, Gpu>,
+    /// Profile to inherit the GPU settings from. When set, `overrides` are used instead of `gpus`
+    pub parent: Option<Rc<str>>,
+    /// Settings that differ from the parent profile, per GPU
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub overrides: IndexMap<String, Mapping>,
     pub rule: Option<ProfileRule>,
+    /// GPUs that the profile is activated on when switching automatically. Empty means all GPUs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gpu_ids: Vec<String>,
+    /// How long the rule needs to keep matching before the profile is activated
+    pub activation_delay_secs: Option<u64>,
+    /// How long the profile stays active before automatically switching to another one
+    pub min_active_secs: Option<u64>,
+    /// Shell command to run after the profile is activated
+    pub on_activate: Option<String>,
+    /// Shell command to run after the profile is deactivated
+    pub on_deactivate: Option<String>,
+}
+
+impl Profile {
+    /// Checks if the profile is used on the given GPU when switching automatically
+    pub fn applies_to(&self, gpu_id: &str) -> bool {
+        self.gpu_ids.is_empty() || self.gpu_ids.iter().any(|id| id == gpu_id)
+    }
+
+    /// Names of the settings overridden from the parent profile, per GPU
+    pub fn overridden_settings(&self) -> IndexMap<String, Vec<String>> {
+        self.overrides
+            .iter()
+            .map(|(id, overrides)| {
+                let mut names = Vec::new();
+                for (key, value) in overrides {
+                    let Some(name) = key.as_str() else {
+                        continue;
+                    };
+                    match value {
+                        Value::Mapping(nested) if is_merged_setting(key) => {
+                            names.extend(
+                                nested
+                                    .keys()
+                                    .filter_map(Value::as_str)
+                                    .map(|nested_name| format!("{name}.{nested_name}")),
+                            );
+                        }
+                        _ => names.push(name.to_owned()),
+                    }
+                }
+                (id.clone(), names)
+            })
+            .collect()
+    }
+}
+
+/// The active profile of every GPU
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProfileAssignment {
+    /// Profile of the GPUs that are not in `gpus`
+    pub default: Option<Rc<str>>,
+    pub gpus: IndexMap<String, Option<Rc<str>>>,
+}
+
+impl ProfileAssignment {
+    pub fn profile_for(&self, gpu_id: &str) -> Option<&Rc<str>> {
+        match self.gpus.get(gpu_id) {
+            Some(profile) => profile.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+
+    /// Checks if the profile is active on any GPU
+    pub fn contains(&self, name: &str) -> bool {
+        iter::once(&self.default)
+            .chain(self.gpus.values())
+            .any(|profile| profile.as_deref() == Some(name))
+    }
+
+    /// Lists the profiles that are replaced when switching to the `new` assignment, as `(old, new)` pairs
+    pub fn changes<'a>(
+        &'a self,
+        new: &'a ProfileAssignment,
+    ) -> Vec<(Option<&'a Rc<str>>, Option<&'a Rc<str>>)> {
+        let mut changes = Vec::new();
+        if self.default != new.default {
+            changes.push((self.default.as_ref(), new.default.as_ref()));
+        }
+        for gpu_id in self.gpus.keys().chain(new.gpus.keys()) {
+            let change = (self.profile_for(gpu_id), new.profile_for(gpu_id));
+            if change.0 != change.1 && !changes.contains(&change) {
+                changes.push(change);
+            }
+        }
+        changes
+    }
+
+    /// Removes the GPU profiles that are the same as the default one
+    fn normalize(&mut self) {
+        let default = &self.default;
+        self.gpus.retain(|_, profile| profile != default);
+    }
+}
+
+impl fmt::Display for ProfileAssignment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |profile: &Option<Rc<str>>| match profile {
+            Some(name) => format!("'{name}'"),
+            None => "default".to_owned(),
+        };
+        write!(f, "{}", name(&self.default))?;
+        for (gpu_id, profile) in &self.gpus {
+            write!(f, ", {} on GPU {gpu_id}", name(profile))?;
+        }
+        Ok(())
+    }
 }
 
 #[skip_serializing_none]
@@ -128,6 +298,69 @@ impl Gpu {
         self.clocks_configuration != ClocksConfiguration::default()
     }
 
+    /// Checks if any setting from this config is no longer set in `new`.
+    /// Removed settings are not reverted by applying the new config on top of the old one,
+    /// so the GPU needs to be reset to defaults first.
+    pub fn has_removed_settings(&self, new: &Gpu) -> bool {
+        let (old_pmfw, new_pmfw) = (&self.pmfw_options, &new.pmfw_options);
+        let (old_clocks, new_clocks) = (&self.clocks_configuration, &new.clocks_configuration);
+
+        (self.fan_control_enabled && !new.fan_control_enabled)
+            || is_removed(&old_pmfw.acoustic_limit, &new_pmfw.acoustic_limit)
+            || is_removed(&old_pmfw.acoustic_target, &new_pmfw.acoustic_target)
+            || is_removed(&old_pmfw.minimum_pwm, &new_pmfw.minimum_pwm)
+            || is_removed(&old_pmfw.target_temperature, &new_pmfw.target_temperature)
+            || is_removed(&old_pmfw.zero_rpm, &new_pmfw.zero_rpm)
+            || is_removed(&old_pmfw.zero_rpm_threshold, &new_pmfw.zero_rpm_threshold)
+            || is_removed(&self.power_cap, &new.power_cap)
+            || is_removed(&self.performance_level, &new.performance_level)
+            || is_removed(
+                &self.power_profile_mode_index,
+                &new.power_profile_mode_index,
+            )
+            || (!self.custom_power_profile_mode_hueristics.is_empty()
+                && new.custom_power_profile_mode_hueristics.is_empty())
+            || self
+                .power_states
+                .keys()
+                .any(|kind| !new.power_states.contains_key(kind))
+            || is_removed(&old_clocks.min_core_clock, &new_clocks.min_core_clock)
+            || is_removed(&old_clocks.min_memory_clock, &new_clocks.min_memory_clock)
+            || is_removed(&old_clocks.min_voltage, &new_clocks.min_voltage)
+            || is_removed(&old_clocks.max_core_clock, &new_clocks.max_core_clock)
+            || is_removed(&old_clocks.max_memory_clock, &new_clocks.max_memory_clock)
+            || is_removed(&old_clocks.max_voltage, &new_clocks.max_voltage)
+            || is_removed(&old_clocks.voltage_offset, &new_clocks.voltage_offset)
+            || old_clocks
+                .gpu_clock_offsets
+                .keys()
+                .any(|pstate| !new_clocks.gpu_clock_offsets.contains_key(pstate))
+            || old_clocks
+                .mem_clock_offsets
+                .keys()
+                .any(|pstate| !new_clocks.mem_clock_offsets.contains_key(pstate))
+    }
+
+    /// Creates a new config with the given overridden settings.
+    /// A `null` value removes the setting.
+    pub fn with_overrides(&self, overrides: &Mapping) -> anyhow::Result<Gpu> {
+        let mut settings = self.to_mapping()?;
+        merge_settings(&mut settings, overrides, true);
+        serde_yaml::from_value(Value::Mapping(settings)).context("Invalid overridden settings")
+    }
+
+    /// Gets the settings that need to be overridden to get from this config to `new`
+    pub fn overrides_to(&self, new: &Gpu) -> anyhow::Result<Mapping> {
+        Ok(diff_settings(&self.to_mapping()?, &new.to_mapping()?, true))
+    }
+
+    fn to_mapping(&self) -> anyhow::Result<Mapping> {
+        match serde_yaml::to_value(self)? {
+            Value::Mapping(mapping) => Ok(mapping),
+            _ => bail!("GPU config is not serialized as a map"),
+        }
+    }
+
     pub fn apply_clocks_command(&mut self, command: &SetClocksCommand) {
         let clocks = &mut self.clocks_configuration;
         let value = command.value;
@@ -175,6 +408,22 @@ pub struct FanControlSettings {
     pub curve: FanCurve,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub temperature_smoothing: Option<TemperatureSmoothing>,
+    /// Minimum temperature rise in degrees to speed up the fan. Defaults to `change_threshold`
+    pub hysteresis_up: Option<u64>,
+    /// Minimum temperature drop in degrees to slow down the fan. Defaults to `change_threshold`
+    pub hysteresis_down: Option<u64>,
+    /// Maximum PWM change (out of 255) per second
+    pub max_pwm_slew_rate: Option<u32>,
+    /// Sensor name to curve, used with the `multi_sensor` mode
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub sensor_curves: IndexMap<String, SensorCurve>,
+    pub sensor_combination: Option<SensorCombination>,
+    /// Fan index to settings that only apply to that fan
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub fan_overrides: IndexMap<u32, FanOverride>,
+    /// Stall and temperature runaway detection. Uses the default values when not specified
+    pub safety: Option<FanSafetySettings>,
 }
 
 impl Default for FanControlSettings {
@@ -187,6 +436,26 @@ impl Default for FanControlSettings {
             curve: FanCurve(default_fan_curve()),
             spindown_delay_ms: None,
             change_threshold: None,
+            temperature_smoothing: None,
+            hysteresis_up: None,
+            hysteresis_down: None,
+            max_pwm_slew_rate: None,
+            sensor_curves: IndexMap::new(),
+            sensor_combination: None,
+            fan_overrides: IndexMap::new(),
+            safety: None,
+        }
+    }
+}
+
+impl FanControlSettings {
+    pub fn clear_setting(&mut self, setting: FanSetting) {
+        match setting {
+            FanSetting::TemperatureSmoothing => self.temperature_smoothing = None,
+            FanSetting::HysteresisUp => self.hysteresis_up = None,
+            FanSetting::HysteresisDown => self.hysteresis_down = None,
+            FanSetting::MaxPwmSlewRate => self.max_pwm_slew_rate = None,
+            FanSetting::SensorCombination => self.sensor_combination = None,
         }
     }
 }
@@ -195,6 +464,41 @@ pub fn default_fan_static_speed() -> f64 {
     0.5
 }
 
+/// Checked by the fan control loop. Fan curves that are handled by the AMD PMFW are not monitored,
+/// as the firmware controls the fan by itself.
+#[skip_serializing_none]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FanSafetySettings {
+    /// Seconds a fan can stay at 0 RPM while being driven before it's considered stalled.
+    /// `0` disables stall detection
+    #[serde(default = "default_fan_stall_timeout_secs")]
+    pub stall_timeout_secs: u64,
+    /// Stalls are only detected when the controlled temperature is at or above this value
+    #[serde(default = "default_fan_stall_temperature")]
+    pub stall_temperature: f32,
+    /// Stalls are only detected when the fan is set to at least this speed (from 0 to 1),
+    /// as fans may not spin up below it
+    #[serde(default = "default_fan_stall_min_speed")]
+    pub stall_min_speed: f64,
+    /// Defaults to 5 degrees below the critical temperature of each sensor
+    pub runaway_temperature: Option<f32>,
+    /// Seconds the temperature can stay above `runaway_temperature`. `0` disables runaway detection
+    #[serde(default = "default_fan_runaway_timeout_secs")]
+    pub runaway_timeout_secs: u64,
+}
+
+impl Default for FanSafetySettings {
+    fn default() -> Self {
+        Self {
+            stall_timeout_secs: default_fan_stall_timeout_secs(),
+            stall_temperature: default_fan_stall_temperature(),
+            stall_min_speed: default_fan_stall_min_speed(),
+            runaway_temperature: None,
+            runaway_timeout_secs: default_fan_runaway_timeout_secs(),
+        }
+    }
+}
+
 impl Config {
     pub fn load() -> anyhow::Result<Option<Self>> {
         let path = get_path();
@@ -232,6 +536,7 @@ impl Config {
     }
 
     pub fn migrate_versions(&mut self) {
+        self.resolved_gpus = ResolvedGpus::default();
         loop {
             let next_version = self.version + 1;
             match next_version {
@@ -278,32 +583,154 @@ impl Config {
         }
     }
 
-    /// Gets the GPU configs according to the current profile. Returns an error if the current profile could not be found.
-    pub fn gpus(&self) -> anyhow::Result<&IndexMap<String, Gpu>> {
-        match &self.current_profile {
-            Some(profile) => {
-                let profile = self
-                    .profiles
-                    .get(profile)
-                    .with_context(|| format!("Could not find profile '{profile}'"))?;
-                Ok(&profile.gpus)
+    /// Gets the GPU configs according to the active profile of each GPU, with the settings inherited from parent profiles.
+    /// Returns an error if an active profile could not be found.
+    ///
+    /// The resolved configs are cached until the GPU settings, the profiles or the active profiles are changed.
+    pub fn gpus(&self) -> anyhow::Result<Cow<'_, IndexMap<String, Gpu>>> {
+        if let Some(gpus) = self.resolved_gpus.0.get() {
+            return Ok(Cow::Borrowed(gpus));
+        }
+
+        match self.resolve_gpus()? {
+            Cow::Borrowed(gpus) => Ok(Cow::Borrowed(gpus)),
+            Cow::Owned(gpus) => Ok(Cow::Borrowed(self.resolved_gpus.0.get_or_init(|| gpus))),
+        }
+    }
+
+    fn resolve_gpus(&self) -> anyhow::Result<Cow<'_, IndexMap<String, Gpu>>> {
+        let mut gpus = match &self.current_profile {
+            Some(profile) => self.profile_gpus(profile)?,
+            None => Cow::Borrowed(&self.gpus),
+        };
+
+        for (id, profile) in &self.gpu_profiles {
+            let gpu = match profile {
+                Some(profile) => self.profile_gpus(profile)?.get(id).cloned(),
+                None => self.gpus.get(id).cloned(),
+            };
+            match gpu {
+                Some(gpu) => {
+                    gpus.to_mut().insert(id.clone(), gpu);
+                }
+                None => {
+                    if gpus.contains_key(id) {
+                        gpus.to_mut().shift_remove(id);
+                    }
+                }
             }
-            None => Ok(&self.gpus),
         }
+
+        Ok(gpus)
     }
 
-    /// Same as [`gpus`], but with a mutable reference
-    pub fn gpus_mut(&mut self) -> anyhow::Result<&mut IndexMap<String, Gpu>> {
-        match &self.current_profile {
-            Some(profile) => {
-                let profile = self
-                    .profiles
-                    .get_mut(profile)
-                    .with_context(|| format!("Could not find profile '{profile}'"))?;
-                Ok(&mut profile.gpus)
+    /// Gets the name of the active profile of a GPU
+    pub fn active_profile(&self, gpu_id: &str) -> Option<&Rc<str>> {
+        match self.gpu_profiles.get(gpu_id) {
+            Some(profile) => profile.as_ref(),
+            None => self.current_profile.as_ref(),
+        }
+    }
+
+    pub fn profile_assignment(&self) -> ProfileAssignment {
+        ProfileAssignment {
+            default: self.current_profile.clone(),
+            gpus: self.gpu_profiles.clone(),
+        }
+    }
+
+    /// Gets the name of the profile used by GPUs without a GPU-specific profile
+    pub fn current_profile(&self) -> Option<&Rc<str>> {
+        self.current_profile.as_ref()
+    }
+
+    pub fn profiles(&self) -> &IndexMap<Rc<str>, Profile> {
+        &self.profiles
+    }
+
+    /// Gets the profiles for editing, which clears the cached GPU configs
+    pub fn profiles_mut(&mut self) -> &mut IndexMap<Rc<str>, Profile> {
+        self.resolved_gpus = ResolvedGpus::default();
+        &mut self.profiles
+    }
+
+    pub fn set_profile_assignment(&mut self, mut assignment: ProfileAssignment) {
+        self.resolved_gpus = ResolvedGpus::default();
+        assignment.normalize();
+        self.current_profile = assignment.default;
+        self.gpu_profiles = assignment.gpus;
+    }
+
+    /// Gets the GPU configs of a profile, with the settings inherited from its parent profiles
+    pub fn profile_gpus(&self, profile: &str) -> anyhow::Result<Cow<'_, IndexMap<String, Gpu>>> {
+        self.resolve_profile_gpus(profile, &mut Vec::new())
+    }
+
+    fn resolve_profile_gpus<'a>(
+        &'a self,
+        name: &'a str,
+        visited: &mut Vec<&'a str>,
+    ) -> anyhow::Result<Cow<'a, IndexMap<String, Gpu>>> {
+        if visited.contains(&name) {
+            bail!("Profile '{name}' inherits from itself");
+        }
+        visited.push(name);
+
+        let profile = self
+            .profiles
+            .get(name)
+            .with_context(|| format!("Could not find profile '{name}'"))?;
+        let Some(parent) = &profile.parent else {
+            return Ok(Cow::Borrowed(&profile.gpus));
+        };
+
+        let mut gpus = self.resolve_profile_gpus(parent, visited)?.into_owned();
+        for (id, overrides) in &profile.overrides {
+            let gpu = gpus
+                .get(id)
+                .cloned()
+                .unwrap_or_default()
+                .with_overrides(overrides)
+                .with_context(|| format!("Invalid settings for GPU {id} in profile '{name}'"))?;
+            gpus.insert(id.clone(), gpu);
+        }
+        Ok(Cow::Owned(gpus))
+    }
+
+    /// Sets the config of a GPU in its active profile.
+    /// Profiles with a parent only store the settings that differ from the parent.
+    pub fn set_gpu_config(&mut self, id: String, gpu: Gpu) -> anyhow::Result<()> {
+        self.resolved_gpus = ResolvedGpus::default();
+        let Some(name) = self.active_profile(&id).cloned() else {
+            self.gpus.insert(id, gpu);
+            return Ok(());
+        };
+
+        let overrides = match &self.profile(&name)?.parent {
+            Some(parent) => {
+                let parent_gpus = self.profile_gpus(parent)?;
+                let parent_gpu = parent_gpus.get(&id).cloned().unwrap_or_default();
+                Some(parent_gpu.overrides_to(&gpu)?)
+            }
+            None => None,
+        };
+
+        let profile = self
+            .profiles
+            .get_mut(&name)
+            .with_context(|| format!("Could not find profile '{name}'"))?;
+        match overrides {
+            Some(overrides) if overrides.is_empty() => {
+                profile.overrides.shift_remove(&id);
+            }
+            Some(overrides) => {
+                profile.overrides.insert(id, overrides);
+            }
+            None => {
+                profile.gpus.insert(id, gpu);
             }
-            None => Ok(&mut self.gpus),
         }
+        Ok(())
     }
 
     /// Get a specific profile
@@ -317,14 +744,16 @@ impl Config {
     pub fn default_profile(&self) -> Profile {
         Profile {
             gpus: self.gpus.clone(),
-            rule: None,
+            ..Default::default()
         }
     }
 
     pub fn clear(&mut self) {
+        self.resolved_gpus = ResolvedGpus::default();
         self.gpus.clear();
         self.profiles.clear();
         self.current_profile = None;
+        self.gpu_profiles.clear();
     }
 }
 
@@ -415,6 +844,57 @@ impl notify::EventHandler for SenderEventHandler {
     }
 }
 
+fn is_merged_setting(key: &Value) -> bool {
+    key.as_str()
+        .is_some_and(|key| MERGED_GPU_SETTINGS.contains(&key))
+}
+
+fn merge_settings(settings: &mut Mapping, overrides: &Mapping, top_level: bool) {
+    for (key, value) in overrides {
+        match (settings.get_mut(key), value) {
+            (_, Value::Null) => {
+                settings.remove(key);
+            }
+            (Some(Value::Mapping(nested_settings)), Value::Mapping(nested_overrides))
+                if top_level && is_merged_setting(key) =>
+            {
+                merge_settings(nested_settings, nested_overrides, false);
+            }
+            _ => {
+                settings.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn diff_settings(base: &Mapping, new: &Mapping, top_level: bool) -> Mapping {
+    let mut diff = Mapping::new();
+    for (key, new_value) in new {
+        match (base.get(key), new_value) {
+            (Some(base_value), _) if base_value == new_value => (),
+            (Some(Value::Mapping(base_nested)), Value::Mapping(new_nested))
+                if top_level && is_merged_setting(key) =>
+            {
+                let nested_diff = diff_settings(base_nested, new_nested, false);
+                diff.insert(key.clone(), Value::Mapping(nested_diff));
+            }
+            _ => {
+                diff.insert(key.clone(), new_value.clone());
+            }
+        }
+    }
+    for key in base.keys() {
+        if !new.contains_key(key) {
+            diff.insert(key.clone(), Value::Null);
+        }
+    }
+    diff
+}
+
+fn is_removed<T>(old: &Option<T>, new: &Option<T>) -> bool {
+    old.is_some() && new.is_none()
+}
+
 fn get_path() -> PathBuf {
     let uid = getuid();
     if uid.is_root() {
@@ -432,13 +912,59 @@ fn default_apply_settings_timer() -> u64 {
     5
 }
 
+fn default_fan_stall_timeout_secs() -> u64 {
+    15
+}
+
+fn default_fan_stall_temperature() -> f32 {
+    50.0
+}
+
+fn default_fan_stall_min_speed() -> f64 {
+    0.3
+}
+
+fn default_fan_runaway_timeout_secs() -> u64 {
+    10
+}
+
+fn default_settings_check_interval_secs() -> u64 {
+    30
+}
+
+fn default_stats_history_path() -> PathBuf {
+    PathBuf::from("/var/lib/lact/history")
+}
+
+fn default_stats_history_interval_ms() -> u64 {
+    1000
+}
+
+fn default_stats_history_raw_retention_secs() -> u64 {
+    60 * 60
+}
+
+fn default_stats_history_downsample_interval_secs() -> u64 {
+    60
+}
+
+fn default_stats_history_retention_secs() -> u64 {
+    7 * 24 * 60 * 60
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{ClocksConfiguration, Config, Daemon, FanControlSettings, Gpu};
+    use super::{
+        ClocksConfiguration, Config, Daemon, FanControlSettings, FanSafetySettings, Gpu, Profile,
+        ProfileAssignment, ResolvedGpus,
+    };
     use crate::server::gpu_controller::fan_control::FanCurve;
     use indexmap::IndexMap;
     use insta::assert_yaml_snapshot;
-    use lact_schema::{FanControlMode, PmfwOptions};
+    use lact_schema::{
+        FanControlMode, FanOverride, FanSetting, PmfwOptions, SensorCombination, SensorCurve,
+        TemperatureSmoothing,
+    };
 
     #[test]
     fn serde_de_full() {
@@ -456,6 +982,32 @@ mod tests {
                         static_speed: 0.5,
                         spindown_delay_ms: Some(5000),
                         change_threshold: Some(3),
+                        temperature_smoothing: Some(TemperatureSmoothing::Ema { alpha: 0.3 }),
+                        hysteresis_up: Some(2),
+                        hysteresis_down: Some(4),
+                        max_pwm_slew_rate: Some(25),
+                        sensor_curves: IndexMap::from([(
+                            "junction".to_owned(),
+                            SensorCurve {
+                                curve: [(60, 0.3), (90, 1.0)].into(),
+                                weight: 2.0,
+                            },
+                        )]),
+                        sensor_combination: Some(SensorCombination::Weighted),
+                        fan_overrides: IndexMap::from([(
+                            2,
+                            FanOverride {
+                                curve: None,
+                                speed_offset: Some(-0.1),
+                            },
+                        )]),
+                        safety: Some(FanSafetySettings {
+                            stall_timeout_secs: 15,
+                            stall_temperature: 60.0,
+                            stall_min_speed: 0.5,
+                            runaway_temperature: Some(100.0),
+                            runaway_timeout_secs: 20,
+                        }),
                     }),
                     ..Default::default()
                 },
@@ -504,6 +1056,196 @@ mod tests {
         assert!(gpu.is_core_clocks_used());
     }
 
+    #[test]
+    fn fan_settings_cleared() {
+        let mut settings = FanControlSettings {
+            change_threshold: Some(2),
+            temperature_smoothing: Some(TemperatureSmoothing::Ema { alpha: 0.3 }),
+            hysteresis_up: Some(2),
+            hysteresis_down: Some(4),
+            max_pwm_slew_rate: Some(25),
+            sensor_combination: Some(SensorCombination::Weighted),
+            ..Default::default()
+        };
+
+        settings.clear_setting(FanSetting::HysteresisUp);
+        settings.clear_setting(FanSetting::MaxPwmSlewRate);
+        assert_eq!(None, settings.hysteresis_up);
+        assert_eq!(None, settings.max_pwm_slew_rate);
+        assert_eq!(Some(4), settings.hysteresis_down);
+
+        settings.clear_setting(FanSetting::TemperatureSmoothing);
+        settings.clear_setting(FanSetting::HysteresisDown);
+        settings.clear_setting(FanSetting::SensorCombination);
+        assert_eq!(
+            FanControlSettings {
+                change_threshold: Some(2),
+                ..Default::default()
+            },
+            settings
+        );
+    }
+
+    #[test]
+    fn removed_settings() {
+        let old = Gpu {
+            power_cap: Some(200.0),
+            clocks_configuration: ClocksConfiguration {
+                max_core_clock: Some(2000),
+                gpu_clock_offsets: IndexMap::from([(0, 100)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let mut new = old.clone();
+        new.power_cap = Some(250.0);
+        new.clocks_configuration.max_core_clock = Some(2100);
+        new.pmfw_options.zero_rpm = Some(true);
+        assert!(!old.has_removed_settings(&new));
+
+        new.clocks_configuration.gpu_clock_offsets.clear();
+        assert!(old.has_removed_settings(&new));
+        assert!(old.has_removed_settings(&Gpu::default()));
+        assert!(!Gpu::default().has_removed_settings(&old));
+    }
+
+    #[test]
+    fn profile_inheritance() {
+        let base_gpu = Gpu {
+            fan_control_enabled: true,
+            fan_control_settings: Some(FanControlSettings {
+                change_threshold: Some(2),
+                ..Default::default()
+            }),
+            power_cap: Some(200.0),
+            ..Default::default()
+        };
+        let mut config = Config {
+            profiles: IndexMap::from([
+                (
+                    "base".into(),
+                    Profile {
+                        gpus: IndexMap::from([("gpu".to_owned(), base_gpu.clone())]),
+                        ..Default::default()
+                    },
+                ),
+                (
+                    "quiet".into(),
+                    Profile {
+                        parent: Some("base".into()),
+                        ..Default::default()
+                    },
+                ),
+            ]),
+            current_profile: Some("quiet".into()),
+            ..Default::default()
+        };
+        assert_eq!(base_gpu, config.gpus().unwrap()["gpu"]);
+
+        let mut quiet_gpu = base_gpu.clone();
+        quiet_gpu.power_cap = None;
+        quiet_gpu
+            .fan_control_settings
+            .as_mut()
+            .unwrap()
+            .spindown_delay_ms = Some(5000);
+        config
+            .set_gpu_config("gpu".to_owned(), quiet_gpu.clone())
+            .unwrap();
+
+        let overrides = serde_yaml::to_string(&config.profiles["quiet"].overrides).unwrap();
+        assert_eq!(
+            "gpu:\n  fan_control_settings:\n    spindown_delay_ms: 5000\n  power_cap: null\n",
+            overrides
+        );
+        assert_eq!(quiet_gpu, config.gpus().unwrap()["gpu"]);
+        assert_eq!(
+            vec![
+                "fan_control_settings.spindown_delay_ms".to_owned(),
+                "power_cap".to_owned()
+            ],
+            config.profiles["quiet"].overridden_settings()["gpu"]
+        );
+
+        // Changes in the parent are inherited
+        config.profiles_mut()["base"]
+            .gpus
+            .get_mut("gpu")
+            .unwrap()
+            .fan_control_settings
+            .as_mut()
+            .unwrap()
+            .change_threshold = Some(4);
+        let gpus = config.gpus().unwrap();
+        let settings = gpus["gpu"].fan_control_settings.as_ref().unwrap();
+        assert_eq!(Some(4), settings.change_threshold);
+        assert_eq!(Some(5000), settings.spindown_delay_ms);
+        assert_eq!(None, gpus["gpu"].power_cap);
+    }
+
+    #[test]
+    fn profile_inheritance_cycle() {
+        let profile = |parent: &str| Profile {
+            parent: Some(parent.into()),
+            ..Default::default()
+        };
+        let config = Config {
+            profiles: IndexMap::from([("a".into(), profile("b")), ("b".into(), profile("a"))]),
+            current_profile: Some("a".into()),
+            ..Default::default()
+        };
+        assert!(config.gpus().is_err());
+    }
+
+    #[test]
+    fn gpu_profiles() {
+        let gpu = |power_cap| Gpu {
+            power_cap: Some(power_cap),
+            ..Default::default()
+        };
+        let profile = |power_cap| Profile {
+            gpus: IndexMap::from([
+                ("display".to_owned(), gpu(power_cap)),
+                ("compute".to_owned(), gpu(power_cap)),
+            ]),
+            ..Default::default()
+        };
+        let mut config = Config {
+            gpus: IndexMap::from([("display".to_owned(), gpu(100.0))]),
+            profiles: IndexMap::from([
+                ("game".into(), profile(200.0)),
+                ("render".into(), profile(300.0)),
+            ]),
+            ..Default::default()
+        };
+
+        config.set_profile_assignment(ProfileAssignment {
+            default: Some("render".into()),
+            gpus: IndexMap::from([
+                ("display".to_owned(), Some("game".into())),
+                ("compute".to_owned(), Some("render".into())),
+            ]),
+        });
+        assert_eq!(1, config.gpu_profiles.len());
+        let gpus = config.gpus().unwrap();
+        assert_eq!(Some(200.0), gpus["display"].power_cap);
+        assert_eq!(Some(300.0), gpus["compute"].power_cap);
+
+        config.set_profile_assignment(ProfileAssignment {
+            default: Some("render".into()),
+            gpus: IndexMap::from([("display".to_owned(), None)]),
+        });
+        let gpus = config.gpus().unwrap();
+        assert_eq!(Some(100.0), gpus["display"].power_cap);
+        assert_eq!(Some(300.0), gpus["compute"].power_cap);
+
+        config
+            .set_gpu_config("display".to_owned(), gpu(150.0))
+            .unwrap();
+        assert_eq!(Some(150.0), config.gpus["display"].power_cap);
+    }
+
     #[test]
     fn migrate_versions() {
         let mut config = Config {
@@ -536,7 +1278,9 @@ mod tests {
             ]),
             profiles: IndexMap::new(),
             current_profile: None,
+            gpu_profiles: IndexMap::new(),
             auto_switch_profiles: false,
+            resolved_gpus: ResolvedGpus::default(),
         };
 
         config.migrate_versions();
@@ -19,7 +19,7 @@ use std::sync::Arc;
 use std::time::Instant;
 use std::{os::unix::net::UnixStream as StdUnixStream, time::Duration};
 use tokio::net::UnixStream;
-use tokio::sync::Notify;
+use tokio::sync::{mpsc, Notify};
 use tokio::time::timeout;
 use tokio::{
     runtime,
@@ -37,6 +37,8 @@ pub use server::system::MODULE_CONF_PATH;
 
 const MIN_SYSTEM_UPTIME_SECS: f32 = 15.0;
 const DRM_EVENT_TIMEOUT_PERIOD_MS: u64 = 100;
+/// How often to check whether the settings drift check was enabled by a config reload
+const DISABLED_SETTINGS_CHECK_POLL_SECS: u64 = 5;
 const SHUTDOWN_SIGNALS: [SignalKind; 4] = [
     SignalKind::terminate(),
     SignalKind::interrupt(),
@@ -78,6 +80,7 @@ pub fn run() -> anyhow::Result<()> {
                 tokio::task::spawn_local(listen_config_changes(handler.clone()));
                 tokio::task::spawn_local(listen_exit_signals(handler.clone()));
                 tokio::task::spawn_local(listen_device_events(handler.clone()));
+                tokio::task::spawn_local(verify_settings_periodically(handler.clone()));
                 tokio::task::spawn_local(suspend::listen_events(handler));
 
                 server.run().await;
@@ -117,6 +120,8 @@ async fn listen_exit_signals(handler: Handler) {
     select_all(signal_futures).await;
 
     info!("cleaning up and shutting down...");
+    // Disarmed before the cleanup, so that a reboot during a stuck cleanup is not treated as a crash
+    handler.disarm_crash_guard();
     async {
         handler.cleanup().await;
         socket::cleanup();
@@ -139,18 +144,33 @@ async fn listen_config_changes(handler: Handler) {
                 error!("could not apply new config: {err:#}");
             }
         }
+        handler.update_profile_watcher().await;
     }
 }
 
 async fn listen_device_events(handler: Handler) {
     let notify = Arc::new(Notify::new());
     let task_notify = notify.clone();
+    let power_supply_notify = handler.power_supply_notify.clone();
+    let (reset_tx, mut reset_rx) = mpsc::unbounded_channel();
     tokio::task::spawn_blocking(move || {
-        if let Err(err) = system::listen_netlink_kernel_event(&task_notify) {
+        let listeners = [
+            ("drm", &*task_notify),
+            ("power_supply", &*power_supply_notify),
+        ];
+        if let Err(err) = system::listen_netlink_kernel_event(&listeners, &reset_tx) {
             error!("kernel event listener error: {err:#}");
         }
     });
 
+    // Resets are handled right away, so that the settings are reverted before the GPUs are reloaded
+    let reset_handler = handler.clone();
+    tokio::task::spawn_local(async move {
+        while let Some(event) = reset_rx.recv().await {
+            reset_handler.handle_gpu_reset(event).await;
+        }
+    });
+
     loop {
         notify.notified().await;
 
@@ -168,6 +188,26 @@ async fn listen_device_events(handler: Handler) {
     }
 }
 
+/// Reapplies the settings that were changed outside of the daemon, such as by the driver.
+/// The interval is re-read every iteration, so that the check can be enabled with a config reload.
+async fn verify_settings_periodically(handler: Handler) {
+    loop {
+        let interval = handler
+            .config
+            .read()
+            .await
+            .daemon
+            .settings_check_interval_secs;
+        if interval == 0 {
+            tokio::time::sleep(Duration::from_secs(DISABLED_SETTINGS_CHECK_POLL_SECS)).await;
+            continue;
+        }
+
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+        handler.verify_applied_settings().await;
+    }
+}
+
 async fn ensure_sufficient_uptime() {
     match get_uptime() {
         Ok(current_uptime) => {
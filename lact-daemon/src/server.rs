@@ -1,18 +1,23 @@
+mod crash_guard;
 pub mod gpu_controller;
 pub mod handler;
+mod metrics;
 mod profiles;
+mod stats_history;
+mod stats_sampler;
 pub(crate) mod system;
+mod validation;
 mod vulkan;
 
-use self::handler::Handler;
+use self::{handler::Handler, stats_sampler::StatsSubscription};
 use crate::{config::Config, socket};
-use anyhow::Context;
+use anyhow::{anyhow, Context};
 use futures::future::join_all;
 use lact_schema::{Pong, Request, Response};
 use serde::Serialize;
 use std::fmt::Debug;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader, Lines},
     net::{TcpListener, UnixListener},
 };
 use tracing::{error, info, instrument, trace};
@@ -21,6 +26,7 @@ pub struct Server {
     pub handler: Handler,
     unix_listener: UnixListener,
     tcp_listener: Option<TcpListener>,
+    metrics_listener: Option<TcpListener>,
 }
 
 impl Server {
@@ -38,12 +44,23 @@ impl Server {
             None
         };
 
+        let metrics_listener = if let Some(address) = &config.daemon.metrics_listen_address {
+            let listener = TcpListener::bind(address)
+                .await
+                .with_context(|| format!("Could not bind metrics listener to {address}"))?;
+            info!("serving metrics on {}", listener.local_addr()?);
+            Some(listener)
+        } else {
+            None
+        };
+
         let handler = Handler::new(config).await?;
 
         Ok(Self {
             handler,
             unix_listener,
             tcp_listener,
+            metrics_listener,
         })
     }
 
@@ -70,6 +87,12 @@ impl Server {
         });
         tasks.push(unix_task);
 
+        if let Some(metrics_listener) = self.metrics_listener {
+            let metrics_task =
+                tokio::task::spawn_local(metrics::listen(metrics_listener, self.handler.clone()));
+            tasks.push(metrics_task);
+        }
+
         if let Some(tcp_listener) = self.tcp_listener {
             let tcp_task = tokio::task::spawn_local(async move {
                 loop {
@@ -100,14 +123,29 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
     stream: T,
     handler: Handler,
 ) -> anyhow::Result<()> {
-    let mut stream = BufReader::new(stream);
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut lines = BufReader::new(reader).lines();
 
-    let mut buf = String::new();
-    while stream.read_line(&mut buf).await? != 0 {
-        trace!("handling request: {}", buf.trim_end());
+    while let Some(line) = lines.next_line().await? {
+        trace!("handling request: {line}");
 
-        let maybe_request = serde_json::from_str(&buf);
+        let maybe_request = serde_json::from_str(&line);
         let response = match maybe_request {
+            Ok(Request::SubscribeStats { id, interval_ms }) => {
+                match handler.subscribe_stats(id, interval_ms).await {
+                    Ok(subscription) => {
+                        write_line(&mut writer, &serde_json::to_vec(&Response::Ok(()))?).await?;
+
+                        if stream_stats(&mut lines, &mut writer, subscription).await? {
+                            serde_json::to_vec(&Response::Ok(()))?
+                        } else {
+                            // Client disconnected while subscribed
+                            break;
+                        }
+                    }
+                    Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
+                }
+            }
             Ok(request) => match handle_request(request, &handler).await {
                 Ok(response) => response,
                 Err(error) => serde_json::to_vec(&Response::<()>::from(error))?,
@@ -117,12 +155,44 @@ pub async fn handle_stream<T: AsyncRead + AsyncWrite + Unpin>(
             ))?,
         };
 
-        stream.write_all(&response).await?;
-        stream.write_all(b"\n").await?;
+        write_line(&mut writer, &response).await?;
+    }
+
+    Ok(())
+}
 
-        buf.clear();
+/// Pushes stats frames to the client until it unsubscribes or disconnects.
+/// Returns `false` if the client disconnected.
+async fn stream_stats<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
+    lines: &mut Lines<BufReader<R>>,
+    writer: &mut W,
+    mut subscription: StatsSubscription,
+) -> anyhow::Result<bool> {
+    loop {
+        tokio::select! {
+            frame = subscription.recv() => match frame {
+                Some(frame) => write_line(writer, &frame).await?,
+                None => return Ok(true),
+            },
+            line = lines.next_line() => match line? {
+                Some(line) => match serde_json::from_str(&line) {
+                    Ok(Request::UnsubscribeStats) => return Ok(true),
+                    _ => {
+                        let response = Response::<()>::from(anyhow!(
+                            "Only `unsubscribe_stats` is allowed while subscribed to stats"
+                        ));
+                        write_line(writer, &serde_json::to_vec(&response)?).await?;
+                    }
+                },
+                None => return Ok(false),
+            },
+        }
     }
+}
 
+async fn write_line<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8]) -> anyhow::Result<()> {
+    writer.write_all(data).await?;
+    writer.write_all(b"\n").await?;
     Ok(())
 }
 
@@ -134,6 +204,20 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
         Request::ListDevices => ok_response(handler.list_devices().await),
         Request::DeviceInfo { id } => ok_response(handler.get_device_info(id).await?),
         Request::DeviceStats { id } => ok_response(handler.get_gpu_stats(id).await?),
+        Request::SubscribeStats { .. } => {
+            Err(anyhow!("Stats subscriptions are handled by the stream"))
+        }
+        Request::UnsubscribeStats => Err(anyhow!("Not subscribed to stats")),
+        Request::GetStatsHistory {
+            id,
+            since,
+            until,
+            resolution,
+        } => ok_response(
+            handler
+                .get_stats_history(id, since, until, resolution)
+                .await?,
+        ),
         Request::DeviceClocksInfo { id } => ok_response(handler.get_clocks_info(id).await?),
         Request::DevicePowerProfileModes { id } => {
             ok_response(handler.get_power_profile_modes(id).await?)
@@ -165,12 +249,22 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
             ok_response(handler.set_enabled_power_states(id, kind, states).await?)
         }
         Request::VbiosDump { id } => ok_response(handler.vbios_dump(id).await?),
+        Request::ValidateGpuConfig { id, config } => {
+            ok_response(handler.validate_gpu_config(id, config).await?)
+        }
+        Request::ValidateConfig { config, gpu_id } => {
+            ok_response(handler.validate_config(config, gpu_id.as_deref()).await?)
+        }
         Request::ListProfiles { include_state } => {
             ok_response(handler.list_profiles(include_state).await)
         }
-        Request::SetProfile { name, auto_switch } => ok_response(
+        Request::SetProfile {
+            name,
+            auto_switch,
+            gpu_id,
+        } => ok_response(
             handler
-                .set_profile(name.map(Into::into), auto_switch)
+                .set_profile(name.map(Into::into), auto_switch, gpu_id)
                 .await?,
         ),
         Request::CreateProfile { name, base } => {
@@ -187,8 +281,12 @@ async fn handle_request<'a>(request: Request<'a>, handler: &'a Handler) -> anyho
         Request::EnableOverdrive => ok_response(system::enable_overdrive().await?),
         Request::DisableOverdrive => ok_response(system::disable_overdrive().await?),
         Request::GenerateSnapshot => ok_response(handler.generate_snapshot().await?),
-        Request::ConfirmPendingConfig(command) => {
-            ok_response(handler.confirm_pending_config(command)?)
+        Request::ListPendingConfigs => ok_response(handler.list_pending_configs()?),
+        Request::CrashRecoveryInfo => ok_response(handler.crash_recovery_info()),
+        Request::ListGpuResets => ok_response(handler.list_gpu_resets()?),
+        Request::ListSettingsDrift => ok_response(handler.list_settings_drift()?),
+        Request::ConfirmPendingConfig { command, id } => {
+            ok_response(handler.confirm_pending_config(id.as_deref(), command)?)
         }
         Request::RestConfig => {
             handler.reset_config().await;
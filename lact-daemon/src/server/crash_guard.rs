@@ -0,0 +1,386 @@
+use super::handler::Handler;
+use crate::config::{ClocksConfiguration, Gpu};
+use anyhow::Context;
+use indexmap::IndexMap;
+use lact_schema::CrashRecoveryInfo;
+use nix::unistd::getuid;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    env,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+
+const MARKER_FILE_NAME: &str = "apply-marker.yaml";
+const LAST_KNOWN_GOOD_FILE_NAME: &str = "last-known-good.yaml";
+const RECOVERY_FILE_NAME: &str = "crash-recovery.yaml";
+const BOOT_ID_PATH: &str = "/proc/sys/kernel/random/boot_id";
+/// How long the system needs to stay up after applying clock and voltage settings for them to be considered stable
+const STABLE_UPTIME: Duration = Duration::from_secs(120);
+
+/// Written before applying clock and voltage settings, and removed once the system has stayed up for a while.
+/// Finding a marker from a previous boot means that the settings may have crashed the system.
+#[derive(Serialize, Deserialize, Debug)]
+struct ApplyMarker {
+    boot_id: Option<String>,
+    /// Unix timestamp in milliseconds
+    applied_at: i64,
+    gpu_ids: Vec<String>,
+}
+
+impl ApplyMarker {
+    fn is_from_other_boot(&self, boot_id: Option<&str>) -> bool {
+        match (self.boot_id.as_deref(), boot_id) {
+            (Some(marker_boot_id), Some(boot_id)) => marker_boot_id != boot_id,
+            _ => false,
+        }
+    }
+}
+
+/// Protects against applying settings that crash the system on every boot.
+///
+/// The state is stored outside of the config directory, so that writing it does not trigger a config reload.
+pub struct CrashGuard {
+    dir: PathBuf,
+    boot_id: Option<String>,
+    /// Increased every time the marker is written, so that only the latest stability timer removes it
+    generation: Cell<u64>,
+    /// Settings which have stayed stable, by GPU id
+    last_known_good: RefCell<IndexMap<String, Gpu>>,
+    recovery: RefCell<Option<CrashRecoveryInfo>>,
+}
+
+impl CrashGuard {
+    /// Loads the state from the given directory, checking for a marker left by a previous boot
+    pub fn load(dir: PathBuf) -> Self {
+        let guard = Self {
+            boot_id: fs::read_to_string(BOOT_ID_PATH)
+                .ok()
+                .map(|boot_id| boot_id.trim().to_owned()),
+            generation: Cell::new(0),
+            last_known_good: RefCell::new(
+                read_yaml(&dir.join(LAST_KNOWN_GOOD_FILE_NAME)).unwrap_or_default(),
+            ),
+            recovery: RefCell::new(read_yaml(&dir.join(RECOVERY_FILE_NAME))),
+            dir,
+        };
+
+        if let Some(marker) = read_yaml::<ApplyMarker>(&guard.dir.join(MARKER_FILE_NAME)) {
+            if marker.is_from_other_boot(guard.boot_id.as_deref()) {
+                warn!(
+                    "system did not stay up after applying settings on GPUs {:?}, skipping clocks and voltages",
+                    marker.gpu_ids
+                );
+                guard.add_recovery(marker.applied_at, marker.gpu_ids);
+            }
+            guard.remove_marker();
+        }
+
+        if let Some(recovery) = &*guard.recovery.borrow() {
+            info!(
+                "using last known good settings for GPUs {:?}",
+                recovery.gpu_ids
+            );
+        }
+
+        guard
+    }
+
+    pub fn recovery_info(&self) -> Option<CrashRecoveryInfo> {
+        self.recovery.borrow().clone()
+    }
+
+    /// Replaces the clocks and voltage settings of the GPUs that were skipped after a crash
+    /// with their last known good ones, or removes them. The other settings are kept as configured.
+    pub fn safe_gpus<'a>(
+        &self,
+        mut gpus: Cow<'a, IndexMap<String, Gpu>>,
+    ) -> Cow<'a, IndexMap<String, Gpu>> {
+        let recovery = self.recovery.borrow();
+        let Some(recovery) = recovery.as_ref() else {
+            return gpus;
+        };
+        let last_known_good = self.last_known_good.borrow();
+
+        for id in &recovery.gpu_ids {
+            let Some(gpu) = gpus.get(id) else {
+                continue;
+            };
+            let safe_clocks = last_known_good
+                .get(id)
+                .map(|gpu| gpu.clocks_configuration.clone())
+                .unwrap_or_default();
+            if gpu.clocks_configuration != safe_clocks {
+                let safe_gpu = Gpu {
+                    clocks_configuration: safe_clocks,
+                    ..gpu.clone()
+                };
+                gpus.to_mut().insert(id.clone(), safe_gpu);
+            }
+        }
+
+        gpus
+    }
+
+    /// Whether the given clocks and voltage settings of the GPU have already stayed stable
+    pub fn is_known_good(&self, id: &str, clocks: &ClocksConfiguration) -> bool {
+        self.last_known_good
+            .borrow()
+            .get(id)
+            .is_some_and(|gpu| gpu.clocks_configuration == *clocks)
+    }
+
+    /// Writes the marker for the given GPUs, keeping the GPUs from a marker that was not removed yet.
+    /// Returns the generation that needs to be passed to `mark_stable`.
+    pub fn write_marker(&self, gpu_ids: Vec<String>) -> anyhow::Result<u64> {
+        let marker_path = self.dir.join(MARKER_FILE_NAME);
+        let mut marker = ApplyMarker {
+            boot_id: self.boot_id.clone(),
+            applied_at: chrono::Utc::now().timestamp_millis(),
+            gpu_ids,
+        };
+        if let Some(old_marker) = read_yaml::<ApplyMarker>(&marker_path) {
+            for id in old_marker.gpu_ids {
+                if !marker.gpu_ids.contains(&id) {
+                    marker.gpu_ids.push(id);
+                }
+            }
+        }
+        debug!("writing apply marker for GPUs {:?}", marker.gpu_ids);
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Could not create directory {:?}", self.dir))?;
+        let mut file = File::create(&marker_path).context("Could not create marker file")?;
+        file.write_all(serde_yaml::to_string(&marker)?.as_bytes())
+            .context("Could not write marker file")?;
+        // The marker needs to reach the disk before the settings can lock up the system
+        file.sync_all().context("Could not sync marker file")?;
+
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        Ok(generation)
+    }
+
+    /// Removes the marker and stores the given settings as known good,
+    /// unless the marker has been written again since the given generation
+    pub fn mark_stable(&self, generation: u64, gpus: &IndexMap<String, Gpu>) {
+        if generation != self.generation.get() {
+            return;
+        }
+        self.remove_marker();
+
+        let recovery = self.recovery.borrow();
+        let is_skipped = |id: &str| {
+            recovery
+                .as_ref()
+                .is_some_and(|info| info.gpu_ids.iter().any(|skipped| skipped == id))
+        };
+
+        // The settings of the skipped GPUs were not applied, so their known good settings stay the same
+        let mut last_known_good = self.last_known_good.borrow_mut();
+        last_known_good.retain(|id, _| is_skipped(id));
+        for (id, gpu) in gpus {
+            if !is_skipped(id) {
+                last_known_good.insert(id.clone(), gpu.clone());
+            }
+        }
+        debug!("settings are stable, saving them as last known good");
+
+        let path = self.dir.join(LAST_KNOWN_GOOD_FILE_NAME);
+        if let Err(err) = write_yaml(&path, &*last_known_good) {
+            error!("could not save last known good settings: {err:#}");
+        }
+    }
+
+    /// Removes the marker without marking the settings as stable,
+    /// used when the settings are reset or the daemon is shut down cleanly
+    pub fn disarm(&self) {
+        self.generation.set(self.generation.get() + 1);
+        self.remove_marker();
+    }
+
+    /// Stops using the last known good settings for the given GPU, or for all GPUs
+    pub fn clear_recovery(&self, gpu_id: Option<&str>) {
+        let mut recovery = self.recovery.borrow_mut();
+        let Some(info) = recovery.as_mut() else {
+            return;
+        };
+
+        match gpu_id {
+            Some(gpu_id) => info.gpu_ids.retain(|id| id != gpu_id),
+            None => info.gpu_ids.clear(),
+        }
+
+        let path = self.dir.join(RECOVERY_FILE_NAME);
+        let result = if info.gpu_ids.is_empty() {
+            *recovery = None;
+            fs::remove_file(path).context("Could not remove file")
+        } else {
+            write_yaml(&path, info)
+        };
+        if let Err(err) = result {
+            error!("could not update crash recovery state: {err:#}");
+        }
+    }
+
+    /// Skips the clock and voltage settings of a GPU that was reset while using them
+    pub fn skip_gpu(&self, id: String) {
+        self.add_recovery(chrono::Utc::now().timestamp_millis(), vec![id]);
+    }
+
+    fn add_recovery(&self, applied_at: i64, gpu_ids: Vec<String>) {
+        let mut recovery = self.recovery.borrow_mut();
+        let info = recovery.get_or_insert_with(|| CrashRecoveryInfo {
+            applied_at,
+            gpu_ids: Vec::new(),
+        });
+        info.applied_at = applied_at;
+        for id in gpu_ids {
+            if !info.gpu_ids.contains(&id) {
+                info.gpu_ids.push(id);
+            }
+        }
+
+        if let Err(err) = write_yaml(&self.dir.join(RECOVERY_FILE_NAME), info) {
+            error!("could not save crash recovery state: {err:#}");
+        }
+    }
+
+    fn remove_marker(&self) {
+        let path = self.dir.join(MARKER_FILE_NAME);
+        if path.exists() {
+            if let Err(err) = fs::remove_file(&path) {
+                error!("could not remove apply marker {path:?}: {err}");
+            }
+        }
+    }
+}
+
+/// Marks the applied settings as stable once the system has stayed up for long enough
+pub async fn wait_until_stable(handler: Handler, guard: Rc<CrashGuard>, generation: u64) {
+    sleep(STABLE_UPTIME).await;
+
+    match handler.config.read().await.gpus() {
+        Ok(gpus) => guard.mark_stable(generation, &gpus),
+        Err(err) => error!("could not get GPU settings: {err:#}"),
+    }
+}
+
+/// Directory for the daemon state that is not a part of the config
+pub fn state_dir() -> PathBuf {
+    if getuid().is_root() {
+        PathBuf::from("/var/lib/lact")
+    } else {
+        let state_dir = PathBuf::from(env::var("XDG_STATE_HOME").unwrap_or_else(|_| {
+            let home = env::var("HOME").expect("$HOME variable is not set");
+            format!("{home}/.local/state")
+        }));
+        state_dir.join("lact")
+    }
+}
+
+fn read_yaml<T: DeserializeOwned>(path: &Path) -> Option<T> {
+    let raw = fs::read_to_string(path).ok()?;
+    serde_yaml::from_str(&raw)
+        .inspect_err(|err| error!("could not parse {path:?}: {err}"))
+        .ok()
+}
+
+fn write_yaml<T: Serialize + ?Sized>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_yaml::to_string(value)?)
+        .with_context(|| format!("Could not write {path:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ApplyMarker, CrashGuard};
+    use crate::config::{ClocksConfiguration, Gpu};
+    use indexmap::IndexMap;
+    use lact_schema::CrashRecoveryInfo;
+    use pretty_assertions::assert_eq;
+    use std::{
+        borrow::Cow,
+        cell::{Cell, RefCell},
+        path::PathBuf,
+    };
+
+    #[test]
+    fn marker_from_other_boot() {
+        let marker = ApplyMarker {
+            boot_id: Some("a".to_owned()),
+            applied_at: 0,
+            gpu_ids: vec![],
+        };
+        assert!(marker.is_from_other_boot(Some("b")));
+        assert!(!marker.is_from_other_boot(Some("a")));
+        assert!(!marker.is_from_other_boot(None));
+    }
+
+    #[test]
+    fn skip_clocks_after_crash() {
+        let overclocked = Gpu {
+            power_cap: Some(250.0),
+            clocks_configuration: ClocksConfiguration {
+                max_core_clock: Some(2500),
+                voltage_offset: Some(-50),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let known_good = Gpu {
+            power_cap: Some(200.0),
+            clocks_configuration: ClocksConfiguration {
+                max_core_clock: Some(2300),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let guard = CrashGuard {
+            dir: PathBuf::from("/nonexistent"),
+            boot_id: None,
+            generation: Cell::new(0),
+            last_known_good: RefCell::new(IndexMap::from([(
+                "gpu2".to_owned(),
+                known_good.clone(),
+            )])),
+            recovery: RefCell::new(Some(CrashRecoveryInfo {
+                applied_at: 0,
+                gpu_ids: vec!["gpu1".to_owned(), "gpu2".to_owned()],
+            })),
+        };
+
+        let gpus = IndexMap::from([
+            ("gpu1".to_owned(), overclocked.clone()),
+            ("gpu2".to_owned(), overclocked.clone()),
+            ("gpu3".to_owned(), overclocked.clone()),
+        ]);
+        let safe_gpus = guard.safe_gpus(Cow::Borrowed(&gpus));
+
+        let expected_gpu1 = Gpu {
+            power_cap: Some(250.0),
+            ..Default::default()
+        };
+        // Only the clocks are replaced, the other settings stay as configured
+        let expected_gpu2 = Gpu {
+            power_cap: Some(250.0),
+            clocks_configuration: known_good.clocks_configuration.clone(),
+            ..Default::default()
+        };
+        assert_eq!(expected_gpu1, safe_gpus["gpu1"]);
+        assert_eq!(expected_gpu2, safe_gpus["gpu2"]);
+        assert_eq!(overclocked, safe_gpus["gpu3"]);
+        assert!(guard.is_known_good("gpu2", &known_good.clocks_configuration));
+        assert!(!guard.is_known_good("gpu2", &overclocked.clocks_configuration));
+        assert!(!guard.is_known_good("gpu3", &overclocked.clocks_configuration));
+    }
+}
@@ -11,6 +11,9 @@ use nvidia::NvidiaGpuController;
 pub const VENDOR_AMD: &str = "1002";
 pub const VENDOR_NVIDIA: &str = "10DE";
 
+/// Drivers may round the power cap, so small differences are not treated as drift
+const POWER_CAP_DRIFT_TOLERANCE: f64 = 1.0;
+
 use crate::{
     bindings::intel::IntelDrm,
     config::{self},
@@ -18,15 +21,74 @@ use crate::{
 use amdgpu_sysfs::gpu_handle::power_profile_mode::PowerProfileModesTable;
 use anyhow::Context;
 use futures::future::LocalBoxFuture;
-use lact_schema::{ClocksInfo, DeviceInfo, DeviceStats, GpuPciInfo, PciInfo, PowerStates};
+use lact_schema::{
+    ClocksInfo, DeviceInfo, DeviceStats, FanAlert, GpuPciInfo, PciInfo, PowerStates,
+};
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use nvml_wrapper::Nvml;
-use std::{cell::LazyCell, collections::HashMap, fs, path::PathBuf, rc::Rc};
+use std::{
+    cell::{LazyCell, RefCell},
+    collections::HashMap,
+    fmt, fs,
+    path::PathBuf,
+    rc::Rc,
+};
 use tokio::{sync::Notify, task::JoinHandle};
 use tracing::{error, warn};
 
 pub type DynGpuController = Box<dyn GpuController>;
-type FanControlHandle = (Rc<Notify>, JoinHandle<()>);
+
+/// A setting that can be changed outside of the daemon, such as by the driver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriftedSetting {
+    PowerCap,
+    PerformanceLevel,
+    PowerProfileMode,
+    FanControl,
+}
+
+impl DriftedSetting {
+    /// Name of the setting in the API
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PowerCap => "power_cap",
+            Self::PerformanceLevel => "performance_level",
+            Self::PowerProfileMode => "power_profile_mode_index",
+            Self::FanControl => "fan_control",
+        }
+    }
+}
+
+impl fmt::Display for DriftedSetting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+pub struct FanControlHandle {
+    notify: Rc<Notify>,
+    handle: JoinHandle<()>,
+    /// Set by the fan control task when it stops because of a fan problem
+    alert: Rc<RefCell<Option<FanAlert>>>,
+}
+
+/// The alert of the currently running (or stopped) fan control task, if there is one
+fn fan_control_alert(handle: &RefCell<Option<FanControlHandle>>) -> Option<FanAlert> {
+    handle
+        .try_borrow()
+        .ok()?
+        .as_ref()
+        .and_then(|handle| handle.alert.borrow().clone())
+}
+
+/// Whether a setting differs from the previously applied config, or there is no previously applied config
+fn is_changed<T: PartialEq>(
+    previous: Option<&config::Gpu>,
+    config: &config::Gpu,
+    setting: fn(&config::Gpu) -> &T,
+) -> bool {
+    previous.map_or(true, |previous| setting(previous) != setting(config))
+}
 
 pub trait GpuController {
     fn controller_info(&self) -> &CommonControllerInfo;
@@ -38,6 +100,23 @@ pub trait GpuController {
         config: &'a config::Gpu,
     ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
 
+    /// Applies `config` over the currently applied config, which must not have any settings
+    /// that were removed in `config`. Only the settings that have changed are written,
+    /// as rewriting clocks briefly resets them, and restarting fan control resets its state.
+    fn apply_config_changes<'a>(
+        &'a self,
+        previous: &'a config::Gpu,
+        config: &'a config::Gpu,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
+    /// Reapplies the given settings from `config`, as reported by `get_drifted_settings`.
+    /// Unlike a full apply, this leaves the other settings untouched.
+    fn reapply_settings<'a>(
+        &'a self,
+        config: &'a config::Gpu,
+        settings: &'a [DriftedSetting],
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>>;
+
     fn get_stats(&self, gpu_config: Option<&config::Gpu>) -> DeviceStats;
 
     fn get_clocks_info(&self) -> anyhow::Result<ClocksInfo>;
@@ -50,6 +129,9 @@ pub trait GpuController {
 
     fn get_power_profile_modes(&self) -> anyhow::Result<PowerProfileModesTable>;
 
+    /// Compares the hardware state with the applied config, returning the names of the settings that no longer match
+    fn get_drifted_settings(&self, config: &config::Gpu) -> Vec<DriftedSetting>;
+
     fn vbios_dump(&self) -> anyhow::Result<Vec<u8>>;
 }
 
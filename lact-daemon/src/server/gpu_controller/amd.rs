@@ -1,5 +1,7 @@
 use super::{
-    fan_control::FanCurve, CommonControllerInfo, FanControlHandle, GpuController, VENDOR_AMD,
+    fan_control::{spawn_curve_fan_control, FanBackend, FanCurve},
+    fan_control_alert, is_changed, CommonControllerInfo, DriftedSetting, FanControlHandle,
+    GpuController, POWER_CAP_DRIFT_TOLERANCE, VENDOR_AMD,
 };
 use crate::{
     config::{self, ClocksConfiguration, FanControlSettings},
@@ -13,13 +15,14 @@ use amdgpu_sysfs::{
         power_profile_mode::PowerProfileModesTable,
         CommitHandle, GpuHandle, PerformanceLevel, PowerLevelKind, PowerLevels,
     },
-    hw_mon::{FanControlMethod, HwMon},
+    hw_mon::{FanControlMethod, HwMon, Temperature},
 };
 use anyhow::{anyhow, Context};
 use futures::future::LocalBoxFuture;
 use lact_schema::{
     ClocksInfo, ClockspeedStats, DeviceInfo, DeviceStats, DrmInfo, FanStats, IntelDrmInfo,
-    LinkInfo, PmfwInfo, PowerState, PowerStates, PowerStats, VoltageStats, VramStats,
+    LinkInfo, PmfwInfo, PowerState, PowerStates, PowerStats, SingleFanStats, VoltageStats,
+    VramStats,
 };
 use libdrm_amdgpu_sys::AMDGPU::{ThrottleStatus, ThrottlerBit};
 use libdrm_amdgpu_sys::{LibDrmAmdgpu, AMDGPU::SENSOR_INFO::SENSOR_TYPE};
@@ -28,16 +31,11 @@ use std::{
     cmp,
     collections::{HashMap, HashSet},
     path::PathBuf,
-    rc::Rc,
     time::Duration,
 };
-use std::{collections::BTreeMap, fs, time::Instant};
-use tokio::{
-    select,
-    sync::Notify,
-    time::{sleep, timeout},
-};
-use tracing::{debug, error, info, trace, warn};
+use std::{collections::BTreeMap, fs};
+use tokio::time::{sleep, timeout};
+use tracing::{debug, trace, warn};
 
 use {
     lact_schema::DrmMemoryInfo,
@@ -94,13 +92,14 @@ impl AmdGpuController {
 
     async fn set_static_fan_control(
         &self,
-        static_speed: f64,
+        settings: &FanControlSettings,
     ) -> anyhow::Result<Option<CommitHandle>> {
         // Stop existing task to set static speed
         self.stop_fan_control(false).await?;
 
         // Use PMFW curve functionality for static speed when it is available
         if let Ok(current_curve) = self.handle.get_fan_curve() {
+            let static_speed = settings.static_speed;
             let allowed_ranges = current_curve.allowed_ranges.clone().ok_or_else(|| {
                 anyhow!("The GPU does not allow setting custom fan values (is overdrive enabled?)")
             })?;
@@ -130,25 +129,9 @@ impl AmdGpuController {
 
             Ok(Some(commit_handle))
         } else {
-            let hw_mon = self
-                .handle
-                .hw_monitors
-                .first()
-                .cloned()
-                .context("This GPU has no monitor")?;
-
-            hw_mon
-                .set_fan_control_method(FanControlMethod::Manual)
-                .context("Could not set fan control method")?;
-
-            #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-            let static_pwm = (f64::from(u8::MAX) * static_speed) as u8;
-
-            hw_mon
-                .set_fan_pwm(static_pwm)
-                .context("could not set fan speed")?;
-
-            debug!("set fan speed to {}", static_speed);
+            // The speed is held by the fan control task, which also monitors the fan
+            self.start_curve_fan_control_task(settings).await?;
+            debug!("set fan speed to {}", settings.static_speed);
 
             Ok(None)
         }
@@ -157,7 +140,7 @@ impl AmdGpuController {
     async fn start_curve_fan_control(
         &self,
         curve: FanCurve,
-        settings: FanControlSettings,
+        settings: &FanControlSettings,
     ) -> anyhow::Result<Option<CommitHandle>> {
         // Use the PMFW curve functionality when it is available
         // Otherwise, fall back to manual fan control via a task
@@ -175,15 +158,14 @@ impl AmdGpuController {
 
             Ok(Some(commit_handle))
         } else {
-            self.start_curve_fan_control_task(curve, settings).await?;
+            self.start_curve_fan_control_task(settings).await?;
             Ok(None)
         }
     }
 
     async fn start_curve_fan_control_task(
         &self,
-        curve: FanCurve,
-        settings: FanControlSettings,
+        settings: &FanControlSettings,
     ) -> anyhow::Result<()> {
         // Stop existing task to re-apply new curve
         self.stop_fan_control(false).await?;
@@ -198,7 +180,7 @@ impl AmdGpuController {
         let temps = hw_mon.get_temps();
         match temps.len() {
             0 => return Err(anyhow!("GPU has no temperature reporting")),
-            1 => {
+            1 if settings.mode == lact_schema::FanControlMode::Curve => {
                 warn!("GPU has only one temperature sensor, 'temperature_key' setting will be ignored");
             }
             _ => (),
@@ -213,86 +195,8 @@ impl AmdGpuController {
             .try_borrow_mut()
             .map_err(|err| anyhow!("Lock error: {err}"))?;
 
-        let notify = Rc::new(Notify::new());
-        let task_notify = notify.clone();
-
-        debug!("spawning new fan control task");
-        let handle = tokio::task::spawn_local(async move {
-            let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0.0;
-
-            // If the fan speed could was able to be set at least once
-            let mut control_available = false;
-
-            let temp_key = settings.temperature_key.clone();
-            let interval = Duration::from_millis(settings.interval_ms);
-            let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
-            #[allow(clippy::cast_precision_loss)]
-            let change_threshold = settings.change_threshold.unwrap_or(0) as f32;
-
-            loop {
-                select! {
-                    () = sleep(interval) => (),
-                    () = task_notify.notified() => break,
-                }
-
-                let mut temps = hw_mon.get_temps();
-                let temp = if temps.len() == 1 {
-                    temps.into_values().next().unwrap()
-                } else {
-                    temps
-                        .remove(&temp_key)
-                        .expect("Could not get temperature by given key")
-                };
-
-                let current_temp = temp.current.expect("Missing temp");
-
-                if (last_temp - current_temp).abs() < change_threshold {
-                    trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
-                    continue;
-                }
-
-                let target_pwm = curve.pwm_at_temp(temp);
-                let now = Instant::now();
-
-                if let (Some(previous_pwm), previous_timestamp) = last_pwm {
-                    let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
-                        trace!(
-                            "delaying fan spindown ({}ms left)",
-                            (spindown_delay - diff).as_millis()
-                        );
-                        continue;
-                    }
-                }
-
-                last_pwm = (Some(target_pwm), now);
-                last_temp = current_temp;
-
-                trace!("fan control tick: setting pwm to {target_pwm}");
-
-                match hw_mon.set_fan_pwm(target_pwm) {
-                    Ok(()) => control_available = true,
-                    Err(err) => {
-                        error!("could not set fan speed: {err}");
-                        if control_available {
-                            info!("fan control was previously available, assuming the error is temporary");
-                        } else {
-                            info!("disabling fan control");
-                            break;
-                        }
-                    }
-                }
-            }
-            debug!("exited fan control task");
-        });
-
-        *notify_guard = Some((notify, handle));
-
-        debug!(
-            "started fan control with interval {}ms",
-            settings.interval_ms
-        );
+        let backend = HwMonFanBackend { hw_mon };
+        *notify_guard = Some(spawn_curve_fan_control(backend, settings)?);
 
         Ok(())
     }
@@ -303,7 +207,7 @@ impl AmdGpuController {
             .try_borrow_mut()
             .map_err(|err| anyhow!("Lock error: {err}"))?
             .take();
-        if let Some((notify, handle)) = maybe_notify {
+        if let Some(FanControlHandle { notify, handle, .. }) = maybe_notify {
             notify.notify_one();
             handle.await?;
         }
@@ -368,6 +272,380 @@ impl AmdGpuController {
             .collect()
     }
 
+    async fn start_fan_control(
+        &self,
+        settings: &FanControlSettings,
+    ) -> anyhow::Result<Option<CommitHandle>> {
+        match settings.mode {
+            lact_schema::FanControlMode::Static => self
+                .set_static_fan_control(settings)
+                .await
+                .context("Failed to set static fan control"),
+            lact_schema::FanControlMode::Curve => {
+                if settings.curve.0.is_empty() {
+                    return Err(anyhow!("Cannot use empty fan curve"));
+                }
+
+                self.start_curve_fan_control(settings.curve.clone(), settings)
+                    .await
+                    .context("Failed to set curve fan control")
+            }
+            lact_schema::FanControlMode::MultiSensor => {
+                // PMFW curves only support a single sensor
+                self.start_curve_fan_control_task(settings)
+                    .await
+                    .context("Failed to set multi-sensor fan control")?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reapplies only the given settings, as a full apply would also rewrite the clocks
+    async fn reapply_drifted_settings(
+        &self,
+        config: &config::Gpu,
+        settings: &[DriftedSetting],
+    ) -> anyhow::Result<()> {
+        for setting in settings {
+            match setting {
+                DriftedSetting::PowerCap => self.apply_power_cap(config.power_cap).await?,
+                DriftedSetting::PerformanceLevel => {
+                    if let Some(level) = config.performance_level {
+                        self.handle
+                            .set_power_force_performance_level(level)
+                            .context("Failed to set power performance level")?;
+                    }
+                }
+                DriftedSetting::PowerProfileMode => {
+                    if let Some(mode_index) = config.power_profile_mode_index {
+                        self.handle
+                            .set_active_power_profile_mode(mode_index)
+                            .context("Failed to set active power profile mode")?;
+                    }
+                }
+                DriftedSetting::FanControl => {
+                    if let Some(settings) = &config.fan_control_settings {
+                        if let Some(commit_handle) = self.start_fan_control(settings).await? {
+                            commit_handle.commit()?;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn apply_power_cap(&self, power_cap: Option<f64>) -> anyhow::Result<()> {
+        if let Some(cap) = power_cap {
+            let hw_mon = self.first_hw_mon()?;
+
+            let current_usage = hw_mon
+                .get_power_input()
+                .or_else(|_| hw_mon.get_power_average())
+                .context("Could not get current power usage")?;
+
+            // When applying a power limit that's lower than the current power consumption,
+            // try to downclock the GPU first by forcing it into the lowest performance level.
+            // Workaround for behaviour described in https://github.com/ilya-zlobintsev/LACT/issues/207
+            let mut original_performance_level = None;
+            if current_usage > cap {
+                if let Ok(performance_level) = self.handle.get_power_force_performance_level() {
+                    if self
+                        .handle
+                        .set_power_force_performance_level(PerformanceLevel::Low)
+                        .is_ok()
+                    {
+                        debug!(
+                            "waiting for the GPU to clock down before applying a new power limit"
+                        );
+
+                        match timeout(
+                            Duration::from_secs(GPU_CLOCKDOWN_TIMEOUT_SECS),
+                            wait_until_lowest_clock_level(&self.handle),
+                        )
+                        .await
+                        {
+                            Ok(()) => {
+                                debug!("GPU clocked down successfully");
+                            }
+                            Err(_) => {
+                                warn!("GPU did not clock down after {GPU_CLOCKDOWN_TIMEOUT_SECS}");
+                            }
+                        }
+
+                        original_performance_level = Some(performance_level);
+                    }
+                }
+            }
+
+            // Due to possible driver bug, RX 7900 XTX really doesn't like when we set the same value again.
+            // But, also in general we want to avoid setting same value twice
+            if Ok(cap) != hw_mon.get_power_cap() {
+                hw_mon
+                    .set_power_cap(cap)
+                    .with_context(|| format!("Failed to set power cap: {cap}"))?;
+            }
+
+            // Reapply old power level
+            if let Some(level) = original_performance_level {
+                self.handle
+                    .set_power_force_performance_level(level)
+                    .context("Could not reapply original performance level")?;
+            }
+        } else if let Ok(hw_mon) = self.first_hw_mon() {
+            if let Ok(default_cap) = hw_mon.get_power_cap_default() {
+                // Due to possible driver bug, RX 7900 XTX really doesn't like when we set the same value again.
+                // But, also in general we want to avoid setting same value twice
+                if Ok(default_cap) != hw_mon.get_power_cap() {
+                    hw_mon.set_power_cap(default_cap).with_context(|| {
+                        format!("Failed to set power cap to default cap: {default_cap}")
+                    })?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies the given config. When the previously applied config is given,
+    /// only the settings that differ from it are written.
+    #[allow(clippy::too_many_lines)]
+    async fn apply_settings(
+        &self,
+        config: &config::Gpu,
+        previous: Option<&config::Gpu>,
+    ) -> anyhow::Result<()> {
+        if is_changed(previous, config, |gpu| &gpu.power_cap) {
+            self.apply_power_cap(config.power_cap).await?;
+        }
+
+        let mut commit_handles = Vec::new();
+
+        // Clocks are left as-is when they were not changed, as rewriting them briefly resets them
+        let write_clocks = is_changed(previous, config, |gpu| &gpu.clocks_configuration);
+        if write_clocks {
+            // Reset the clocks table in case the settings get reverted back to not having a clocks value configured
+            self.handle.reset_clocks_table().ok();
+
+            if self.is_steam_deck() {
+                // Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
+                self.handle
+                    .set_power_force_performance_level(PerformanceLevel::Manual)
+                    .ok();
+            } else {
+                // Reset performance level to work around some GPU quirks (found to be an issue on RDNA2)
+                self.handle
+                    .set_power_force_performance_level(PerformanceLevel::Auto)
+                    .ok();
+            }
+
+            if config.is_core_clocks_used() {
+                let original_table = self
+                    .handle
+                    .get_clocks_table()
+                    .context("Failed to get clocks table")?;
+                let mut table = original_table.clone();
+                config
+                    .clocks_configuration
+                    .apply_to_table(&mut table)
+                    .context("Failed to apply clocks configuration to table")?;
+
+                debug!(
+                    "writing clocks commands: {:#?}",
+                    table
+                        .get_commands(&original_table)
+                        .context("Failed to get table commands")?
+                );
+
+                let handle = self
+                    .handle
+                    .set_clocks_table(&table)
+                    .context("Could not write clocks table")
+                    .with_context(|| {
+                        format!(
+                            "Clocks table commands: {:?}",
+                            table.get_commands(&original_table)
+                        )
+                    })?;
+                commit_handles.push(handle);
+            }
+        }
+
+        // Writing the clocks resets the performance level, which can also reset the profile mode and power states
+        let write_performance_level =
+            write_clocks || is_changed(previous, config, |gpu| &gpu.performance_level);
+        if write_performance_level {
+            if let Some(level) = config.performance_level {
+                self.handle
+                    .set_power_force_performance_level(level)
+                    .context("Failed to set power performance level")?;
+            }
+            // Else is not needed, it was previously reset to auto already or was not set before
+        }
+
+        let write_power_profile_mode = write_performance_level
+            || is_changed(previous, config, |gpu| &gpu.power_profile_mode_index)
+            || is_changed(previous, config, |gpu| {
+                &gpu.custom_power_profile_mode_hueristics
+            });
+        if let Some(mode_index) = config
+            .power_profile_mode_index
+            .filter(|_| write_power_profile_mode)
+        {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to use power profile modes"
+                ));
+            }
+
+            if config.custom_power_profile_mode_hueristics.is_empty() {
+                self.handle
+                    .set_active_power_profile_mode(mode_index)
+                    .context("Failed to set active power profile mode")?;
+            } else {
+                self.handle
+                    .set_custom_power_profile_mode_heuristics(
+                        &config.custom_power_profile_mode_hueristics,
+                    )
+                    .context("Failed to set custom power profile mode heuristics")?;
+            }
+        }
+
+        // Restarting the fan control task resets its state, such as the smoothing and the stall timers
+        let write_fan_control = is_changed(previous, config, |gpu| &gpu.fan_control_enabled)
+            || is_changed(previous, config, |gpu| &gpu.fan_control_settings)
+            || is_changed(previous, config, |gpu| &gpu.pmfw_options);
+        if write_fan_control {
+            if config.fan_control_enabled {
+                if let Some(ref settings) = config.fan_control_settings {
+                    if let Some(commit_handle) = self.start_fan_control(settings).await? {
+                        commit_handles.push(commit_handle);
+                    }
+                } else {
+                    return Err(anyhow!(
+                        "Trying to enable fan control with no settings provided"
+                    ));
+                }
+            } else {
+                let pmfw = &config.pmfw_options;
+                if let Some(acoustic_limit) = pmfw.acoustic_limit {
+                    if self
+                        .handle
+                        .get_fan_acoustic_limit()
+                        .context("Could not get acoustic limit")?
+                        .current
+                        != acoustic_limit
+                    {
+                        let commit_handle = self
+                            .handle
+                            .set_fan_acoustic_limit(acoustic_limit)
+                            .context("Could not set acoustic limit")?;
+                        commit_handles.push(commit_handle);
+                    }
+                }
+                if let Some(acoustic_target) = pmfw.acoustic_target {
+                    if self
+                        .handle
+                        .get_fan_acoustic_target()
+                        .context("Could not get acoustic target")?
+                        .current
+                        != acoustic_target
+                    {
+                        let commit_handle = self
+                            .handle
+                            .set_fan_acoustic_target(acoustic_target)
+                            .context("Could not set acoustic target")?;
+                        commit_handles.push(commit_handle);
+                    }
+                }
+                if let Some(target_temperature) = pmfw.target_temperature {
+                    if self
+                        .handle
+                        .get_fan_target_temperature()
+                        .context("Could not get target temperature")?
+                        .current
+                        != target_temperature
+                    {
+                        let commit_handle = self
+                            .handle
+                            .set_fan_target_temperature(target_temperature)
+                            .context("Could not set target temperature")?;
+                        commit_handles.push(commit_handle);
+                    }
+                }
+                if let Some(minimum_pwm) = pmfw.minimum_pwm {
+                    if self
+                        .handle
+                        .get_fan_minimum_pwm()
+                        .context("Could not get minimum pwm")?
+                        .current
+                        != minimum_pwm
+                    {
+                        let commit_handle = self
+                            .handle
+                            .set_fan_minimum_pwm(minimum_pwm)
+                            .context("Could not set minimum pwm")?;
+                        commit_handles.push(commit_handle);
+                    }
+                }
+
+                self.stop_fan_control(true)
+                    .await
+                    .context("Failed to stop fan control")?;
+            }
+        }
+
+        // Unlike the other PMFW options, zero rpm should be functional with a custom curve
+        if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
+            let current_zero_rpm = self
+                .handle
+                .get_fan_zero_rpm_enable()
+                .context("Could not get zero RPM mode")?;
+            if current_zero_rpm != zero_rpm {
+                let commit_handle = self
+                    .handle
+                    .set_fan_zero_rpm_enable(zero_rpm)
+                    .context("Could not set zero RPM mode")?;
+                commit_handles.push(commit_handle);
+            }
+        }
+
+        if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
+            let current_threshold = self
+                .handle
+                .get_fan_zero_rpm_stop_temperature()
+                .context("Could not get zero RPM temperature")?;
+            if current_threshold.current != zero_rpm_threshold {
+                let commit_handle = self
+                    .handle
+                    .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
+                    .context("Could not set zero RPM temperature")?;
+                commit_handles.push(commit_handle);
+            }
+        }
+
+        for handle in commit_handles {
+            handle.commit()?;
+        }
+
+        let write_power_states =
+            write_performance_level || is_changed(previous, config, |gpu| &gpu.power_states);
+        for (kind, states) in config.power_states.iter().filter(|_| write_power_states) {
+            if config.performance_level != Some(PerformanceLevel::Manual) {
+                return Err(anyhow!(
+                    "Performance level has to be set to `manual` to configure power states"
+                ));
+            }
+
+            self.handle
+                .set_enabled_power_levels(*kind, states)
+                .with_context(|| format!("Could not set {kind:?} power states"))?;
+        }
+
+        Ok(())
+    }
+
     fn first_hw_mon(&self) -> anyhow::Result<&HwMon> {
         self.handle
             .hw_monitors
@@ -524,6 +802,36 @@ impl AmdGpuController {
     }
 }
 
+struct HwMonFanBackend {
+    hw_mon: HwMon,
+}
+
+impl FanBackend for HwMonFanBackend {
+    fn read_temperatures(&self) -> anyhow::Result<HashMap<String, Temperature>> {
+        Ok(self.hw_mon.get_temps())
+    }
+
+    fn fan_count(&self) -> anyhow::Result<u32> {
+        Ok(1)
+    }
+
+    fn set_duty(&self, _fan: u32, pwm: u8) -> anyhow::Result<()> {
+        self.hw_mon
+            .set_fan_pwm(pwm)
+            .context("Could not set fan pwm")
+    }
+
+    fn fan_speed(&self, _fan: u32) -> anyhow::Result<Option<u32>> {
+        Ok(self.hw_mon.get_fan_current().ok())
+    }
+
+    fn restore_auto(&self) -> anyhow::Result<()> {
+        self.hw_mon
+            .set_fan_control_method(FanControlMethod::Auto)
+            .context("Could not set fan control back to automatic")
+    }
+}
+
 impl GpuController for AmdGpuController {
     fn controller_info(&self) -> &CommonControllerInfo {
         &self.common
@@ -555,6 +863,20 @@ impl GpuController for AmdGpuController {
 
     fn get_stats(&self, gpu_config: Option<&config::Gpu>) -> DeviceStats {
         let fan_settings = gpu_config.and_then(|config| config.fan_control_settings.as_ref());
+        let speed_current = self.hw_mon_and_then(HwMon::get_fan_current);
+        let pwm_current = self.hw_mon_and_then(HwMon::get_fan_pwm);
+        // The hwmon interface only exposes a single fan
+        let fans = if speed_current.is_some() || pwm_current.is_some() {
+            vec![SingleFanStats {
+                index: 0,
+                speed_current,
+                pwm_current,
+                pwm_target: None,
+            }]
+        } else {
+            Vec::new()
+        };
+
         DeviceStats {
             fan: FanStats {
                 control_enabled: gpu_config.is_some_and(|config| config.fan_control_enabled),
@@ -563,10 +885,24 @@ impl GpuController for AmdGpuController {
                 curve: fan_settings.map(|settings| settings.curve.0.clone()),
                 spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
-                speed_current: self.hw_mon_and_then(HwMon::get_fan_current),
+                temperature_smoothing: fan_settings
+                    .and_then(|settings| settings.temperature_smoothing),
+                hysteresis_up: fan_settings.and_then(|settings| settings.hysteresis_up),
+                hysteresis_down: fan_settings.and_then(|settings| settings.hysteresis_down),
+                max_pwm_slew_rate: fan_settings.and_then(|settings| settings.max_pwm_slew_rate),
+                sensor_curves: fan_settings
+                    .map(|settings| settings.sensor_curves.clone())
+                    .unwrap_or_default(),
+                sensor_combination: fan_settings.and_then(|settings| settings.sensor_combination),
+                fan_overrides: fan_settings
+                    .map(|settings| settings.fan_overrides.clone())
+                    .unwrap_or_default(),
+                speed_current,
                 speed_max: self.hw_mon_and_then(HwMon::get_fan_max),
                 speed_min: self.hw_mon_and_then(HwMon::get_fan_min),
-                pwm_current: self.hw_mon_and_then(HwMon::get_fan_pwm),
+                pwm_current,
+                fans,
+                alert: fan_control_alert(&self.fan_control_handle),
                 pmfw_info: PmfwInfo {
                     acoustic_limit: self.handle.get_fan_acoustic_limit().ok(),
                     acoustic_target: self.handle.get_fan_acoustic_target().ok(),
@@ -575,6 +911,9 @@ impl GpuController for AmdGpuController {
                     zero_rpm_enable: self.handle.get_fan_zero_rpm_enable().ok(),
                     zero_rpm_temperature: self.handle.get_fan_zero_rpm_stop_temperature().ok(),
                 },
+                pmfw_options: gpu_config
+                    .map(|config| config.pmfw_options)
+                    .unwrap_or_default(),
             },
             clockspeed: self.get_clockspeed(),
             voltage: VoltageStats {
@@ -633,6 +972,61 @@ impl GpuController for AmdGpuController {
         Ok(self.handle.get_power_profile_modes()?)
     }
 
+    fn get_drifted_settings(&self, config: &config::Gpu) -> Vec<DriftedSetting> {
+        let mut drifted = Vec::new();
+
+        if let Some(cap) = config.power_cap {
+            if self
+                .hw_mon_and_then(HwMon::get_power_cap)
+                .is_some_and(|current| (current - cap).abs() > POWER_CAP_DRIFT_TOLERANCE)
+            {
+                drifted.push(DriftedSetting::PowerCap);
+            }
+        }
+
+        if let Some(level) = config.performance_level {
+            if self
+                .handle
+                .get_power_force_performance_level()
+                .is_ok_and(|current| current != level)
+            {
+                drifted.push(DriftedSetting::PerformanceLevel);
+            }
+        }
+
+        if let Some(mode_index) = config.power_profile_mode_index {
+            if config.custom_power_profile_mode_hueristics.is_empty()
+                && self
+                    .handle
+                    .get_power_profile_modes()
+                    .is_ok_and(|table| table.active != usize::from(mode_index))
+            {
+                drifted.push(DriftedSetting::PowerProfileMode);
+            }
+        }
+
+        // Fan control that is not done through the PMFW curve needs the manual control method.
+        // An active fan alert means the control task has handed the fan back to the firmware.
+        if let Some(settings) = config
+            .fan_control_settings
+            .as_ref()
+            .filter(|_| config.fan_control_enabled)
+            .filter(|_| fan_control_alert(&self.fan_control_handle).is_none())
+        {
+            let uses_hw_mon = settings.mode == lact_schema::FanControlMode::MultiSensor
+                || self.handle.get_fan_curve().is_err();
+            if uses_hw_mon
+                && self
+                    .hw_mon_and_then(HwMon::get_fan_control_method)
+                    .is_some_and(|method| !matches!(method, FanControlMethod::Manual))
+            {
+                drifted.push(DriftedSetting::FanControl);
+            }
+        }
+
+        drifted
+    }
+
     fn reset_pmfw_settings(&self) {
         let handle = &self.handle;
         if self.handle.get_fan_target_temperature().is_ok() {
@@ -662,303 +1056,27 @@ impl GpuController for AmdGpuController {
         fs::read(debugfs.join("amdgpu_vbios")).context("Could not read VBIOS file")
     }
 
-    #[allow(clippy::too_many_lines)]
     fn apply_config<'a>(
         &'a self,
         config: &'a config::Gpu,
     ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
-        Box::pin(async {
-            if let Some(cap) = config.power_cap {
-                let hw_mon = self.first_hw_mon()?;
-
-                let current_usage = hw_mon
-                    .get_power_input()
-                    .or_else(|_| hw_mon.get_power_average())
-                    .context("Could not get current power usage")?;
-
-                // When applying a power limit that's lower than the current power consumption,
-                // try to downclock the GPU first by forcing it into the lowest performance level.
-                // Workaround for behaviour described in https://github.com/ilya-zlobintsev/LACT/issues/207
-                let mut original_performance_level = None;
-                if current_usage > cap {
-                    if let Ok(performance_level) = self.handle.get_power_force_performance_level() {
-                        if self
-                            .handle
-                            .set_power_force_performance_level(PerformanceLevel::Low)
-                            .is_ok()
-                        {
-                            debug!(
-                            "waiting for the GPU to clock down before applying a new power limit"
-                        );
-
-                            match timeout(
-                                Duration::from_secs(GPU_CLOCKDOWN_TIMEOUT_SECS),
-                                wait_until_lowest_clock_level(&self.handle),
-                            )
-                            .await
-                            {
-                                Ok(()) => {
-                                    debug!("GPU clocked down successfully");
-                                }
-                                Err(_) => {
-                                    warn!(
-                                        "GPU did not clock down after {GPU_CLOCKDOWN_TIMEOUT_SECS}"
-                                    );
-                                }
-                            }
-
-                            original_performance_level = Some(performance_level);
-                        }
-                    }
-                }
-
-                // Due to possible driver bug, RX 7900 XTX really doesn't like when we set the same value again.
-                // But, also in general we want to avoid setting same value twice
-                if Ok(cap) != hw_mon.get_power_cap() {
-                    hw_mon
-                        .set_power_cap(cap)
-                        .with_context(|| format!("Failed to set power cap: {cap}"))?;
-                }
-
-                // Reapply old power level
-                if let Some(level) = original_performance_level {
-                    self.handle
-                        .set_power_force_performance_level(level)
-                        .context("Could not reapply original performance level")?;
-                }
-            } else if let Ok(hw_mon) = self.first_hw_mon() {
-                if let Ok(default_cap) = hw_mon.get_power_cap_default() {
-                    // Due to possible driver bug, RX 7900 XTX really doesn't like when we set the same value again.
-                    // But, also in general we want to avoid setting same value twice
-                    if Ok(default_cap) != hw_mon.get_power_cap() {
-                        hw_mon.set_power_cap(default_cap).with_context(|| {
-                            format!("Failed to set power cap to default cap: {default_cap}")
-                        })?;
-                    }
-                }
-            }
-
-            let mut commit_handles = Vec::new();
-
-            // Reset the clocks table in case the settings get reverted back to not having a clocks value configured
-            self.handle.reset_clocks_table().ok();
-
-            if self.is_steam_deck() {
-                // Van Gogh/Sephiroth only allow clock settings to be used with manual performance mode
-                self.handle
-                    .set_power_force_performance_level(PerformanceLevel::Manual)
-                    .ok();
-            } else {
-                // Reset performance level to work around some GPU quirks (found to be an issue on RDNA2)
-                self.handle
-                    .set_power_force_performance_level(PerformanceLevel::Auto)
-                    .ok();
-            }
-
-            if config.is_core_clocks_used() {
-                let original_table = self
-                    .handle
-                    .get_clocks_table()
-                    .context("Failed to get clocks table")?;
-                let mut table = original_table.clone();
-                config
-                    .clocks_configuration
-                    .apply_to_table(&mut table)
-                    .context("Failed to apply clocks configuration to table")?;
-
-                debug!(
-                    "writing clocks commands: {:#?}",
-                    table
-                        .get_commands(&original_table)
-                        .context("Failed to get table commands")?
-                );
-
-                let handle = self
-                    .handle
-                    .set_clocks_table(&table)
-                    .context("Could not write clocks table")
-                    .with_context(|| {
-                        format!(
-                            "Clocks table commands: {:?}",
-                            table.get_commands(&original_table)
-                        )
-                    })?;
-                commit_handles.push(handle);
-            }
-
-            if let Some(level) = config.performance_level {
-                self.handle
-                    .set_power_force_performance_level(level)
-                    .context("Failed to set power performance level")?;
-            }
-            // Else is not needed, it was previously reset to auto already
-
-            if let Some(mode_index) = config.power_profile_mode_index {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to use power profile modes"
-                    ));
-                }
-
-                if config.custom_power_profile_mode_hueristics.is_empty() {
-                    self.handle
-                        .set_active_power_profile_mode(mode_index)
-                        .context("Failed to set active power profile mode")?;
-                } else {
-                    self.handle
-                        .set_custom_power_profile_mode_heuristics(
-                            &config.custom_power_profile_mode_hueristics,
-                        )
-                        .context("Failed to set custom power profile mode heuristics")?;
-                }
-            }
-
-            if config.fan_control_enabled {
-                if let Some(ref settings) = config.fan_control_settings {
-                    match settings.mode {
-                        lact_schema::FanControlMode::Static => {
-                            if let Some(commit_handle) = self
-                                .set_static_fan_control(settings.static_speed)
-                                .await
-                                .context("Failed to set static fan control")?
-                            {
-                                commit_handles.push(commit_handle);
-                            }
-                        }
-                        lact_schema::FanControlMode::Curve => {
-                            if settings.curve.0.is_empty() {
-                                return Err(anyhow!("Cannot use empty fan curve"));
-                            }
-
-                            if let Some(commit_handle) = self
-                                .start_curve_fan_control(settings.curve.clone(), settings.clone())
-                                .await
-                                .context("Failed to set curve fan control")?
-                            {
-                                commit_handles.push(commit_handle);
-                            }
-                        }
-                    }
-                } else {
-                    return Err(anyhow!(
-                        "Trying to enable fan control with no settings provided"
-                    ));
-                }
-            } else {
-                let pmfw = &config.pmfw_options;
-                if let Some(acoustic_limit) = pmfw.acoustic_limit {
-                    if self
-                        .handle
-                        .get_fan_acoustic_limit()
-                        .context("Could not get acoustic limit")?
-                        .current
-                        != acoustic_limit
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_limit(acoustic_limit)
-                            .context("Could not set acoustic limit")?;
-                        commit_handles.push(commit_handle);
-                    }
-                }
-                if let Some(acoustic_target) = pmfw.acoustic_target {
-                    if self
-                        .handle
-                        .get_fan_acoustic_target()
-                        .context("Could not get acoustic target")?
-                        .current
-                        != acoustic_target
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_acoustic_target(acoustic_target)
-                            .context("Could not set acoustic target")?;
-                        commit_handles.push(commit_handle);
-                    }
-                }
-                if let Some(target_temperature) = pmfw.target_temperature {
-                    if self
-                        .handle
-                        .get_fan_target_temperature()
-                        .context("Could not get target temperature")?
-                        .current
-                        != target_temperature
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_target_temperature(target_temperature)
-                            .context("Could not set target temperature")?;
-                        commit_handles.push(commit_handle);
-                    }
-                }
-                if let Some(minimum_pwm) = pmfw.minimum_pwm {
-                    if self
-                        .handle
-                        .get_fan_minimum_pwm()
-                        .context("Could not get minimum pwm")?
-                        .current
-                        != minimum_pwm
-                    {
-                        let commit_handle = self
-                            .handle
-                            .set_fan_minimum_pwm(minimum_pwm)
-                            .context("Could not set minimum pwm")?;
-                        commit_handles.push(commit_handle);
-                    }
-                }
-
-                self.stop_fan_control(true)
-                    .await
-                    .context("Failed to stop fan control")?;
-            }
-
-            // Unlike the other PMFW options, zero rpm should be functional with a custom curve
-            if let Some(zero_rpm) = config.pmfw_options.zero_rpm {
-                let current_zero_rpm = self
-                    .handle
-                    .get_fan_zero_rpm_enable()
-                    .context("Could not get zero RPM mode")?;
-                if current_zero_rpm != zero_rpm {
-                    let commit_handle = self
-                        .handle
-                        .set_fan_zero_rpm_enable(zero_rpm)
-                        .context("Could not set zero RPM mode")?;
-                    commit_handles.push(commit_handle);
-                }
-            }
-
-            if let Some(zero_rpm_threshold) = config.pmfw_options.zero_rpm_threshold {
-                let current_threshold = self
-                    .handle
-                    .get_fan_zero_rpm_stop_temperature()
-                    .context("Could not get zero RPM temperature")?;
-                if current_threshold.current != zero_rpm_threshold {
-                    let commit_handle = self
-                        .handle
-                        .set_fan_zero_rpm_stop_temperature(zero_rpm_threshold)
-                        .context("Could not set zero RPM temperature")?;
-                    commit_handles.push(commit_handle);
-                }
-            }
-
-            for handle in commit_handles {
-                handle.commit()?;
-            }
-
-            for (kind, states) in &config.power_states {
-                if config.performance_level != Some(PerformanceLevel::Manual) {
-                    return Err(anyhow!(
-                        "Performance level has to be set to `manual` to configure power states"
-                    ));
-                }
+        Box::pin(self.apply_settings(config, None))
+    }
 
-                self.handle
-                    .set_enabled_power_levels(*kind, states)
-                    .with_context(|| format!("Could not set {kind:?} power states"))?;
-            }
+    fn apply_config_changes<'a>(
+        &'a self,
+        previous: &'a config::Gpu,
+        config: &'a config::Gpu,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.apply_settings(config, Some(previous)))
+    }
 
-            Ok(())
-        })
+    fn reapply_settings<'a>(
+        &'a self,
+        config: &'a config::Gpu,
+        settings: &'a [DriftedSetting],
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.reapply_drifted_settings(config, settings))
     }
 
     fn cleanup_clocks(&self) -> anyhow::Result<()> {
@@ -1,10 +1,26 @@
-use std::cmp;
+use std::{
+    cell::RefCell,
+    cmp,
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
+use super::FanControlHandle;
+use crate::config::{FanControlSettings, FanSafetySettings};
 use amdgpu_sysfs::{gpu_handle::fan_control::FanCurve as PmfwCurve, hw_mon::Temperature};
 use anyhow::{anyhow, Context};
-use lact_schema::{default_fan_curve, FanCurveMap};
+use lact_schema::{
+    default_fan_curve, default_sensor_weight, FanAlert, FanControlMode, FanCurveMap,
+    SensorCombination, TemperatureSmoothing,
+};
 use serde::{Deserialize, Serialize};
-use tracing::warn;
+use tokio::{select, sync::Notify, time::sleep};
+use tracing::{debug, error, info, trace, warn};
+
+/// How far below the critical temperature a sensor is considered to be in a runaway,
+/// unless the runaway temperature is specified explicitly
+const RUNAWAY_CRIT_MARGIN: f32 = 5.0;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct FanCurve(pub FanCurveMap);
@@ -89,10 +105,578 @@ impl Default for FanCurve {
     }
 }
 
+/// Hardware access used by the curve fan control loop.
+/// Implemented separately for each vendor, so that the curve logic is shared between them.
+pub trait FanBackend {
+    /// All available temperature sensors by name
+    fn read_temperatures(&self) -> anyhow::Result<HashMap<String, Temperature>>;
+
+    fn fan_count(&self) -> anyhow::Result<u32>;
+
+    /// Set the PWM value (out of 255) of the given fan
+    fn set_duty(&self, fan: u32, pwm: u8) -> anyhow::Result<()>;
+
+    /// Current speed of the given fan in RPM, if it can be read
+    fn fan_speed(&self, fan: u32) -> anyhow::Result<Option<u32>>;
+
+    /// Give fan control back to the firmware
+    fn restore_auto(&self) -> anyhow::Result<()>;
+}
+
+/// Follows a fan curve using the given backend.
+/// Static speeds are also held through this loop, so that they get the same safety monitoring.
+pub struct CurveFanControl<B> {
+    backend: B,
+    fans: Vec<FanTarget>,
+    /// Last PWM value that was set on each fan
+    duties: Vec<Option<u8>>,
+    /// If the fan speed was able to be set at least once
+    control_available: bool,
+    safety: SafetyMonitor,
+    alert: Rc<RefCell<Option<FanAlert>>>,
+}
+
+enum FanTarget {
+    Curve { filter: CurveFilter, offset: f32 },
+    Static(u8),
+}
+
+impl<B: FanBackend> CurveFanControl<B> {
+    pub fn new(backend: B, settings: &FanControlSettings) -> anyhow::Result<Self> {
+        let temps = backend
+            .read_temperatures()
+            .context("Could not read temperature")?;
+
+        let fan_count = backend.fan_count().context("Could not read fan count")?;
+        if fan_count == 0 {
+            return Err(anyhow!("Device has no fans"));
+        }
+
+        let fans = (0..fan_count)
+            .map(|fan| {
+                if settings.mode == FanControlMode::Static {
+                    return Ok(FanTarget::Static(static_fan_pwm(settings, fan)));
+                }
+
+                let fan_override = settings.fan_overrides.get(&fan);
+                let filter = match fan_override.and_then(|fan_override| fan_override.curve.as_ref())
+                {
+                    Some(curve) => CurveFilter::new(&FanControlSettings {
+                        mode: FanControlMode::Curve,
+                        curve: FanCurve(curve.clone()),
+                        ..settings.clone()
+                    })?,
+                    None => CurveFilter::new(settings)?,
+                };
+                filter.check_sensors(&temps)?;
+
+                let offset = fan_override
+                    .and_then(|fan_override| fan_override.speed_offset)
+                    .unwrap_or(0.0);
+                Ok(FanTarget::Curve { filter, offset })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            backend,
+            duties: vec![None; fans.len()],
+            safety: SafetyMonitor::new(settings, fans.len()),
+            fans,
+            control_available: false,
+            alert: Rc::new(RefCell::new(None)),
+        })
+    }
+
+    /// Runs a single iteration of the control loop.
+    /// Returns `false` if fan control should be stopped.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let temps = match self.backend.read_temperatures() {
+            Ok(temps) => temps,
+            Err(err) => {
+                error!("could not read temperature: {err:#}");
+                return is_error_transient(self.control_available);
+            }
+        };
+
+        for ((fan, target), duty) in (0..).zip(&mut self.fans).zip(&mut self.duties) {
+            let target_pwm = match target {
+                FanTarget::Curve { filter, offset } => match filter.next_pwm(&temps, now) {
+                    Ok(Some(pwm)) => apply_speed_offset(pwm, *offset),
+                    Ok(None) => continue,
+                    Err(err) => {
+                        error!("could not read temperature: {err:#}");
+                        if is_error_transient(self.control_available) {
+                            continue;
+                        }
+                        return false;
+                    }
+                },
+                FanTarget::Static(pwm) if *duty == Some(*pwm) => continue,
+                FanTarget::Static(pwm) => *pwm,
+            };
+
+            trace!("fan control tick: setting pwm of fan {fan} to {target_pwm}");
+
+            match self.backend.set_duty(fan, target_pwm) {
+                Ok(()) => {
+                    self.control_available = true;
+                    *duty = Some(target_pwm);
+                }
+                Err(err) => {
+                    error!("could not set fan speed: {err:#}");
+                    if !is_error_transient(self.control_available) {
+                        return false;
+                    }
+                }
+            }
+        }
+
+        if let Some(alert) = self.safety.check(&self.backend, &temps, &self.duties, now) {
+            error!("{alert}, giving fan control back to the firmware");
+            *self.alert.borrow_mut() = Some(alert);
+            return false;
+        }
+
+        true
+    }
+
+    async fn run(mut self, interval: Duration, notify: Rc<Notify>) {
+        loop {
+            select! {
+                () = sleep(interval) => (),
+                () = notify.notified() => break,
+            }
+
+            if !self.tick(Instant::now()) {
+                info!("disabling fan control");
+                if let Err(err) = self.backend.restore_auto() {
+                    error!("could not restore automatic fan control: {err:#}");
+                }
+                break;
+            }
+        }
+        debug!("exited fan control task");
+    }
+}
+
+fn is_error_transient(control_available: bool) -> bool {
+    if control_available {
+        info!("fan control was previously available, assuming the error is temporary");
+    }
+    control_available
+}
+
+/// Adds a speed offset (from -1 to 1) to a PWM value. Full speed is never reduced.
+pub fn apply_speed_offset(pwm: u8, offset: f32) -> u8 {
+    if pwm == u8::MAX {
+        return pwm;
+    }
+    let max = f32::from(u8::MAX);
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let pwm = (f32::from(pwm) + offset * max).round().clamp(0.0, max) as u8;
+    pwm
+}
+
+/// PWM value of the given fan in static mode, with the fan's speed offset applied
+fn static_fan_pwm(settings: &FanControlSettings, fan: u32) -> u8 {
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    let pwm = (settings.static_speed * f64::from(u8::MAX)) as u8;
+    match settings
+        .fan_overrides
+        .get(&fan)
+        .and_then(|fan_override| fan_override.speed_offset)
+    {
+        Some(offset) => apply_speed_offset(pwm, offset),
+        None => pwm,
+    }
+}
+
+/// Spawns a task that follows the fan curve (or the per-sensor curves, or holds the static speed)
+/// until notified to stop
+pub fn spawn_curve_fan_control<B: FanBackend + 'static>(
+    backend: B,
+    settings: &FanControlSettings,
+) -> anyhow::Result<FanControlHandle> {
+    let control = CurveFanControl::new(backend, settings)?;
+    let interval = Duration::from_millis(settings.interval_ms);
+    let alert = control.alert.clone();
+
+    let notify = Rc::new(Notify::new());
+    debug!("spawning new fan control task");
+    let handle = tokio::task::spawn_local(control.run(interval, notify.clone()));
+
+    debug!(
+        "started fan control with interval {}ms",
+        settings.interval_ms
+    );
+    Ok(FanControlHandle {
+        notify,
+        handle,
+        alert,
+    })
+}
+
+/// Detects fans that don't spin and temperatures that keep being too high under manual control
+struct SafetyMonitor {
+    settings: FanSafetySettings,
+    /// Sensors the fan speed is based on, stalls are only detected when these are hot
+    controlled_sensors: Vec<String>,
+    single_sensor: bool,
+    /// When each fan was first seen not spinning
+    stalled_since: Vec<Option<Instant>>,
+    runaway_since: Option<Instant>,
+}
+
+impl SafetyMonitor {
+    fn new(settings: &FanControlSettings, fan_count: usize) -> Self {
+        let single_sensor = settings.mode != FanControlMode::MultiSensor;
+        let controlled_sensors = if single_sensor {
+            vec![settings.temperature_key.clone()]
+        } else {
+            settings.sensor_curves.keys().cloned().collect()
+        };
+
+        Self {
+            settings: settings.safety.unwrap_or_default(),
+            controlled_sensors,
+            single_sensor,
+            stalled_since: vec![None; fan_count],
+            runaway_since: None,
+        }
+    }
+
+    /// `duties` are the last PWM values set on each fan
+    fn check(
+        &mut self,
+        backend: &impl FanBackend,
+        temps: &HashMap<String, Temperature>,
+        duties: &[Option<u8>],
+        now: Instant,
+    ) -> Option<FanAlert> {
+        self.check_runaway(temps, now)
+            .or_else(|| self.check_stall(backend, temps, duties, now))
+    }
+
+    fn check_runaway(
+        &mut self,
+        temps: &HashMap<String, Temperature>,
+        now: Instant,
+    ) -> Option<FanAlert> {
+        if self.settings.runaway_timeout_secs == 0 {
+            return None;
+        }
+
+        let runaway_temperature = self.settings.runaway_temperature;
+        let hot_sensor = temps.iter().find_map(|(name, temp)| {
+            let current = temp.current?;
+            let limit =
+                runaway_temperature.or_else(|| temp.crit.map(|crit| crit - RUNAWAY_CRIT_MARGIN))?;
+            (current >= limit).then_some((name, current))
+        });
+
+        let Some((sensor, temperature)) = hot_sensor else {
+            self.runaway_since = None;
+            return None;
+        };
+
+        let since = *self.runaway_since.get_or_insert(now);
+        if now.saturating_duration_since(since)
+            >= Duration::from_secs(self.settings.runaway_timeout_secs)
+        {
+            return Some(FanAlert::TemperatureRunaway {
+                sensor: sensor.clone(),
+                temperature,
+            });
+        }
+        None
+    }
+
+    fn check_stall(
+        &mut self,
+        backend: &impl FanBackend,
+        temps: &HashMap<String, Temperature>,
+        duties: &[Option<u8>],
+        now: Instant,
+    ) -> Option<FanAlert> {
+        if self.settings.stall_timeout_secs == 0 {
+            return None;
+        }
+        let temperature = self
+            .controlled_sensors
+            .iter()
+            .filter_map(|key| sensor_temp(self.single_sensor, key, temps).ok()?.current)
+            .reduce(f32::max)?;
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let min_pwm = (self.settings.stall_min_speed.clamp(0.0, 1.0) * f64::from(u8::MAX)) as u8;
+
+        for ((fan, duty), stalled_since) in (0..).zip(duties).zip(&mut self.stalled_since) {
+            let stalled = temperature >= self.settings.stall_temperature
+                && duty.is_some_and(|pwm| pwm > 0 && pwm >= min_pwm)
+                && match backend.fan_speed(fan) {
+                    Ok(speed) => speed == Some(0),
+                    Err(err) => {
+                        debug!("could not read speed of fan {fan}: {err:#}");
+                        false
+                    }
+                };
+
+            if !stalled {
+                *stalled_since = None;
+                continue;
+            }
+
+            let since = *stalled_since.get_or_insert(now);
+            if now.saturating_duration_since(since)
+                >= Duration::from_secs(self.settings.stall_timeout_secs)
+            {
+                return Some(FanAlert::Stall { fan, temperature });
+            }
+        }
+        None
+    }
+}
+
+/// Turns raw temperature readings into the PWM values that should be written to the fan,
+/// applying temperature smoothing, hysteresis, the maximum slew rate and the spindown delay.
+pub struct CurveFilter {
+    sensors: Vec<SensorFilter>,
+    /// With a single curve, the only available sensor is used regardless of its name
+    single_curve: bool,
+    combination: SensorCombination,
+    max_pwm_slew_rate: Option<u32>,
+    spindown_delay: Duration,
+    last_pwm: Option<(u8, Instant)>,
+}
+
+/// Smoothing and hysteresis state of a single temperature sensor
+struct SensorFilter {
+    key: String,
+    curve: FanCurve,
+    weight: f32,
+    smoothing: Option<TemperatureSmoothing>,
+    hysteresis_up: f32,
+    hysteresis_down: f32,
+
+    samples: VecDeque<f32>,
+    smoothed_temp: Option<f32>,
+    /// Temperature the current target was calculated at
+    reference_temp: Option<f32>,
+    target_pwm: u8,
+}
+
+#[allow(clippy::cast_precision_loss)]
+impl CurveFilter {
+    pub fn new(settings: &FanControlSettings) -> anyhow::Result<Self> {
+        let change_threshold = settings.change_threshold.unwrap_or(0);
+        let sensor_filter = |key: &str, curve: FanCurve, weight: f32| SensorFilter {
+            key: key.to_owned(),
+            curve,
+            weight,
+            smoothing: settings.temperature_smoothing,
+            hysteresis_up: settings.hysteresis_up.unwrap_or(change_threshold) as f32,
+            hysteresis_down: settings.hysteresis_down.unwrap_or(change_threshold) as f32,
+            samples: VecDeque::new(),
+            smoothed_temp: None,
+            reference_temp: None,
+            target_pwm: 0,
+        };
+
+        let single_curve = settings.mode != FanControlMode::MultiSensor;
+        let sensors = if single_curve {
+            vec![sensor_filter(
+                &settings.temperature_key,
+                settings.curve.clone(),
+                default_sensor_weight(),
+            )]
+        } else {
+            if settings.sensor_curves.is_empty() {
+                return Err(anyhow!("No sensor curves specified"));
+            }
+            settings
+                .sensor_curves
+                .iter()
+                .map(|(key, sensor_curve)| {
+                    let curve = FanCurve(sensor_curve.curve.clone());
+                    if curve.0.is_empty() {
+                        return Err(anyhow!("Curve for sensor '{key}' is empty"));
+                    }
+                    Ok(sensor_filter(key, curve, sensor_curve.weight))
+                })
+                .collect::<anyhow::Result<_>>()?
+        };
+
+        Ok(Self {
+            sensors,
+            single_curve,
+            combination: settings.sensor_combination.unwrap_or_default(),
+            max_pwm_slew_rate: settings.max_pwm_slew_rate,
+            spindown_delay: Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0)),
+            last_pwm: None,
+        })
+    }
+
+    /// Checks that all sensors used by the curves are available
+    pub fn check_sensors(&self, temps: &HashMap<String, Temperature>) -> anyhow::Result<()> {
+        for sensor in &self.sensors {
+            sensor_temp(self.single_curve, &sensor.key, temps)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the PWM value to set, or `None` if the fan speed should be left as-is
+    pub fn next_pwm(
+        &mut self,
+        temps: &HashMap<String, Temperature>,
+        now: Instant,
+    ) -> anyhow::Result<Option<u8>> {
+        let mut critical = false;
+        let mut max_pwm = 0;
+        let mut weighted_sum = 0.0;
+        let mut total_weight = 0.0;
+
+        for sensor in &mut self.sensors {
+            let temp = sensor_temp(self.single_curve, &sensor.key, temps)?;
+            let current = temp
+                .current
+                .with_context(|| format!("Temperature '{}' has no current value", sensor.key))?;
+
+            if temp.crit.is_some_and(|crit| current > crit) {
+                critical = true;
+            }
+
+            let pwm = sensor.target_pwm(current, temp);
+            max_pwm = max_pwm.max(pwm);
+            weighted_sum += f32::from(pwm) * sensor.weight;
+            total_weight += sensor.weight;
+        }
+
+        // Critical temperature should not be delayed by any of the filters
+        if critical {
+            self.last_pwm = Some((u8::MAX, now));
+            return Ok(Some(u8::MAX));
+        }
+
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let target_pwm = match self.combination {
+            SensorCombination::Weighted if total_weight > 0.0 => {
+                (weighted_sum / total_weight).round() as u8
+            }
+            _ => max_pwm,
+        };
+
+        let Some((previous_pwm, previous_timestamp)) = self.last_pwm else {
+            self.last_pwm = Some((target_pwm, now));
+            return Ok(Some(target_pwm));
+        };
+        let elapsed = now.saturating_duration_since(previous_timestamp);
+
+        let mut pwm = target_pwm;
+        if let Some(slew_rate) = self.max_pwm_slew_rate {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let max_step = (slew_rate as f32 * elapsed.as_secs_f32()).min(255.0) as u8;
+            if pwm != previous_pwm && max_step == 0 {
+                return Ok(None);
+            }
+            pwm = pwm.clamp(
+                previous_pwm.saturating_sub(max_step),
+                previous_pwm.saturating_add(max_step),
+            );
+        }
+
+        if pwm < previous_pwm && elapsed < self.spindown_delay {
+            trace!(
+                "delaying fan spindown ({}ms left)",
+                (self.spindown_delay - elapsed).as_millis()
+            );
+            return Ok(None);
+        }
+
+        self.last_pwm = Some((pwm, now));
+        Ok(Some(pwm))
+    }
+}
+
+fn sensor_temp<'a>(
+    single_curve: bool,
+    key: &str,
+    temps: &'a HashMap<String, Temperature>,
+) -> anyhow::Result<&'a Temperature> {
+    match temps.get(key) {
+        Some(temp) => Ok(temp),
+        None if single_curve && temps.len() == 1 => Ok(temps.values().next().unwrap()),
+        None => Err(anyhow!("Could not get temperature '{key}'")),
+    }
+}
+
+#[allow(clippy::cast_precision_loss)]
+impl SensorFilter {
+    fn target_pwm(&mut self, current: f32, temp: &Temperature) -> u8 {
+        let smoothed = self.smooth(current);
+
+        match self.reference_temp {
+            Some(reference)
+                if smoothed - reference < self.hysteresis_up
+                    && reference - smoothed < self.hysteresis_down =>
+            {
+                trace!(
+                    "temperature '{}' {smoothed:.1}°C is within hysteresis of {reference:.1}°C",
+                    self.key
+                );
+            }
+            _ => {
+                self.reference_temp = Some(smoothed);
+                self.target_pwm = self.curve.pwm_at_temp(Temperature {
+                    current: Some(smoothed),
+                    crit: temp.crit,
+                    crit_hyst: temp.crit_hyst,
+                });
+            }
+        }
+
+        self.target_pwm
+    }
+
+    fn smooth(&mut self, current: f32) -> f32 {
+        let smoothed = match self.smoothing {
+            None => current,
+            Some(TemperatureSmoothing::Ema { alpha }) => match self.smoothed_temp {
+                Some(previous) => alpha * current + (1.0 - alpha) * previous,
+                None => current,
+            },
+            Some(TemperatureSmoothing::MovingAverage { samples }) => {
+                self.samples.push_back(current);
+                while self.samples.len() > samples.max(1) {
+                    self.samples.pop_front();
+                }
+                self.samples.iter().sum::<f32>() / self.samples.len() as f32
+            }
+        };
+        self.smoothed_temp = Some(smoothed);
+        smoothed
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{FanCurve, PmfwCurve};
+    use super::{
+        apply_speed_offset, spawn_curve_fan_control, CurveFanControl, CurveFilter, FanBackend,
+        FanCurve, PmfwCurve,
+    };
+    use crate::config::{FanControlSettings, FanSafetySettings};
     use amdgpu_sysfs::{gpu_handle::fan_control::FanCurveRanges, hw_mon::Temperature};
+    use anyhow::anyhow;
+    use indexmap::IndexMap;
+    use lact_schema::{
+        FanAlert, FanControlMode, FanOverride, SensorCombination, SensorCurve, TemperatureSmoothing,
+    };
+    use std::{
+        cell::{Cell, RefCell},
+        collections::HashMap,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+    use tokio::task::LocalSet;
 
     fn simple_pwm(temp: f32) -> u8 {
         let curve = FanCurve([(0, 0.0), (100, 1.0)].into());
@@ -201,4 +785,481 @@ mod tests {
         let expected_points = [(40, 20), (50, 35), (60, 50), (70, 75), (80, 100)];
         assert_eq!(&expected_points, pmfw_curve.points.as_ref());
     }
+
+    fn sensor_temp(current: f32) -> Temperature {
+        Temperature {
+            current: Some(current),
+            crit: Some(150.0),
+            crit_hyst: Some(-100.0),
+        }
+    }
+
+    fn filter_temp(current: f32) -> HashMap<String, Temperature> {
+        HashMap::from([("edge".to_owned(), sensor_temp(current))])
+    }
+
+    fn linear_filter(settings: FanControlSettings) -> CurveFilter {
+        CurveFilter::new(&FanControlSettings {
+            curve: FanCurve([(0, 0.0), (100, 1.0)].into()),
+            ..settings
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn filter_ema_smoothing() {
+        let mut filter = linear_filter(FanControlSettings {
+            temperature_smoothing: Some(TemperatureSmoothing::Ema { alpha: 0.5 }),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert_eq!(Some(102), filter.next_pwm(&filter_temp(40.0), now).unwrap());
+        // Smoothed temperature is 50 instead of 60
+        assert_eq!(Some(127), filter.next_pwm(&filter_temp(60.0), now).unwrap());
+    }
+
+    #[test]
+    fn filter_moving_average() {
+        let mut filter = linear_filter(FanControlSettings {
+            temperature_smoothing: Some(TemperatureSmoothing::MovingAverage { samples: 2 }),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        filter.next_pwm(&filter_temp(20.0), now).unwrap();
+        filter.next_pwm(&filter_temp(40.0), now).unwrap();
+        // Average of 40 and 60, the first reading is out of the window
+        assert_eq!(Some(127), filter.next_pwm(&filter_temp(60.0), now).unwrap());
+    }
+
+    #[test]
+    fn filter_hysteresis() {
+        let mut filter = linear_filter(FanControlSettings {
+            hysteresis_up: Some(2),
+            hysteresis_down: Some(5),
+            ..Default::default()
+        });
+        let now = Instant::now();
+        assert_eq!(Some(127), filter.next_pwm(&filter_temp(50.0), now).unwrap());
+        assert_eq!(Some(127), filter.next_pwm(&filter_temp(51.0), now).unwrap());
+        assert_eq!(Some(132), filter.next_pwm(&filter_temp(52.0), now).unwrap());
+        assert_eq!(Some(132), filter.next_pwm(&filter_temp(48.0), now).unwrap());
+        assert_eq!(Some(119), filter.next_pwm(&filter_temp(47.0), now).unwrap());
+    }
+
+    #[test]
+    fn filter_slew_rate() {
+        let mut filter = linear_filter(FanControlSettings {
+            max_pwm_slew_rate: Some(20),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert_eq!(Some(0), filter.next_pwm(&filter_temp(0.0), start).unwrap());
+        assert_eq!(
+            Some(10),
+            filter
+                .next_pwm(&filter_temp(100.0), start + Duration::from_millis(500))
+                .unwrap()
+        );
+        assert_eq!(
+            None,
+            filter
+                .next_pwm(&filter_temp(100.0), start + Duration::from_millis(510))
+                .unwrap()
+        );
+        assert_eq!(
+            Some(30),
+            filter
+                .next_pwm(&filter_temp(100.0), start + Duration::from_millis(1500))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn filter_spindown_delay() {
+        let mut filter = linear_filter(FanControlSettings {
+            spindown_delay_ms: Some(1000),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        assert_eq!(
+            Some(127),
+            filter.next_pwm(&filter_temp(50.0), start).unwrap()
+        );
+        assert_eq!(
+            None,
+            filter
+                .next_pwm(&filter_temp(40.0), start + Duration::from_millis(500))
+                .unwrap()
+        );
+        assert_eq!(
+            Some(102),
+            filter
+                .next_pwm(&filter_temp(40.0), start + Duration::from_millis(1000))
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn filter_crit_bypasses_slew_rate() {
+        let mut filter = linear_filter(FanControlSettings {
+            max_pwm_slew_rate: Some(1),
+            ..Default::default()
+        });
+        let start = Instant::now();
+        filter.next_pwm(&filter_temp(0.0), start).unwrap();
+        let temp = Temperature {
+            current: Some(95.0),
+            crit: Some(90.0),
+            crit_hyst: None,
+        };
+        let temps = HashMap::from([("edge".to_owned(), temp)]);
+        assert_eq!(Some(255), filter.next_pwm(&temps, start).unwrap());
+    }
+
+    fn multi_sensor_filter(combination: SensorCombination) -> CurveFilter {
+        let sensor_curves = [
+            ("edge", [(0, 0.0), (100, 1.0)], 1.0),
+            ("junction", [(50, 0.0), (100, 1.0)], 3.0),
+        ]
+        .into_iter()
+        .map(|(key, curve, weight)| {
+            let curve = SensorCurve {
+                curve: curve.into(),
+                weight,
+            };
+            (key.to_owned(), curve)
+        })
+        .collect();
+        CurveFilter::new(&FanControlSettings {
+            mode: FanControlMode::MultiSensor,
+            sensor_curves,
+            sensor_combination: Some(combination),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn multi_sensor_temps(edge: f32, junction: f32) -> HashMap<String, Temperature> {
+        HashMap::from([
+            ("edge".to_owned(), sensor_temp(edge)),
+            ("junction".to_owned(), sensor_temp(junction)),
+            ("mem".to_owned(), sensor_temp(90.0)),
+        ])
+    }
+
+    #[test]
+    fn multi_sensor_max() {
+        let mut filter = multi_sensor_filter(SensorCombination::Max);
+        let now = Instant::now();
+        // Junction curve gives 102, edge curve gives 127
+        let temps = multi_sensor_temps(50.0, 70.0);
+        assert_eq!(Some(127), filter.next_pwm(&temps, now).unwrap());
+        // Junction curve gives 204, edge curve gives 153
+        let temps = multi_sensor_temps(60.0, 90.0);
+        assert_eq!(Some(204), filter.next_pwm(&temps, now).unwrap());
+    }
+
+    #[test]
+    fn multi_sensor_weighted() {
+        let mut filter = multi_sensor_filter(SensorCombination::Weighted);
+        let temps = multi_sensor_temps(50.0, 70.0);
+        // (127 + 102 * 3) / 4
+        assert_eq!(Some(108), filter.next_pwm(&temps, Instant::now()).unwrap());
+    }
+
+    #[test]
+    fn multi_sensor_missing_sensor() {
+        let filter = multi_sensor_filter(SensorCombination::Max);
+        assert!(filter.check_sensors(&filter_temp(50.0)).is_err());
+    }
+
+    #[derive(Default)]
+    struct FakeBackend {
+        temp: Cell<Option<f32>>,
+        fan_count: u32,
+        fail_set: Cell<bool>,
+        speed: Cell<Option<u32>>,
+        duties: RefCell<Vec<(u32, u8)>>,
+        auto_restored: Cell<bool>,
+    }
+
+    impl FakeBackend {
+        fn new(temp: f32, fan_count: u32) -> Rc<Self> {
+            Rc::new(Self {
+                temp: Cell::new(Some(temp)),
+                fan_count,
+                ..Default::default()
+            })
+        }
+    }
+
+    impl FanBackend for Rc<FakeBackend> {
+        fn read_temperatures(&self) -> anyhow::Result<HashMap<String, Temperature>> {
+            let current = self.temp.get().ok_or_else(|| anyhow!("sensor error"))?;
+            Ok(filter_temp(current))
+        }
+
+        fn fan_count(&self) -> anyhow::Result<u32> {
+            Ok(self.fan_count)
+        }
+
+        fn set_duty(&self, fan: u32, pwm: u8) -> anyhow::Result<()> {
+            if self.fail_set.get() {
+                return Err(anyhow!("write error"));
+            }
+            self.duties.borrow_mut().push((fan, pwm));
+            Ok(())
+        }
+
+        fn fan_speed(&self, _fan: u32) -> anyhow::Result<Option<u32>> {
+            Ok(self.speed.get())
+        }
+
+        fn restore_auto(&self) -> anyhow::Result<()> {
+            self.auto_restored.set(true);
+            Ok(())
+        }
+    }
+
+    fn linear_control(backend: &Rc<FakeBackend>) -> CurveFanControl<Rc<FakeBackend>> {
+        let settings = FanControlSettings {
+            curve: FanCurve([(0, 0.0), (100, 1.0)].into()),
+            ..Default::default()
+        };
+        CurveFanControl::new(backend.clone(), &settings).unwrap()
+    }
+
+    #[test]
+    fn control_sets_all_fans() {
+        let backend = FakeBackend::new(50.0, 2);
+        let mut control = linear_control(&backend);
+        assert!(control.tick(Instant::now()));
+        assert_eq!(vec![(0, 127), (1, 127)], *backend.duties.borrow());
+    }
+
+    #[test]
+    fn control_fan_overrides() {
+        let backend = FakeBackend::new(50.0, 3);
+        let settings = FanControlSettings {
+            curve: FanCurve([(0, 0.0), (100, 1.0)].into()),
+            fan_overrides: IndexMap::from([
+                (
+                    1,
+                    FanOverride {
+                        curve: None,
+                        speed_offset: Some(-0.2),
+                    },
+                ),
+                (
+                    2,
+                    FanOverride {
+                        curve: Some([(50, 1.0)].into()),
+                        speed_offset: None,
+                    },
+                ),
+            ]),
+            ..Default::default()
+        };
+        let mut control = CurveFanControl::new(backend.clone(), &settings).unwrap();
+        assert!(control.tick(Instant::now()));
+        assert_eq!(vec![(0, 127), (1, 76), (2, 255)], *backend.duties.borrow());
+    }
+
+    #[test]
+    fn speed_offsets() {
+        assert_eq!(153, apply_speed_offset(127, 0.1));
+        assert_eq!(0, apply_speed_offset(10, -0.5));
+        assert_eq!(255, apply_speed_offset(250, 0.5));
+        assert_eq!(255, apply_speed_offset(255, -0.5));
+    }
+
+    #[test]
+    fn control_requires_fans() {
+        let backend = FakeBackend::new(50.0, 0);
+        let result = CurveFanControl::new(backend, &FanControlSettings::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn control_tolerates_transient_errors() {
+        let backend = FakeBackend::new(50.0, 1);
+        let mut control = linear_control(&backend);
+        assert!(control.tick(Instant::now()));
+
+        backend.temp.set(None);
+        assert!(control.tick(Instant::now()));
+
+        backend.temp.set(Some(60.0));
+        backend.fail_set.set(true);
+        assert!(control.tick(Instant::now()));
+
+        backend.fail_set.set(false);
+        assert!(control.tick(Instant::now()));
+        assert_eq!(vec![(0, 127), (0, 153)], *backend.duties.borrow());
+    }
+
+    #[test]
+    fn control_stops_when_unavailable() {
+        let backend = FakeBackend::new(50.0, 1);
+        backend.fail_set.set(true);
+        let mut control = linear_control(&backend);
+        assert!(!control.tick(Instant::now()));
+    }
+
+    fn safety_control(
+        backend: &Rc<FakeBackend>,
+        safety: FanSafetySettings,
+    ) -> CurveFanControl<Rc<FakeBackend>> {
+        let settings = FanControlSettings {
+            curve: FanCurve([(0, 0.0), (100, 1.0)].into()),
+            safety: Some(safety),
+            ..Default::default()
+        };
+        CurveFanControl::new(backend.clone(), &settings).unwrap()
+    }
+
+    #[test]
+    fn control_detects_stalled_fan() {
+        let backend = FakeBackend::new(60.0, 1);
+        backend.speed.set(Some(0));
+        let mut control = safety_control(
+            &backend,
+            FanSafetySettings {
+                stall_timeout_secs: 5,
+                ..Default::default()
+            },
+        );
+
+        let start = Instant::now();
+        assert!(control.tick(start));
+        assert!(control.tick(start + Duration::from_secs(4)));
+        assert!(!control.tick(start + Duration::from_secs(5)));
+        assert_eq!(
+            Some(FanAlert::Stall {
+                fan: 0,
+                temperature: 60.0
+            }),
+            *control.alert.borrow()
+        );
+    }
+
+    #[test]
+    fn control_ignores_stopped_fan_when_cool() {
+        let backend = FakeBackend::new(40.0, 1);
+        backend.speed.set(Some(0));
+        let mut control = safety_control(
+            &backend,
+            FanSafetySettings {
+                stall_timeout_secs: 5,
+                ..Default::default()
+            },
+        );
+
+        let start = Instant::now();
+        assert!(control.tick(start));
+        assert!(control.tick(start + Duration::from_secs(10)));
+
+        backend.temp.set(Some(60.0));
+        backend.speed.set(Some(1500));
+        assert!(control.tick(start + Duration::from_secs(20)));
+        assert!(control.alert.borrow().is_none());
+    }
+
+    #[test]
+    fn control_ignores_stopped_fan_below_min_speed() {
+        let backend = FakeBackend::new(60.0, 1);
+        backend.speed.set(Some(0));
+        let settings = FanControlSettings {
+            curve: FanCurve([(0, 0.0), (100, 0.2)].into()),
+            safety: Some(FanSafetySettings {
+                stall_timeout_secs: 5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut control = CurveFanControl::new(backend.clone(), &settings).unwrap();
+
+        let start = Instant::now();
+        assert!(control.tick(start));
+        assert!(control.tick(start + Duration::from_secs(10)));
+        assert!(control.alert.borrow().is_none());
+    }
+
+    #[test]
+    fn static_control_detects_stalled_fan() {
+        let backend = FakeBackend::new(60.0, 2);
+        backend.speed.set(Some(0));
+        let settings = FanControlSettings {
+            mode: FanControlMode::Static,
+            static_speed: 0.5,
+            safety: Some(FanSafetySettings {
+                stall_timeout_secs: 5,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut control = CurveFanControl::new(backend.clone(), &settings).unwrap();
+
+        let start = Instant::now();
+        assert!(control.tick(start));
+        assert!(control.tick(start + Duration::from_secs(4)));
+        // The static speed is only written once
+        assert_eq!(vec![(0, 127), (1, 127)], *backend.duties.borrow());
+
+        assert!(!control.tick(start + Duration::from_secs(5)));
+        assert_eq!(
+            Some(FanAlert::Stall {
+                fan: 0,
+                temperature: 60.0
+            }),
+            *control.alert.borrow()
+        );
+    }
+
+    #[test]
+    fn control_detects_temperature_runaway() {
+        let backend = FakeBackend::new(95.0, 1);
+        let mut control = safety_control(
+            &backend,
+            FanSafetySettings {
+                runaway_temperature: Some(90.0),
+                runaway_timeout_secs: 5,
+                ..Default::default()
+            },
+        );
+
+        let start = Instant::now();
+        assert!(control.tick(start));
+
+        // Dropping below the limit resets the timer
+        backend.temp.set(Some(85.0));
+        assert!(control.tick(start + Duration::from_secs(3)));
+        backend.temp.set(Some(95.0));
+        assert!(control.tick(start + Duration::from_secs(6)));
+        assert!(!control.tick(start + Duration::from_secs(11)));
+        assert_eq!(
+            Some(FanAlert::TemperatureRunaway {
+                sensor: "edge".to_owned(),
+                temperature: 95.0
+            }),
+            *control.alert.borrow()
+        );
+    }
+
+    #[tokio::test]
+    async fn control_task_restores_auto_on_failure() {
+        LocalSet::new()
+            .run_until(async {
+                let backend = FakeBackend::new(50.0, 1);
+                backend.fail_set.set(true);
+                let settings = FanControlSettings {
+                    interval_ms: 1,
+                    ..Default::default()
+                };
+                let handle = spawn_curve_fan_control(backend.clone(), &settings).unwrap();
+                handle.handle.await.unwrap();
+                assert!(backend.auto_restored.get());
+            })
+            .await;
+    }
 }
@@ -1,6 +1,10 @@
 mod drm;
 
-use super::{CommonControllerInfo, GpuController};
+use super::{
+    fan_control::{spawn_curve_fan_control, FanBackend},
+    fan_control_alert, is_changed, CommonControllerInfo, DriftedSetting, FanControlHandle,
+    GpuController, POWER_CAP_DRIFT_TOLERANCE,
+};
 use crate::{
     bindings::intel::{
         drm_i915_gem_memory_class_I915_MEMORY_CLASS_DEVICE,
@@ -15,10 +19,10 @@ use futures::future::LocalBoxFuture;
 use lact_schema::{
     ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo, DeviceStats, DrmInfo, DrmMemoryInfo,
     FanStats, IntelClocksTable, IntelDrmInfo, LinkInfo, PowerState, PowerStates, PowerStats,
-    VoltageStats, VramStats,
+    SingleFanStats, VoltageStats, VramStats,
 };
 use std::{
-    cell::Cell,
+    cell::{Cell, RefCell},
     collections::{BTreeMap, HashMap},
     fmt::{self, Display},
     fs,
@@ -31,6 +35,10 @@ use std::{
 };
 use tracing::{debug, error, info, trace, warn};
 
+/// Values of the hwmon `pwmN_enable` files
+const PWM_ENABLE_MANUAL: &str = "1";
+const PWM_ENABLE_AUTO: &str = "2";
+
 #[derive(Clone, Copy)]
 enum DriverType {
     I915,
@@ -47,6 +55,7 @@ pub struct IntelGpuController {
     last_gpu_busy: Cell<Option<(Instant, u64)>>,
     last_energy_value: Cell<Option<(Instant, u64)>>,
     initial_power_cap: Option<f64>,
+    fan_control_handle: RefCell<Option<FanControlHandle>>,
 }
 
 impl IntelGpuController {
@@ -118,6 +127,7 @@ impl IntelGpuController {
             last_gpu_busy: Cell::new(None),
             last_energy_value: Cell::new(None),
             initial_power_cap: None,
+            fan_control_handle: RefCell::new(None),
         };
 
         let stats = controller.get_stats(None);
@@ -322,6 +332,128 @@ impl IntelGpuController {
             .collect()
     }
 
+    fn fan_backend(&self) -> anyhow::Result<IntelFanBackend> {
+        let hwmon_path = self
+            .hwmon_path
+            .clone()
+            .context("Fan control is not supported on this GPU: no hwmon available")?;
+
+        let channels = hwmon_fan_channels(&hwmon_path);
+        if channels.is_empty() {
+            return Err(anyhow!(
+                "Fan control is not supported on this GPU: the driver does not expose pwm controls"
+            ));
+        }
+
+        let temp_path = fs::read_dir(&hwmon_path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .find(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("temp") && name.ends_with("_input"))
+            });
+
+        Ok(IntelFanBackend {
+            hwmon_path,
+            channels,
+            temp_path,
+        })
+    }
+
+    /// Applies the given config. When the previously applied config is given,
+    /// only the settings that differ from it are written.
+    async fn apply_settings(
+        &self,
+        config: &config::Gpu,
+        previous: Option<&config::Gpu>,
+    ) -> anyhow::Result<()> {
+        if is_changed(previous, config, |gpu| &gpu.clocks_configuration) {
+            if let Some(max_clock) = config.clocks_configuration.max_core_clock {
+                self.write_freq(FrequencyType::Max, max_clock)
+                    .context("Could not set max clock")?;
+            }
+
+            if let Some(min_clock) = config.clocks_configuration.min_core_clock {
+                self.write_freq(FrequencyType::Min, min_clock)
+                    .context("Could not set min clock")?;
+            }
+        }
+
+        if let Some(cap) = config
+            .power_cap
+            .filter(|_| is_changed(previous, config, |gpu| &gpu.power_cap))
+        {
+            self.write_power_cap(cap)?;
+        }
+
+        // Restarting the fan control task resets its state, such as the smoothing and the stall timers
+        if is_changed(previous, config, |gpu| &gpu.fan_control_enabled)
+            || is_changed(previous, config, |gpu| &gpu.fan_control_settings)
+        {
+            self.apply_fan_control(config).await?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn write_power_cap(&self, cap: f64) -> anyhow::Result<()> {
+        self.write_hwmon_file("power", "_max", &((cap * 1_000_000.0) as u64).to_string())
+            .context("Could not set power cap")
+    }
+
+    async fn stop_fan_control(&self) -> anyhow::Result<()> {
+        let maybe_notify = self
+            .fan_control_handle
+            .try_borrow_mut()
+            .map_err(|err| anyhow!("Lock error: {err}"))?
+            .take();
+        if let Some(FanControlHandle { notify, handle, .. }) = maybe_notify {
+            notify.notify_one();
+            handle.await?;
+        }
+        Ok(())
+    }
+
+    async fn apply_fan_control(&self, config: &config::Gpu) -> anyhow::Result<()> {
+        if config.fan_control_enabled {
+            let settings = config
+                .fan_control_settings
+                .as_ref()
+                .context("Fan control enabled with no settings")?;
+            let backend = self.fan_backend()?;
+
+            self.stop_fan_control().await?;
+            backend.set_enable(PWM_ENABLE_MANUAL)?;
+
+            // Static speeds are also held by the task, so that the fans are monitored
+            let handle = spawn_curve_fan_control(backend, settings)?;
+            *self
+                .fan_control_handle
+                .try_borrow_mut()
+                .map_err(|err| anyhow!("Lock error: {err}"))? = Some(handle);
+        } else {
+            self.stop_fan_control().await?;
+
+            if let Ok(backend) = self.fan_backend() {
+                for channel in &backend.channels {
+                    let enable_path = backend.enable_path(*channel);
+                    let current = fs::read_to_string(&enable_path).unwrap_or_default();
+                    if current.trim() == PWM_ENABLE_MANUAL {
+                        fs::write(&enable_path, PWM_ENABLE_AUTO).with_context(|| {
+                            format!("Could not write to '{}'", enable_path.display())
+                        })?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn read_freq(&self, freq: FrequencyType) -> Option<u64> {
         self.freq_path(freq).and_then(|path| self.read_file(&path))
     }
@@ -529,32 +661,45 @@ impl GpuController for IntelGpuController {
         }
     }
 
-    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
     fn apply_config<'a>(
         &'a self,
         config: &'a config::Gpu,
     ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
-        Box::pin(async {
-            if let Some(max_clock) = config.clocks_configuration.max_core_clock {
-                self.write_freq(FrequencyType::Max, max_clock)
-                    .context("Could not set max clock")?;
-            }
+        Box::pin(self.apply_settings(config, None))
+    }
 
-            if let Some(min_clock) = config.clocks_configuration.min_core_clock {
-                self.write_freq(FrequencyType::Min, min_clock)
-                    .context("Could not set min clock")?;
-            }
+    fn apply_config_changes<'a>(
+        &'a self,
+        previous: &'a config::Gpu,
+        config: &'a config::Gpu,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.apply_settings(config, Some(previous)))
+    }
 
-            if let Some(cap) = config.power_cap {
-                self.write_hwmon_file("power", "_max", &((cap * 1_000_000.0) as u64).to_string())
-                    .context("Could not set power cap")?;
+    fn reapply_settings<'a>(
+        &'a self,
+        config: &'a config::Gpu,
+        settings: &'a [DriftedSetting],
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for setting in settings {
+                match setting {
+                    DriftedSetting::PowerCap => {
+                        if let Some(cap) = config.power_cap {
+                            self.write_power_cap(cap)?;
+                        }
+                    }
+                    DriftedSetting::FanControl => self.apply_fan_control(config).await?,
+                    DriftedSetting::PerformanceLevel | DriftedSetting::PowerProfileMode => {
+                        return Err(anyhow!("Reapplying {setting} is not supported"));
+                    }
+                }
             }
-
             Ok(())
         })
     }
 
-    fn get_stats(&self, _gpu_config: Option<&config::Gpu>) -> DeviceStats {
+    fn get_stats(&self, gpu_config: Option<&config::Gpu>) -> DeviceStats {
         let current_gfxclk = self.read_freq(FrequencyType::Cur);
         let gpu_clockspeed = self
             .read_freq(FrequencyType::Act)
@@ -590,8 +735,36 @@ impl GpuController for IntelGpuController {
             northbridge: None,
         };
 
+        let fan_settings = gpu_config.and_then(|config| config.fan_control_settings.as_ref());
+        let fans = self
+            .hwmon_path
+            .as_deref()
+            .map(hwmon_fan_stats)
+            .unwrap_or_default();
         let fan = FanStats {
+            control_enabled: gpu_config.is_some_and(|config| config.fan_control_enabled),
+            control_mode: fan_settings.map(|settings| settings.mode),
+            static_speed: fan_settings.map(|settings| settings.static_speed),
+            curve: fan_settings.map(|settings| settings.curve.0.clone()),
+            spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
+            change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
+            temperature_smoothing: fan_settings.and_then(|settings| settings.temperature_smoothing),
+            hysteresis_up: fan_settings.and_then(|settings| settings.hysteresis_up),
+            hysteresis_down: fan_settings.and_then(|settings| settings.hysteresis_down),
+            max_pwm_slew_rate: fan_settings.and_then(|settings| settings.max_pwm_slew_rate),
+            sensor_curves: fan_settings
+                .map(|settings| settings.sensor_curves.clone())
+                .unwrap_or_default(),
+            sensor_combination: fan_settings.and_then(|settings| settings.sensor_combination),
+            fan_overrides: fan_settings
+                .map(|settings| settings.fan_overrides.clone())
+                .unwrap_or_default(),
+            pwm_current: fans.first().and_then(|fan| fan.pwm_current),
             speed_current: self.read_hwmon_file("fan", "_input"),
+            speed_max: self.read_hwmon_file("fan", "_max"),
+            speed_min: self.read_hwmon_file("fan", "_min"),
+            fans,
+            alert: fan_control_alert(&self.fan_control_handle),
             ..Default::default()
         };
 
@@ -687,11 +860,166 @@ impl GpuController for IntelGpuController {
         Err(anyhow!("Not supported"))
     }
 
+    fn get_drifted_settings(&self, config: &config::Gpu) -> Vec<DriftedSetting> {
+        let mut drifted = Vec::new();
+
+        if let Some(cap) = config.power_cap {
+            if self
+                .read_hwmon_file::<f64>("power", "_max")
+                .is_some_and(|current| {
+                    (current / 1_000_000.0 - cap).abs() > POWER_CAP_DRIFT_TOLERANCE
+                })
+            {
+                drifted.push(DriftedSetting::PowerCap);
+            }
+        }
+
+        // An active fan alert means the control task has handed the fan back to the firmware
+        if config.fan_control_enabled && fan_control_alert(&self.fan_control_handle).is_none() {
+            if let Ok(backend) = self.fan_backend() {
+                let is_manual = backend.channels.iter().all(|channel| {
+                    fs::read_to_string(backend.enable_path(*channel))
+                        .is_ok_and(|value| value.trim() == PWM_ENABLE_MANUAL)
+                });
+                if !is_manual {
+                    drifted.push(DriftedSetting::FanControl);
+                }
+            }
+        }
+
+        drifted
+    }
+
     fn vbios_dump(&self) -> anyhow::Result<Vec<u8>> {
         Err(anyhow!("Not supported"))
     }
 }
 
+/// Fan control through the hwmon `pwmN` and `pwmN_enable` files
+struct IntelFanBackend {
+    hwmon_path: PathBuf,
+    channels: Vec<u32>,
+    temp_path: Option<PathBuf>,
+}
+
+impl IntelFanBackend {
+    fn enable_path(&self, channel: u32) -> PathBuf {
+        self.hwmon_path.join(format!("pwm{channel}_enable"))
+    }
+
+    fn set_enable(&self, value: &str) -> anyhow::Result<()> {
+        for channel in &self.channels {
+            let path = self.enable_path(*channel);
+            fs::write(&path, value)
+                .with_context(|| format!("Could not write to '{}'", path.display()))?;
+        }
+        Ok(())
+    }
+
+    /// The hwmon channel of the given fan index
+    fn channel(&self, fan: u32) -> anyhow::Result<u32> {
+        usize::try_from(fan)
+            .ok()
+            .and_then(|index| self.channels.get(index))
+            .copied()
+            .with_context(|| format!("Fan {fan} does not exist"))
+    }
+}
+
+impl FanBackend for IntelFanBackend {
+    fn read_temperatures(&self) -> anyhow::Result<HashMap<String, Temperature>> {
+        let path = self
+            .temp_path
+            .as_ref()
+            .context("GPU has no temperature reporting")?;
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Could not read '{}'", path.display()))?;
+        let temp: f32 = contents
+            .trim()
+            .parse()
+            .with_context(|| format!("Invalid temperature value '{}'", contents.trim()))?;
+
+        let temperature = Temperature {
+            current: Some(temp / 1000.0),
+            crit: None,
+            crit_hyst: None,
+        };
+        Ok(HashMap::from([("gpu".to_owned(), temperature)]))
+    }
+
+    fn fan_count(&self) -> anyhow::Result<u32> {
+        u32::try_from(self.channels.len()).context("Invalid fan count")
+    }
+
+    fn set_duty(&self, fan: u32, pwm: u8) -> anyhow::Result<()> {
+        let path = self.hwmon_path.join(format!("pwm{}", self.channel(fan)?));
+        fs::write(&path, pwm.to_string())
+            .with_context(|| format!("Could not write to '{}'", path.display()))
+    }
+
+    fn fan_speed(&self, fan: u32) -> anyhow::Result<Option<u32>> {
+        let path = self
+            .hwmon_path
+            .join(format!("fan{}_input", self.channel(fan)?));
+        match fs::read_to_string(&path) {
+            Ok(contents) => {
+                let speed = contents
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid fan speed value '{}'", contents.trim()))?;
+                Ok(Some(speed))
+            }
+            Err(_) => Ok(None),
+        }
+    }
+
+    fn restore_auto(&self) -> anyhow::Result<()> {
+        self.set_enable(PWM_ENABLE_AUTO)
+    }
+}
+
+/// Numbers of the hwmon `pwmN` channels that can be switched to manual control
+fn hwmon_fan_channels(hwmon_path: &Path) -> Vec<u32> {
+    let mut channels: Vec<u32> = fs::read_dir(hwmon_path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let channel = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("pwm")?
+                .parse::<u32>()
+                .ok()?;
+            hwmon_path
+                .join(format!("pwm{channel}_enable"))
+                .exists()
+                .then_some(channel)
+        })
+        .collect();
+    channels.sort_unstable();
+    channels
+}
+
+/// Readings of the fans on the hwmon `pwmN` channels, indexed the same way as in `IntelFanBackend`
+fn hwmon_fan_stats(hwmon_path: &Path) -> Vec<SingleFanStats> {
+    let read_value = |name: String| {
+        fs::read_to_string(hwmon_path.join(name))
+            .ok()
+            .and_then(|contents| contents.trim().parse().ok())
+    };
+
+    (0..)
+        .zip(hwmon_fan_channels(hwmon_path))
+        .map(|(index, channel)| SingleFanStats {
+            index,
+            speed_current: read_value(format!("fan{channel}_input")),
+            pwm_current: read_value(format!("pwm{channel}")),
+            pwm_target: None,
+        })
+        .collect()
+}
+
 #[derive(Clone, Copy)]
 enum FrequencyType {
     Cur,
@@ -725,3 +1053,66 @@ struct IntelVramInfo {
     used: u64,
     mem_info: DrmMemoryInfo,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{hwmon_fan_channels, hwmon_fan_stats, IntelFanBackend, PWM_ENABLE_AUTO};
+    use crate::server::gpu_controller::fan_control::FanBackend;
+    use lact_schema::SingleFanStats;
+    use std::{env, fs, path::PathBuf};
+
+    #[test]
+    fn no_fan_channels() {
+        let hwmon_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests/data/intel/a380-i915/card1/device/hwmon/hwmon1");
+        assert!(hwmon_fan_channels(&hwmon_path).is_empty());
+    }
+
+    #[test]
+    fn hwmon_fan_backend() {
+        let hwmon_path = env::temp_dir().join(format!("lact-intel-hwmon-{}", std::process::id()));
+        fs::create_dir_all(&hwmon_path).unwrap();
+        for (name, contents) in [
+            ("pwm1", "0"),
+            ("pwm1_enable", "1"),
+            ("pwm2", "0"),
+            ("fan1_input", "1200"),
+            ("temp1_input", "45000"),
+        ] {
+            fs::write(hwmon_path.join(name), contents).unwrap();
+        }
+
+        let channels = hwmon_fan_channels(&hwmon_path);
+        assert_eq!(vec![1], channels);
+
+        let backend = IntelFanBackend {
+            hwmon_path: hwmon_path.clone(),
+            channels,
+            temp_path: Some(hwmon_path.join("temp1_input")),
+        };
+        let temps = backend.read_temperatures().unwrap();
+        assert_eq!(Some(45.0), temps["gpu"].current);
+
+        backend.set_duty(0, 128).unwrap();
+        assert!(backend.set_duty(1, 128).is_err());
+        assert_eq!("128", fs::read_to_string(hwmon_path.join("pwm1")).unwrap());
+        assert_eq!(Some(1200), backend.fan_speed(0).unwrap());
+        assert_eq!(
+            vec![SingleFanStats {
+                index: 0,
+                speed_current: Some(1200),
+                pwm_current: Some(128),
+                pwm_target: None,
+            }],
+            hwmon_fan_stats(&hwmon_path)
+        );
+
+        backend.restore_auto().unwrap();
+        assert_eq!(
+            PWM_ENABLE_AUTO,
+            fs::read_to_string(hwmon_path.join("pwm1_enable")).unwrap()
+        );
+
+        fs::remove_dir_all(hwmon_path).unwrap();
+    }
+}
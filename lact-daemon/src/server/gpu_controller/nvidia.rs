@@ -3,30 +3,27 @@ use crate::{
     server::vulkan::get_vulkan_info,
 };
 
-use super::{fan_control::FanCurve, CommonControllerInfo, FanControlHandle, GpuController};
+use super::{
+    fan_control::{spawn_curve_fan_control, FanBackend},
+    fan_control_alert, is_changed, CommonControllerInfo, DriftedSetting, FanControlHandle,
+    GpuController, POWER_CAP_DRIFT_TOLERANCE,
+};
 use amdgpu_sysfs::{gpu_handle::power_profile_mode::PowerProfileModesTable, hw_mon::Temperature};
 use anyhow::{anyhow, Context};
 use futures::future::LocalBoxFuture;
 use indexmap::IndexMap;
 use lact_schema::{
     ClocksInfo, ClocksTable, ClockspeedStats, DeviceInfo, DeviceStats, DrmInfo, DrmMemoryInfo,
-    FanControlMode, FanStats, IntelDrmInfo, LinkInfo, NvidiaClockOffset, NvidiaClocksTable,
-    PmfwInfo, PowerState, PowerStates, PowerStats, VoltageStats, VramStats,
+    FanStats, IntelDrmInfo, LinkInfo, NvidiaClockOffset, NvidiaClocksTable, PmfwInfo, PmfwOptions,
+    PowerState, PowerStates, PowerStats, SingleFanStats, VoltageStats, VramStats,
 };
 use nvml_wrapper::{
     bitmasks::device::ThrottleReasons,
     enum_wrappers::device::{Clock, PerformanceState, TemperatureSensor, TemperatureThreshold},
     Device, Nvml,
 };
-use std::{
-    cell::RefCell,
-    collections::HashMap,
-    fmt::Write,
-    rc::Rc,
-    time::{Duration, Instant},
-};
-use tokio::{select, sync::Notify, time::sleep};
-use tracing::{debug, error, trace, warn};
+use std::{cell::RefCell, collections::HashMap, fmt::Write, rc::Rc};
+use tracing::{debug, error, warn};
 
 pub struct NvidiaGpuController {
     nvml: Rc<Nvml>,
@@ -60,108 +57,133 @@ impl NvidiaGpuController {
             .expect("Can no longer get device")
     }
 
-    async fn start_curve_fan_control_task(
-        &self,
-        curve: FanCurve,
-        settings: FanControlSettings,
-    ) -> anyhow::Result<()> {
-        // Stop existing task to re-apply new curve
-        self.stop_fan_control().await?;
+    fn apply_power_cap(&self, power_cap: Option<f64>) -> anyhow::Result<()> {
+        let mut device = self.device();
 
-        let device = self.device();
-        device
-            .temperature(TemperatureSensor::Gpu)
-            .context("Could not read temperature")?;
+        if let Some(cap) = power_cap {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let cap = (cap * 1000.0) as u32;
 
-        let fan_count = device.num_fans().context("Could not read fan count")?;
-        if fan_count == 0 {
-            return Err(anyhow!("Device has no fans"));
-        }
+            let current_cap = device
+                .power_management_limit()
+                .context("Could not get current cap")?;
 
-        let mut notify_guard = self
-            .fan_control_handle
-            .try_borrow_mut()
-            .map_err(|err| anyhow!("Lock error: {err}"))?;
+            if current_cap != cap {
+                debug!("setting power cap to {cap}");
+                device
+                    .set_power_management_limit(cap)
+                    .context("Could not set power cap")?;
+            }
+        } else {
+            let current_cap = device.power_management_limit();
+            let default_cap = device.power_management_limit_default();
 
-        let notify = Rc::new(Notify::new());
-        let task_notify = notify.clone();
+            if let (Ok(current_cap), Ok(default_cap)) = (current_cap, default_cap) {
+                if current_cap != default_cap {
+                    debug!("resetting power cap to {default_cap}");
+                    device
+                        .set_power_management_limit(default_cap)
+                        .context("Could not reset power cap")?;
+                }
+            }
+        }
 
-        let nvml = self.nvml.clone();
-        let pci_slot_id = self.common.pci_slot_name.clone();
-        debug!("spawning new fan control task");
+        Ok(())
+    }
 
-        let handle = tokio::task::spawn_local(async move {
-            let mut device = nvml
-                .device_by_pci_bus_id(pci_slot_id.as_str())
-                .expect("Can no longer get device");
+    /// Applies the given config. When the previously applied config is given,
+    /// only the settings that differ from it are written.
+    #[allow(clippy::cast_possible_wrap)]
+    async fn apply_settings(
+        &self,
+        config: &config::Gpu,
+        previous: Option<&config::Gpu>,
+    ) -> anyhow::Result<()> {
+        if is_changed(previous, config, |gpu| &gpu.power_cap) {
+            self.apply_power_cap(config.power_cap)?;
+        }
 
-            let mut last_pwm = (None, Instant::now());
-            let mut last_temp = 0;
+        let mut device = self.device();
 
-            let interval = Duration::from_millis(settings.interval_ms);
-            let spindown_delay = Duration::from_millis(settings.spindown_delay_ms.unwrap_or(0));
-            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
-            let change_threshold = settings.change_threshold.unwrap_or(0) as i32;
+        // Offsets are left as-is when they were not changed, as rewriting them briefly resets them
+        if is_changed(previous, config, |gpu| &gpu.clocks_configuration) {
+            self.cleanup_clocks()?;
 
-            loop {
-                select! {
-                    () = sleep(interval) => (),
-                    () = task_notify.notified() => break,
-                }
+            for (pstate, offset) in &config.clocks_configuration.gpu_clock_offsets {
+                let pstate = PerformanceState::try_from(*pstate)
+                    .map_err(|_| anyhow!("Invalid pstate '{pstate}'"))?;
+                debug!("applying offset {offset} for GPU pstate {pstate:?}");
+                device
+                    .set_clock_offset(Clock::Graphics, pstate, *offset)
+                    .with_context(|| {
+                        format!("Could not set clock offset {offset} for GPU pstate {pstate:?}")
+                    })?;
 
-                #[allow(clippy::cast_possible_wrap)]
-                let current_temp = device
-                    .temperature(TemperatureSensor::Gpu)
-                    .expect("Could not read temperature") as i32;
+                self.last_applied_offsets
+                    .borrow_mut()
+                    .entry(Clock::Graphics)
+                    .or_default()
+                    .insert(pstate, *offset);
+            }
 
-                if (last_temp - current_temp).abs() < change_threshold {
-                    trace!("temperature changed from {last_temp}°C to {current_temp}°C, which is less than the {change_threshold}°C threshold, skipping speed adjustment");
-                    continue;
-                }
+            for (pstate, offset) in &config.clocks_configuration.mem_clock_offsets {
+                let pstate = PerformanceState::try_from(*pstate)
+                    .map_err(|_| anyhow!("Invalid pstate '{pstate}'"))?;
+                debug!("applying offset {offset} for VRAM pstate {pstate:?}");
+                device
+                    .set_clock_offset(Clock::Memory, pstate, *offset)
+                    .with_context(|| {
+                        format!("Could not set clock offset {offset} for VRAM pstate {pstate:?}")
+                    })?;
 
-                let target_pwm = curve.pwm_at_temp(Temperature {
-                    #[allow(clippy::cast_precision_loss)]
-                    current: Some(current_temp as f32),
-                    crit: None,
-                    crit_hyst: None,
-                });
-                let now = Instant::now();
-
-                if let (Some(previous_pwm), previous_timestamp) = last_pwm {
-                    let diff = now - previous_timestamp;
-                    if target_pwm < previous_pwm && diff < spindown_delay {
-                        trace!(
-                            "delaying fan spindown ({}ms left)",
-                            (spindown_delay - diff).as_millis()
-                        );
-                        continue;
-                    }
-                }
+                self.last_applied_offsets
+                    .borrow_mut()
+                    .entry(Clock::Memory)
+                    .or_default()
+                    .insert(pstate, *offset);
+            }
+        }
 
-                last_pwm = (Some(target_pwm), now);
-                last_temp = current_temp;
+        // Restarting the fan control task resets its state, such as the smoothing and the stall timers
+        let write_fan_control = is_changed(previous, config, |gpu| &gpu.fan_control_enabled)
+            || is_changed(previous, config, |gpu| &gpu.fan_control_settings);
+        if !write_fan_control {
+            return Ok(());
+        }
+
+        if config.fan_control_enabled {
+            let settings = config
+                .fan_control_settings
+                .as_ref()
+                .context("Fan control enabled with no settings")?;
+            // Static speeds are also held by the task, so that the fans are monitored
+            self.start_curve_fan_control_task(settings).await?;
+        } else {
+            self.stop_fan_control()
+                .await
+                .context("Could not reset fan control")?;
+        }
 
-                trace!("fan control tick: setting pwm to {target_pwm}");
+        Ok(())
+    }
 
-                for fan in 0..fan_count {
-                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                    if let Err(err) =
-                        device.set_fan_speed(fan, (f64::from(target_pwm) / 2.5) as u32)
-                    {
-                        error!("could not set fan speed: {err}, disabling fan control");
-                        break;
-                    }
-                }
-            }
-            debug!("exited fan control task");
-        });
+    async fn start_curve_fan_control_task(
+        &self,
+        settings: &FanControlSettings,
+    ) -> anyhow::Result<()> {
+        // Stop existing task to re-apply new curve
+        self.stop_fan_control().await?;
 
-        *notify_guard = Some((notify, handle));
+        let mut notify_guard = self
+            .fan_control_handle
+            .try_borrow_mut()
+            .map_err(|err| anyhow!("Lock error: {err}"))?;
 
-        debug!(
-            "started fan control with interval {}ms",
-            settings.interval_ms
-        );
+        let backend = NvmlFanBackend {
+            nvml: self.nvml.clone(),
+            pci_slot_id: self.common.pci_slot_name.clone(),
+        };
+        *notify_guard = Some(spawn_curve_fan_control(backend, settings)?);
 
         Ok(())
     }
@@ -174,7 +196,7 @@ impl NvidiaGpuController {
             .try_borrow_mut()
             .map_err(|err| anyhow!("Lock error: {err}"))?
             .take();
-        if let Some((notify, handle)) = maybe_notify {
+        if let Some(FanControlHandle { notify, handle, .. }) = maybe_notify {
             notify.notify_one();
             handle.await?;
             fail_on_error = true;
@@ -244,6 +266,70 @@ impl NvidiaGpuController {
     }
 }
 
+struct NvmlFanBackend {
+    nvml: Rc<Nvml>,
+    pci_slot_id: String,
+}
+
+impl NvmlFanBackend {
+    fn device(&self) -> anyhow::Result<Device<'_>> {
+        self.nvml
+            .device_by_pci_bus_id(self.pci_slot_id.as_str())
+            .context("Can no longer get device")
+    }
+}
+
+impl FanBackend for NvmlFanBackend {
+    fn read_temperatures(&self) -> anyhow::Result<HashMap<String, Temperature>> {
+        let device = self.device()?;
+        let temp = device
+            .temperature(TemperatureSensor::Gpu)
+            .context("Could not read temperature")?;
+        #[allow(clippy::cast_precision_loss)]
+        let crit = device
+            .temperature_threshold(TemperatureThreshold::Shutdown)
+            .map(|value| value as f32)
+            .ok();
+
+        #[allow(clippy::cast_precision_loss)]
+        let temp = Temperature {
+            current: Some(temp as f32),
+            crit,
+            crit_hyst: None,
+        };
+        Ok(HashMap::from([("GPU".to_owned(), temp)]))
+    }
+
+    fn fan_count(&self) -> anyhow::Result<u32> {
+        self.device()?
+            .num_fans()
+            .context("Could not read fan count")
+    }
+
+    fn set_duty(&self, fan: u32, pwm: u8) -> anyhow::Result<()> {
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let speed = (f64::from(pwm) / 2.55) as u32;
+        self.device()?
+            .set_fan_speed(fan, speed)
+            .with_context(|| format!("Could not set speed of fan {fan}"))
+    }
+
+    fn fan_speed(&self, fan: u32) -> anyhow::Result<Option<u32>> {
+        Ok(self.device()?.fan_speed_rpm(fan).ok())
+    }
+
+    fn restore_auto(&self) -> anyhow::Result<()> {
+        let mut device = self.device()?;
+        let fan_count = device.num_fans().context("Could not get fan count")?;
+        for fan in 0..fan_count {
+            device
+                .set_default_fan_speed(fan)
+                .context("Could not reset fan control to default")?;
+        }
+        Ok(())
+    }
+}
+
 impl GpuController for NvidiaGpuController {
     fn controller_info(&self) -> &CommonControllerInfo {
         &self.common
@@ -356,14 +442,22 @@ impl GpuController for NvidiaGpuController {
 
         let fan_settings = gpu_config.and_then(|config| config.fan_control_settings.as_ref());
 
-        let pwm_current = if device.num_fans().is_ok_and(|num| num > 0) {
-            device
-                .fan_speed(0)
-                .ok()
-                .map(|value| (f64::from(value) * 2.55) as u8)
-        } else {
-            None
-        };
+        let fan_count = device.num_fans().unwrap_or(0);
+        let fans: Vec<SingleFanStats> = (0..fan_count)
+            .map(|index| SingleFanStats {
+                index,
+                speed_current: device.fan_speed_rpm(index).ok(),
+                pwm_current: device
+                    .fan_speed(index)
+                    .ok()
+                    .map(|value| (f64::from(value) * 2.55) as u8),
+                pwm_target: device
+                    .target_fan_speed(index)
+                    .ok()
+                    .map(|value| (f64::from(value) * 2.55) as u8),
+            })
+            .collect();
+        let pwm_current = fans.first().and_then(|fan| fan.pwm_current);
 
         let vram = device
             .memory_info()
@@ -387,11 +481,26 @@ impl GpuController for NvidiaGpuController {
                 curve: fan_settings.map(|settings| settings.curve.0.clone()),
                 spindown_delay_ms: fan_settings.and_then(|settings| settings.spindown_delay_ms),
                 change_threshold: fan_settings.and_then(|settings| settings.change_threshold),
-                speed_current: None,
+                temperature_smoothing: fan_settings
+                    .and_then(|settings| settings.temperature_smoothing),
+                hysteresis_up: fan_settings.and_then(|settings| settings.hysteresis_up),
+                hysteresis_down: fan_settings.and_then(|settings| settings.hysteresis_down),
+                max_pwm_slew_rate: fan_settings.and_then(|settings| settings.max_pwm_slew_rate),
+                sensor_curves: fan_settings
+                    .map(|settings| settings.sensor_curves.clone())
+                    .unwrap_or_default(),
+                sensor_combination: fan_settings.and_then(|settings| settings.sensor_combination),
+                fan_overrides: fan_settings
+                    .map(|settings| settings.fan_overrides.clone())
+                    .unwrap_or_default(),
+                speed_current: fans.first().and_then(|fan| fan.speed_current),
                 speed_max: None,
                 speed_min: None,
                 pwm_current,
+                fans,
+                alert: fan_control_alert(&self.fan_control_handle),
                 pmfw_info: PmfwInfo::default(),
+                pmfw_options: PmfwOptions::default(),
             },
             power: PowerStats {
                 average: None,
@@ -504,120 +613,61 @@ impl GpuController for NvidiaGpuController {
         Err(anyhow!("Not supported on Nvidia"))
     }
 
+    fn get_drifted_settings(&self, config: &config::Gpu) -> Vec<DriftedSetting> {
+        let mut drifted = Vec::new();
+
+        if let Some(cap) = config.power_cap {
+            if self.device().power_management_limit().is_ok_and(|current| {
+                (f64::from(current) / 1000.0 - cap).abs() > POWER_CAP_DRIFT_TOLERANCE
+            }) {
+                drifted.push(DriftedSetting::PowerCap);
+            }
+        }
+
+        drifted
+    }
+
     fn reset_pmfw_settings(&self) {}
 
     fn vbios_dump(&self) -> anyhow::Result<Vec<u8>> {
         Err(anyhow!("Not supported on Nvidia"))
     }
 
-    #[allow(clippy::cast_possible_wrap)]
     fn apply_config<'a>(
         &'a self,
         config: &'a config::Gpu,
     ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
-        Box::pin(async {
-            let mut device = self.device();
-
-            if let Some(cap) = config.power_cap {
-                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
-                let cap = (cap * 1000.0) as u32;
-
-                let current_cap = device
-                    .power_management_limit()
-                    .context("Could not get current cap")?;
-
-                if current_cap != cap {
-                    debug!("setting power cap to {cap}");
-                    device
-                        .set_power_management_limit(cap)
-                        .context("Could not set power cap")?;
-                }
-            } else {
-                let current_cap = device.power_management_limit();
-                let default_cap = device.power_management_limit_default();
-
-                if let (Ok(current_cap), Ok(default_cap)) = (current_cap, default_cap) {
-                    if current_cap != default_cap {
-                        debug!("resetting power cap to {default_cap}");
-                        device
-                            .set_power_management_limit(default_cap)
-                            .context("Could not reset power cap")?;
-                    }
-                }
-            }
-
-            self.cleanup_clocks()?;
-
-            for (pstate, offset) in &config.clocks_configuration.gpu_clock_offsets {
-                let pstate = PerformanceState::try_from(*pstate)
-                    .map_err(|_| anyhow!("Invalid pstate '{pstate}'"))?;
-                debug!("applying offset {offset} for GPU pstate {pstate:?}");
-                device
-                    .set_clock_offset(Clock::Graphics, pstate, *offset)
-                    .with_context(|| {
-                        format!("Could not set clock offset {offset} for GPU pstate {pstate:?}")
-                    })?;
-
-                self.last_applied_offsets
-                    .borrow_mut()
-                    .entry(Clock::Graphics)
-                    .or_default()
-                    .insert(pstate, *offset);
-            }
-
-            for (pstate, offset) in &config.clocks_configuration.mem_clock_offsets {
-                let pstate = PerformanceState::try_from(*pstate)
-                    .map_err(|_| anyhow!("Invalid pstate '{pstate}'"))?;
-                debug!("applying offset {offset} for VRAM pstate {pstate:?}");
-                device
-                    .set_clock_offset(Clock::Memory, pstate, *offset)
-                    .with_context(|| {
-                        format!("Could not set clock offset {offset} for VRAM pstate {pstate:?}")
-                    })?;
-
-                self.last_applied_offsets
-                    .borrow_mut()
-                    .entry(Clock::Memory)
-                    .or_default()
-                    .insert(pstate, *offset);
-            }
+        Box::pin(self.apply_settings(config, None))
+    }
 
-            if config.fan_control_enabled {
-                let settings = config
-                    .fan_control_settings
-                    .as_ref()
-                    .context("Fan control enabled with no settings")?;
-                match settings.mode {
-                    FanControlMode::Static => {
-                        self.stop_fan_control()
-                            .await
-                            .context("Could not reset fan control")?;
-
-                        #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-                        let speed = (settings.static_speed * 100.0) as u32;
-
-                        let fan_count = device.num_fans().context("Could not get fan count")?;
-                        for fan in 0..fan_count {
-                            device
-                                .set_fan_speed(fan, speed)
-                                .context("Could not reset fan speed to default")?;
-                        }
-                    }
-                    FanControlMode::Curve => {
-                        self.start_curve_fan_control_task(settings.curve.clone(), settings.clone())
-                            .await?;
+    fn reapply_settings<'a>(
+        &'a self,
+        config: &'a config::Gpu,
+        settings: &'a [DriftedSetting],
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(async move {
+            for setting in settings {
+                match setting {
+                    DriftedSetting::PowerCap => self.apply_power_cap(config.power_cap)?,
+                    DriftedSetting::PerformanceLevel
+                    | DriftedSetting::PowerProfileMode
+                    | DriftedSetting::FanControl => {
+                        return Err(anyhow!("Reapplying {setting} is not supported"));
                     }
                 }
-            } else {
-                self.stop_fan_control()
-                    .await
-                    .context("Could not reset fan control")?;
             }
-
             Ok(())
         })
     }
 
+    fn apply_config_changes<'a>(
+        &'a self,
+        previous: &'a config::Gpu,
+        config: &'a config::Gpu,
+    ) -> LocalBoxFuture<'a, anyhow::Result<()>> {
+        Box::pin(self.apply_settings(config, Some(previous)))
+    }
+
     fn cleanup_clocks(&self) -> anyhow::Result<()> {
         let device = self.device();
 
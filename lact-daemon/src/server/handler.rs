@@ -1,22 +1,34 @@
 use super::{
+    crash_guard::{self, CrashGuard},
     gpu_controller::{fan_control::FanCurve, DynGpuController, GpuController},
-    profiles::ProfileWatcherCommand,
-    system::{self, detect_initramfs_type, PP_FEATURE_MASK_PATH},
+    profiles::{
+        hooks::{self, HookTrigger},
+        ProfileSwitchDebouncer, ProfileWatcherCommand, RulePatterns, SensorTracker,
+    },
+    stats_history::{self, StatsHistory},
+    stats_sampler::{StatsSampler, StatsSubscription},
+    system::{self, detect_initramfs_type, ResetUevent, PP_FEATURE_MASK_PATH},
+    validation::{self, GpuLimits},
 };
 use crate::{
     bindings::intel::IntelDrm,
-    config::{self, default_fan_static_speed, Config, FanControlSettings, Profile},
+    config::{
+        self, default_fan_static_speed, Config, FanControlSettings, Profile, ProfileAssignment,
+    },
     server::{gpu_controller::init_controller, profiles, system::DAEMON_VERSION},
 };
 use amdgpu_sysfs::gpu_handle::{
     power_profile_mode::PowerProfileModesTable, PerformanceLevel, PowerLevelKind,
 };
 use anyhow::{anyhow, bail, Context};
+use indexmap::IndexMap;
 use lact_schema::{
     default_fan_curve,
     request::{ClockspeedType, ConfirmCommand, ProfileBase, SetClocksCommand},
-    ClocksInfo, DeviceInfo, DeviceListEntry, DeviceStats, FanControlMode, FanOptions, PmfwOptions,
-    PowerStates, ProfileRule, ProfileWatcherState, ProfilesInfo,
+    ClocksInfo, ConfigProblem, CrashRecoveryInfo, DeviceInfo, DeviceListEntry, DeviceStats,
+    FanControlMode, FanOptions, GpuResetEvent, PendingConfig, PmfwOptions, PowerStates,
+    ProfileInheritance, ProfileRule, ProfileWatcherState, ProfilesInfo, SettingsDriftInfo,
+    StatsHistorySample,
 };
 use libdrm_amdgpu_sys::LibDrmAmdgpu;
 use libflate::gzip;
@@ -26,25 +38,29 @@ use os_release::OS_RELEASE;
 use pciid_parser::Database;
 use serde_json::json;
 use std::{
+    borrow::Cow,
     cell::{Cell, LazyCell, RefCell},
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, HashMap, VecDeque},
     env,
     fs::{self, File, Permissions},
     io::{BufWriter, Cursor, Write},
+    iter,
     os::unix::fs::{MetadataExt, PermissionsExt},
     path::{Path, PathBuf},
     rc::Rc,
+    sync::Arc,
     time::{Duration, Instant},
 };
 use tokio::{
     process::Command,
-    sync::{mpsc, oneshot, RwLock, RwLockReadGuard},
+    sync::{mpsc, oneshot, Notify, RwLock, RwLockReadGuard},
     time::sleep,
 };
 use tracing::{debug, error, info, trace, warn};
 
 const CONTROLLERS_LOAD_RETRY_ATTEMPTS: u8 = 5;
 const CONTROLLERS_LOAD_RETRY_INTERVAL: u64 = 3;
+const MAX_GPU_RESET_EVENTS: usize = 100;
 
 const SNAPSHOT_GLOBAL_FILES: &[&str] = &[
     PP_FEATURE_MASK_PATH,
@@ -84,23 +100,47 @@ const SNAPSHOT_FAN_CTRL_FILES: &[&str] = &[
     "fan_zero_rpm_stop_temperature",
 ];
 
+struct PendingConfigChange {
+    tx: oneshot::Sender<ConfirmCommand>,
+    /// When the change gets reverted if it's not confirmed
+    deadline: Instant,
+}
+
 #[derive(Clone)]
 pub struct Handler {
     pub config: Rc<RwLock<Config>>,
     pub gpu_controllers: Rc<RwLock<BTreeMap<String, DynGpuController>>>,
-    confirm_config_tx: Rc<RefCell<Option<oneshot::Sender<ConfirmCommand>>>>,
+    /// Unconfirmed configuration changes, by GPU id
+    pending_configs: Rc<RefCell<IndexMap<String, PendingConfigChange>>>,
     pub config_last_saved: Rc<Cell<Instant>>,
     pub profile_watcher_tx: Rc<RefCell<Option<mpsc::Sender<ProfileWatcherCommand>>>>,
     pub profile_watcher_state: Rc<RefCell<Option<ProfileWatcherState>>>,
+    pub profile_rule_patterns: Rc<RefCell<RulePatterns>>,
+    pub profile_sensor_tracker: Rc<RefCell<SensorTracker>>,
+    pub profile_switch_debouncer: Rc<RefCell<ProfileSwitchDebouncer>>,
+    /// Notified on power supply uevents
+    pub power_supply_notify: Arc<Notify>,
+    stats_sampler: StatsSampler,
+    stats_history: Option<Rc<StatsHistory>>,
+    crash_guard: Option<Rc<CrashGuard>>,
+    /// The most recent GPU resets, oldest first
+    gpu_resets: Rc<RefCell<VecDeque<GpuResetEvent>>>,
+    /// Settings that had to be reapplied, by GPU id
+    settings_drift: Rc<RefCell<IndexMap<String, SettingsDriftInfo>>>,
 }
 
 impl<'a> Handler {
     pub async fn new(config: Config) -> anyhow::Result<Self> {
         let base_path = drm_base_path();
-        Self::with_base_path(&base_path, config).await
+        let crash_guard = CrashGuard::load(crash_guard::state_dir());
+        Self::with_base_path(&base_path, config, Some(crash_guard)).await
     }
 
-    pub(crate) async fn with_base_path(base_path: &Path, config: Config) -> anyhow::Result<Self> {
+    pub(crate) async fn with_base_path(
+        base_path: &Path,
+        config: Config,
+        crash_guard: Option<CrashGuard>,
+    ) -> anyhow::Result<Self> {
         let mut controllers = BTreeMap::new();
 
         // Sometimes LACT starts too early in the boot process, before the sysfs is initialized.
@@ -147,26 +187,46 @@ impl<'a> Handler {
         }
         info!("initialized {} GPUs", controllers.len());
 
+        let stats_history = config
+            .daemon
+            .stats_history
+            .clone()
+            .map(|settings| Rc::new(StatsHistory::new(settings)));
+
         let handler = Self {
             gpu_controllers: Rc::new(RwLock::new(controllers)),
             config: Rc::new(RwLock::new(config)),
-            confirm_config_tx: Rc::new(RefCell::new(None)),
+            pending_configs: Rc::new(RefCell::new(IndexMap::new())),
             config_last_saved: Rc::new(Cell::new(Instant::now())),
             profile_watcher_tx: Rc::new(RefCell::new(None)),
             profile_watcher_state: Rc::new(RefCell::new(None)),
+            profile_rule_patterns: Rc::new(RefCell::new(RulePatterns::default())),
+            profile_sensor_tracker: Rc::new(RefCell::new(SensorTracker::default())),
+            profile_switch_debouncer: Rc::new(RefCell::new(ProfileSwitchDebouncer::default())),
+            power_supply_notify: Arc::new(Notify::new()),
+            stats_sampler: StatsSampler::default(),
+            stats_history,
+            crash_guard: crash_guard.map(Rc::new),
+            gpu_resets: Rc::new(RefCell::new(VecDeque::new())),
+            settings_drift: Rc::new(RefCell::new(IndexMap::new())),
         };
         if let Err(err) = handler.apply_current_config().await {
             error!("could not apply config: {err:#}");
         }
 
-        if let Some(profile_name) = &handler.config.read().await.current_profile {
-            info!("using profile '{profile_name}'");
+        let profile_assignment = handler.config.read().await.profile_assignment();
+        if profile_assignment != ProfileAssignment::default() {
+            info!("using profiles {profile_assignment}");
         }
 
         if handler.config.read().await.auto_switch_profiles {
             handler.start_profile_watcher().await;
         }
 
+        if let Some(stats_history) = handler.stats_history.clone() {
+            tokio::task::spawn_local(stats_history::run_recorder(handler.clone(), stats_history));
+        }
+
         // Eagerly release memory
         // `load_controllers` allocates and deallocates the entire PCI ID database,
         // this tells the os to release it right away, lowering measured memory usage (the actual usage is low regardless as it was already deallocated)
@@ -181,9 +241,10 @@ impl<'a> Handler {
     pub async fn apply_current_config(&self) -> anyhow::Result<()> {
         let config = self.config.read().await;
 
-        let gpus = config.gpus()?;
+        let gpus = self.safe_gpus(config.gpus()?);
         let controllers = self.gpu_controllers.read().await;
-        for (id, gpu_config) in gpus {
+        self.arm_crash_guard(gpus.iter());
+        for (id, gpu_config) in gpus.iter() {
             if let Some(controller) = controllers.get(id) {
                 if let Err(err) = controller.apply_config(gpu_config).await {
                     error!("could not apply existing config for gpu {id}: {err}");
@@ -196,6 +257,84 @@ impl<'a> Handler {
         Ok(())
     }
 
+    /// Compares the hardware state with the applied settings, reapplying them if they were changed.
+    /// GPUs with an unconfirmed change are skipped, as their applied settings differ from the config.
+    pub async fn verify_applied_settings(&self) {
+        let config = self.config.read().await;
+        let gpus = match config.gpus() {
+            Ok(gpus) => self.safe_gpus(gpus),
+            Err(err) => {
+                error!("could not get GPU settings: {err:#}");
+                return;
+            }
+        };
+        let controllers = self.gpu_controllers.read().await;
+
+        for (id, gpu_config) in gpus.iter() {
+            let is_pending = self
+                .pending_configs
+                .try_borrow()
+                .map_or(true, |pending_configs| pending_configs.contains_key(id));
+            if is_pending {
+                continue;
+            }
+            let Some(controller) = controllers.get(id) else {
+                continue;
+            };
+
+            let drifted = controller.get_drifted_settings(gpu_config);
+            if drifted.is_empty() {
+                continue;
+            }
+            warn!("settings {drifted:?} of GPU {id} were changed outside of LACT, reapplying");
+
+            let result = controller.reapply_settings(gpu_config, &drifted).await;
+            if let Err(err) = &result {
+                error!("could not reapply settings for GPU {id}: {err:#}");
+            }
+
+            match self.settings_drift.try_borrow_mut() {
+                Ok(mut settings_drift) => {
+                    let info =
+                        settings_drift
+                            .entry(id.clone())
+                            .or_insert_with(|| SettingsDriftInfo {
+                                id: id.clone(),
+                                count: 0,
+                                failed_count: 0,
+                                last_detected_at: 0,
+                                last_settings: Vec::new(),
+                                last_error: None,
+                            });
+                    match result {
+                        Ok(()) => {
+                            info.count += 1;
+                            info.last_error = None;
+                        }
+                        Err(err) => {
+                            info.failed_count += 1;
+                            info.last_error = Some(format!("{err:#}"));
+                        }
+                    }
+                    info.last_detected_at = chrono::Utc::now().timestamp_millis();
+                    info.last_settings = drifted
+                        .into_iter()
+                        .map(|setting| setting.name().to_owned())
+                        .collect();
+                }
+                Err(err) => error!("{err}"),
+            }
+        }
+    }
+
+    pub fn list_settings_drift(&self) -> anyhow::Result<Vec<SettingsDriftInfo>> {
+        let settings_drift = self
+            .settings_drift
+            .try_borrow()
+            .map_err(|err| anyhow!("{err}"))?;
+        Ok(settings_drift.values().cloned().collect())
+    }
+
     pub async fn reload_gpus(&self) {
         let base_path = drm_base_path();
         match load_controllers(&base_path) {
@@ -235,20 +374,24 @@ impl<'a> Handler {
         f: F,
     ) -> anyhow::Result<u64> {
         if self
-            .confirm_config_tx
-            .try_borrow_mut()
+            .pending_configs
+            .try_borrow()
             .map_err(|err| anyhow!("{err}"))?
-            .is_some()
+            .contains_key(&id)
         {
             return Err(anyhow!(
-                "There is an unconfirmed configuration change pending"
+                "There is an unconfirmed configuration change pending for GPU {id}"
             ));
         }
 
         let (gpu_config, apply_timer) = {
             let config = self.config.read().await;
             let apply_timer = config.apply_settings_timer;
-            let gpu_config = config.gpus()?.get(&id).cloned().unwrap_or_default();
+            let gpu_config = self
+                .safe_gpus(config.gpus()?)
+                .get(&id)
+                .cloned()
+                .unwrap_or_default();
             (gpu_config, apply_timer)
         };
 
@@ -256,6 +399,9 @@ impl<'a> Handler {
         f(&mut new_config);
 
         let controller = self.controller_by_id(&id).await?;
+        if new_config.clocks_configuration != gpu_config.clocks_configuration {
+            self.arm_crash_guard([(&id, &new_config)]);
+        }
 
         match controller.apply_config(&new_config).await {
             Ok(()) => {
@@ -283,10 +429,16 @@ impl<'a> Handler {
         apply_timer: u64,
     ) -> anyhow::Result<()> {
         let (tx, rx) = oneshot::channel();
-        *self
-            .confirm_config_tx
+        self.pending_configs
             .try_borrow_mut()
-            .map_err(|err| anyhow!("{err}"))? = Some(tx);
+            .map_err(|err| anyhow!("{err}"))?
+            .insert(
+                id.clone(),
+                PendingConfigChange {
+                    tx,
+                    deadline: Instant::now() + Duration::from_secs(apply_timer),
+                },
+            );
 
         let handler = self.clone();
 
@@ -298,7 +450,7 @@ impl<'a> Handler {
 
             tokio::select! {
                 () = tokio::time::sleep(Duration::from_secs(apply_timer)) => {
-                    info!("no confirmation received, reverting settings");
+                    info!("no confirmation received for GPU {id}, reverting settings");
 
                     if let Err(err) = controller.apply_config(&previous_config).await {
                         error!("could not revert settings: {err:#}");
@@ -307,14 +459,14 @@ impl<'a> Handler {
                 result = rx => {
                     match result {
                         Ok(ConfirmCommand::Confirm) => {
-                            info!("saving updated config");
+                            info!("saving updated config of GPU {id}");
+                            if let Some(crash_guard) = &handler.crash_guard {
+                                crash_guard.clear_recovery(Some(&id));
+                            }
 
                             let mut config_guard = handler.config.write().await;
-                            match config_guard.gpus_mut() {
-                                Ok(gpus) => {
-                                    gpus.insert(id, new_config);
-                                }
-                                Err(err) => error!("{err:#}"),
+                            if let Err(err) = config_guard.set_gpu_config(id.clone(), new_config) {
+                                error!("{err:#}");
                             }
 
                             if let Err(err) = config_guard.save(&handler.config_last_saved) {
@@ -330,8 +482,17 @@ impl<'a> Handler {
                 }
             }
 
-            match handler.confirm_config_tx.try_borrow_mut() {
-                Ok(mut guard) => *guard = None,
+            // The entry is already removed when the change was confirmed or reverted,
+            // and could belong to a newer change by then
+            match handler.pending_configs.try_borrow_mut() {
+                Ok(mut pending_configs) => {
+                    if pending_configs
+                        .get(&id)
+                        .is_some_and(|pending| pending.tx.is_closed())
+                    {
+                        pending_configs.shift_remove(&id);
+                    }
+                }
                 Err(err) => error!("{err}"),
             }
         });
@@ -374,21 +535,48 @@ impl<'a> Handler {
 
     pub async fn get_gpu_stats(&'a self, id: &str) -> anyhow::Result<DeviceStats> {
         let config = self.config.read().await;
-        let gpu_config = config.gpus()?.get(id);
+        let gpus = config.gpus()?;
+        let gpu_config = gpus.get(id);
         Ok(self.controller_by_id(id).await?.get_stats(gpu_config))
     }
 
+    pub async fn subscribe_stats(
+        &'a self,
+        id: &str,
+        interval_ms: u64,
+    ) -> anyhow::Result<StatsSubscription> {
+        // Make sure the GPU exists before starting a sampler for it
+        self.controller_by_id(id).await?;
+        Ok(self.stats_sampler.subscribe(self.clone(), id, interval_ms))
+    }
+
+    pub async fn get_stats_history(
+        &'a self,
+        id: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        resolution: Option<u64>,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        let history = self
+            .stats_history
+            .as_ref()
+            .context("Stats history recording is not enabled in the daemon config")?;
+        self.controller_by_id(id).await?;
+        history.query(id, since, until, resolution).await
+    }
+
     pub async fn get_clocks_info(&'a self, id: &str) -> anyhow::Result<ClocksInfo> {
         self.controller_by_id(id).await?.get_clocks_info()
     }
 
     pub async fn set_fan_control(&'a self, opts: FanOptions<'_>) -> anyhow::Result<u64> {
-        let settings = {
-            let mut config_guard = self.config.write().await;
+        let mut settings = {
+            let config_guard = self.config.read().await;
             let gpu_config = config_guard
-                .gpus_mut()?
-                .entry(opts.id.to_owned())
-                .or_default();
+                .gpus()?
+                .get(opts.id)
+                .cloned()
+                .unwrap_or_default();
 
             match opts.mode {
                 Some(mode) => match mode {
@@ -401,6 +589,9 @@ impl<'a> Handler {
                         if let Some(mut existing_settings) = gpu_config.fan_control_settings.clone()
                         {
                             existing_settings.mode = mode;
+                            for setting in &opts.clear {
+                                existing_settings.clear_setting(*setting);
+                            }
                             if let Some(static_speed) = opts.static_speed {
                                 existing_settings.static_speed = static_speed;
                             }
@@ -415,16 +606,46 @@ impl<'a> Handler {
                             })
                         }
                     }
-                    FanControlMode::Curve => {
+                    FanControlMode::Curve | FanControlMode::MultiSensor => {
+                        if let Some(smoothing) = opts.temperature_smoothing {
+                            smoothing.validate()?;
+                        }
+                        for sensor_curve in
+                            opts.sensor_curves.iter().flat_map(|curves| curves.values())
+                        {
+                            FanCurve(sensor_curve.curve.clone()).validate()?;
+                        }
+
                         if let Some(mut existing_settings) = gpu_config.fan_control_settings.clone()
                         {
                             existing_settings.mode = mode;
+                            for setting in &opts.clear {
+                                existing_settings.clear_setting(*setting);
+                            }
                             if let Some(change_threshold) = opts.change_threshold {
                                 existing_settings.change_threshold = Some(change_threshold);
                             }
                             if let Some(spindown_delay) = opts.spindown_delay_ms {
                                 existing_settings.spindown_delay_ms = Some(spindown_delay);
                             }
+                            if let Some(smoothing) = opts.temperature_smoothing {
+                                existing_settings.temperature_smoothing = Some(smoothing);
+                            }
+                            if let Some(hysteresis_up) = opts.hysteresis_up {
+                                existing_settings.hysteresis_up = Some(hysteresis_up);
+                            }
+                            if let Some(hysteresis_down) = opts.hysteresis_down {
+                                existing_settings.hysteresis_down = Some(hysteresis_down);
+                            }
+                            if let Some(slew_rate) = opts.max_pwm_slew_rate {
+                                existing_settings.max_pwm_slew_rate = Some(slew_rate);
+                            }
+                            if let Some(sensor_curves) = opts.sensor_curves {
+                                existing_settings.sensor_curves = sensor_curves;
+                            }
+                            if let Some(combination) = opts.sensor_combination {
+                                existing_settings.sensor_combination = Some(combination);
+                            }
 
                             if let Some(raw_curve) = opts.curve {
                                 let curve = FanCurve(raw_curve);
@@ -440,6 +661,12 @@ impl<'a> Handler {
                                 curve,
                                 change_threshold: opts.change_threshold,
                                 spindown_delay_ms: opts.spindown_delay_ms,
+                                temperature_smoothing: opts.temperature_smoothing,
+                                hysteresis_up: opts.hysteresis_up,
+                                hysteresis_down: opts.hysteresis_down,
+                                max_pwm_slew_rate: opts.max_pwm_slew_rate,
+                                sensor_curves: opts.sensor_curves.unwrap_or_default(),
+                                sensor_combination: opts.sensor_combination,
                                 ..Default::default()
                             })
                         }
@@ -449,6 +676,21 @@ impl<'a> Handler {
             }
         };
 
+        if let Some(fan_overrides) = opts.fan_overrides {
+            for fan_override in fan_overrides.values() {
+                fan_override.validate()?;
+            }
+            if let Some(settings) = &mut settings {
+                settings.fan_overrides = fan_overrides;
+            }
+        }
+
+        if let Some(settings) = &settings {
+            if settings.mode == FanControlMode::MultiSensor && settings.sensor_curves.is_empty() {
+                return Err(anyhow!("Multi-sensor fan control requires sensor curves"));
+            }
+        }
+
         self.edit_gpu_config(opts.id.to_owned(), |config| {
             config.fan_control_enabled = opts.enabled;
             if let Some(settings) = settings {
@@ -481,7 +723,8 @@ impl<'a> Handler {
 
     pub async fn get_power_states(&self, id: &str) -> anyhow::Result<PowerStates> {
         let config = self.config.read().await;
-        let gpu_config = config.gpus()?.get(id);
+        let gpus = config.gpus()?;
+        let gpu_config = gpus.get(id);
 
         let states = self
             .controller_by_id(id)
@@ -579,6 +822,90 @@ impl<'a> Handler {
         self.controller_by_id(id).await?.vbios_dump()
     }
 
+    /// Checks the given settings against the limits of the GPU, without applying anything
+    pub async fn validate_gpu_config(
+        &self,
+        id: &str,
+        config: serde_json::Value,
+    ) -> anyhow::Result<Vec<ConfigProblem>> {
+        let gpu_config: config::Gpu =
+            serde_json::from_value(config).context("Could not parse GPU config")?;
+
+        let controller = self.controller_by_id(id).await?;
+        Ok(validation::validate_gpu_config(
+            &gpu_config,
+            &gpu_limits(&*controller),
+        ))
+    }
+
+    /// Checks the settings of every GPU in a full config, including the settings that each profile
+    /// resolves to after inheritance, without applying anything. When `id` is given, only that GPU is checked.
+    pub async fn validate_config(
+        &self,
+        mut config: serde_json::Value,
+        id: Option<&str>,
+    ) -> anyhow::Result<Vec<ConfigProblem>> {
+        // Only the GPU settings are checked, so the daemon section can be left out
+        if let Some(object) = config.as_object_mut() {
+            if !object.contains_key("daemon") {
+                object.insert(
+                    "daemon".to_owned(),
+                    serde_json::to_value(config::Daemon::default())?,
+                );
+            }
+        }
+        let config: Config = serde_json::from_value(config).context("Could not parse config")?;
+        let controllers = self.gpu_controllers.read().await;
+
+        let mut sections = vec![(
+            "gpus".to_owned(),
+            Ok(Cow::Owned(config.default_profile().gpus)),
+        )];
+        for name in config.profiles().keys() {
+            sections.push((format!("profiles.{name}.gpus"), config.profile_gpus(name)));
+        }
+
+        let mut problems = Vec::new();
+        for (location, gpus) in sections {
+            let gpus = match gpus {
+                Ok(gpus) => gpus,
+                Err(err) => {
+                    problems.push(ConfigProblem {
+                        field: location,
+                        message: format!("{err:#}"),
+                    });
+                    continue;
+                }
+            };
+
+            for (gpu_id, gpu_config) in gpus.iter() {
+                if id.is_some_and(|id| id != gpu_id) {
+                    continue;
+                }
+
+                let Some(controller) = controllers.get(gpu_id) else {
+                    problems.push(ConfigProblem {
+                        field: format!("{location}.{gpu_id}"),
+                        message: "GPU not found".to_owned(),
+                    });
+                    continue;
+                };
+
+                let limits = gpu_limits(controller.as_ref());
+                problems.extend(
+                    validation::validate_gpu_config(gpu_config, &limits)
+                        .into_iter()
+                        .map(|problem| ConfigProblem {
+                            field: format!("{location}.{gpu_id}.{}", problem.field),
+                            message: problem.message,
+                        }),
+                );
+            }
+        }
+
+        Ok(problems)
+    }
+
     pub async fn generate_snapshot(&self) -> anyhow::Result<String> {
         let datetime = chrono::Local::now().format("%Y%m%d-%H%M%S");
         let out_path = format!("/tmp/LACT-v{DAEMON_VERSION}-snapshot-{datetime}.tar.gz");
@@ -718,8 +1045,9 @@ impl<'a> Handler {
 
         let mut map = BTreeMap::new();
 
+        let gpus = config.gpus().ok();
         for (id, controller) in controllers.iter() {
-            let gpu_config = config.gpus().ok().and_then(|gpus| gpus.get(id));
+            let gpu_config = gpus.as_ref().and_then(|gpus| gpus.get(id));
 
             let data = json!({
                 "pci_info": controller.controller_info().pci_info.clone(),
@@ -746,26 +1074,54 @@ impl<'a> Handler {
         let config = self.config.read().await;
         ProfilesInfo {
             profiles: config
-                .profiles
+                .profiles()
                 .iter()
                 .map(|(name, profile)| (name.to_string(), profile.rule.clone()))
                 .collect(),
-            current_profile: config.current_profile.as_ref().map(Rc::to_string),
+            current_profile: config.current_profile().map(Rc::to_string),
+            gpu_profiles: self
+                .gpu_controllers
+                .read()
+                .await
+                .keys()
+                .map(|id| (id.clone(), config.active_profile(id).map(Rc::to_string)))
+                .collect(),
             auto_switch: config.auto_switch_profiles,
             watcher_state,
+            inheritance: config
+                .profiles()
+                .iter()
+                .filter_map(|(name, profile)| {
+                    let inheritance = ProfileInheritance {
+                        parent: profile.parent.as_ref()?.to_string(),
+                        overridden: profile.overridden_settings(),
+                    };
+                    Some((name.to_string(), inheritance))
+                })
+                .collect(),
         }
     }
 
+    /// Sets the profile of all GPUs, or only of the given GPU
     pub async fn set_profile(
         &self,
         name: Option<Rc<str>>,
         auto_switch: bool,
+        gpu_id: Option<String>,
     ) -> anyhow::Result<()> {
         if auto_switch {
             self.start_profile_watcher().await;
         } else {
             self.stop_profile_watcher().await;
-            self.set_current_profile(name).await?;
+            match gpu_id {
+                Some(gpu_id) => {
+                    self.controller_by_id(&gpu_id).await?;
+                    let mut assignment = self.config.read().await.profile_assignment();
+                    assignment.gpus.insert(gpu_id, name);
+                    self.set_profile_assignment(assignment, None).await?;
+                }
+                None => self.set_current_profile(name).await?,
+            }
         }
 
         let mut config = self.config.write().await;
@@ -775,15 +1131,87 @@ impl<'a> Handler {
         Ok(())
     }
 
-    pub(super) async fn set_current_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
-        if let Some(name) = &name {
-            self.config.read().await.profile(name)?;
-        }
+    /// Sets the same profile on all GPUs
+    async fn set_current_profile(&self, name: Option<Rc<str>>) -> anyhow::Result<()> {
+        self.set_profile_assignment(
+            ProfileAssignment {
+                default: name,
+                gpus: IndexMap::new(),
+            },
+            None,
+        )
+        .await
+    }
 
-        self.cleanup().await;
-        self.config.write().await.current_profile = name;
+    /// Switches the profiles and runs their hooks.
+    /// `trigger` is the process that caused the switch when switching automatically.
+    pub(super) async fn set_profile_assignment(
+        &self,
+        assignment: ProfileAssignment,
+        trigger: Option<&HookTrigger>,
+    ) -> anyhow::Result<()> {
+        let hooks = {
+            let config = self.config.read().await;
+            let profiles = iter::once(&assignment.default).chain(assignment.gpus.values());
+            for name in profiles.flatten() {
+                config.profile(name)?;
+            }
 
-        self.apply_current_config().await?;
+            hooks::collect_hooks(
+                config.profiles(),
+                &config.profile_assignment(),
+                &assignment,
+                trigger,
+            )
+        };
+
+        let previous_gpus = self
+            .safe_gpus(self.config.read().await.gpus()?)
+            .into_owned();
+        self.config.write().await.set_profile_assignment(assignment);
+
+        let result = self.apply_config_changes(&previous_gpus).await;
+        hooks::run_hooks(hooks);
+
+        result
+    }
+
+    /// Applies the current config over the `previous_gpus` settings.
+    /// GPUs with unchanged settings are not touched, and GPUs are only reset to defaults first
+    /// when a setting was removed, to avoid clocks and fans briefly going back to defaults.
+    /// Otherwise only the changed settings are applied, leaving unchanged clocks as-is.
+    async fn apply_config_changes(
+        &self,
+        previous_gpus: &IndexMap<String, config::Gpu>,
+    ) -> anyhow::Result<()> {
+        let config = self.config.read().await;
+        let gpus = self.safe_gpus(config.gpus()?);
+        let default_gpu = config::Gpu::default();
+
+        let controllers = self.gpu_controllers.read().await;
+        for (id, controller) in controllers.iter() {
+            let previous_gpu = previous_gpus.get(id).unwrap_or(&default_gpu);
+            let new_gpu = gpus.get(id).unwrap_or(&default_gpu);
+
+            if previous_gpu == new_gpu {
+                debug!("settings for GPU {id} are unchanged");
+                continue;
+            }
+
+            if new_gpu.clocks_configuration != previous_gpu.clocks_configuration {
+                self.arm_crash_guard([(id, new_gpu)]);
+            }
+            let result = if previous_gpu.has_removed_settings(new_gpu) {
+                debug!("settings were removed for GPU {id}, resetting it before applying");
+                cleanup_controller(id, controller, config.daemon.disable_clocks_cleanup).await;
+                controller.apply_config(new_gpu).await
+            } else {
+                controller.apply_config_changes(previous_gpu, new_gpu).await
+            };
+            if let Err(err) = result {
+                error!("could not apply config for GPU {id}: {err:#}");
+            }
+        }
 
         Ok(())
     }
@@ -791,7 +1219,7 @@ impl<'a> Handler {
     pub async fn create_profile(&self, name: String, base: ProfileBase) -> anyhow::Result<()> {
         {
             let mut config = self.config.write().await;
-            if config.profiles.contains_key(name.as_str()) {
+            if config.profiles().contains_key(name.as_str()) {
                 bail!("Profile {name} already exists");
             }
 
@@ -799,35 +1227,56 @@ impl<'a> Handler {
                 ProfileBase::Empty => Profile::default(),
                 ProfileBase::Default => config.default_profile(),
                 ProfileBase::Profile(name) => config.profile(&name)?.clone(),
+                ProfileBase::Inherit(parent) => {
+                    config.profile(&parent)?;
+                    Profile {
+                        parent: Some(parent.into()),
+                        ..Default::default()
+                    }
+                }
             };
-            config.profiles.insert(name.into(), profile);
+            config.profiles_mut().insert(name.into(), profile);
             config.save(&self.config_last_saved)?;
         }
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.update_profile_watcher().await;
 
         Ok(())
     }
 
     pub async fn delete_profile(&self, name: String) -> anyhow::Result<()> {
-        if self.config.read().await.current_profile.as_deref() == Some(&name) {
-            self.set_current_profile(None).await?;
+        if let Some((child, _)) = self
+            .config
+            .read()
+            .await
+            .profiles()
+            .iter()
+            .find(|(_, profile)| profile.parent.as_deref() == Some(name.as_str()))
+        {
+            bail!("Profile {name} cannot be deleted, as profile {child} inherits from it");
+        }
+
+        let assignment = self.config.read().await.profile_assignment();
+        let mut new_assignment = assignment.clone();
+        let profiles =
+            iter::once(&mut new_assignment.default).chain(new_assignment.gpus.values_mut());
+        for profile in profiles {
+            if profile.as_deref() == Some(name.as_str()) {
+                *profile = None;
+            }
+        }
+        if new_assignment != assignment {
+            self.set_profile_assignment(new_assignment, None).await?;
         }
         self.config
             .write()
             .await
-            .profiles
+            .profiles_mut()
             .shift_remove(name.as_str());
 
         self.config.write().await.save(&self.config_last_saved)?;
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.update_profile_watcher().await;
 
         Ok(())
     }
@@ -837,22 +1286,21 @@ impl<'a> Handler {
             let mut config = self.config.write().await;
 
             let current_index = config
-                .profiles
+                .profiles()
                 .get_index_of(name)
                 .with_context(|| format!("Profile {name} not found"))?;
 
-            if new_position >= config.profiles.len() {
+            if new_position >= config.profiles().len() {
                 bail!("Provided index is out of bounds");
             }
 
-            config.profiles.swap_indices(current_index, new_position);
+            config
+                .profiles_mut()
+                .swap_indices(current_index, new_position);
             config.save(&self.config_last_saved)?;
         }
 
-        let tx = self.profile_watcher_tx.borrow().clone();
-        if let Some(tx) = tx {
-            let _ = tx.send(ProfileWatcherCommand::Update).await;
-        }
+        self.update_profile_watcher().await;
 
         Ok(())
     }
@@ -862,50 +1310,252 @@ impl<'a> Handler {
         name: &str,
         rule: Option<ProfileRule>,
     ) -> anyhow::Result<()> {
+        if let Some(rule) = &rule {
+            self.profile_rule_patterns.borrow_mut().add_rule(rule)?;
+        }
+
         self.config
             .write()
             .await
-            .profiles
+            .profiles_mut()
             .get_mut(name)
             .with_context(|| format!("Profile {name} not found"))?
             .rule = rule;
 
         self.config.read().await.save(&self.config_last_saved)?;
 
+        self.update_profile_watcher().await;
+
+        Ok(())
+    }
+
+    /// Makes the profile watcher reload and re-evaluate the rules, such as when they were edited
+    pub async fn update_profile_watcher(&self) {
         let tx = self.profile_watcher_tx.borrow().clone();
         if let Some(tx) = tx {
             let _ = tx.send(ProfileWatcherCommand::Update).await;
         }
-
-        Ok(())
     }
 
     pub fn evaluate_profile_rule(&self, rule: &ProfileRule) -> anyhow::Result<bool> {
         let profile_watcher_state_guard = self.profile_watcher_state.borrow();
         match profile_watcher_state_guard.as_ref() {
-            Some(state) => Ok(profiles::profile_rule_matches(state, rule)),
+            Some(state) => {
+                let mut patterns = self.profile_rule_patterns.borrow_mut();
+                patterns.add_rule(rule)?;
+                let ctx = profiles::RuleContext {
+                    state,
+                    patterns: &patterns,
+                    sensors: &self.profile_sensor_tracker.borrow(),
+                    gpu_id: None,
+                    now: chrono::Local::now().naive_local(),
+                };
+                Ok(profiles::profile_rule_matches(&ctx, rule))
+            }
             None => Err(anyhow!(
                 "Automatic profile switching is not currently active"
             )),
         }
     }
 
-    pub fn confirm_pending_config(&self, command: ConfirmCommand) -> anyhow::Result<()> {
-        if let Some(tx) = self
-            .confirm_config_tx
-            .try_borrow_mut()
+    pub fn crash_recovery_info(&self) -> Option<CrashRecoveryInfo> {
+        self.crash_guard
+            .as_ref()
+            .and_then(|crash_guard| crash_guard.recovery_info())
+    }
+
+    /// Reverts the settings of a GPU that was reset by the driver, as they may have caused the reset.
+    ///
+    /// An unconfirmed change is reverted to the previous settings.
+    /// Otherwise clock and voltage settings are replaced with the last known good ones, same as after a crash.
+    pub async fn handle_gpu_reset(&self, event: ResetUevent) {
+        let id = self
+            .gpu_controllers
+            .read()
+            .await
+            .iter()
+            .find(|(_, controller)| {
+                controller.controller_info().pci_slot_name == event.pci_slot_name
+            })
+            .map(|(id, _)| id.clone());
+        let Some(id) = id else {
+            warn!(
+                "got reset event ({}) for unknown GPU {}",
+                event.reason, event.pci_slot_name
+            );
+            return;
+        };
+        warn!("GPU {id} was reset ({})", event.reason);
+
+        let settings_reverted = match self.revert_gpu_after_reset(&id).await {
+            Ok(reverted) => reverted,
+            Err(err) => {
+                error!("could not revert settings of GPU {id}: {err:#}");
+                false
+            }
+        };
+
+        match self.gpu_resets.try_borrow_mut() {
+            Ok(mut gpu_resets) => {
+                if gpu_resets.len() >= MAX_GPU_RESET_EVENTS {
+                    gpu_resets.pop_front();
+                }
+                gpu_resets.push_back(GpuResetEvent {
+                    id,
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                    reason: event.reason,
+                    settings_reverted,
+                });
+            }
+            Err(err) => error!("{err}"),
+        }
+    }
+
+    async fn revert_gpu_after_reset(&self, id: &str) -> anyhow::Result<bool> {
+        let has_pending_config = self
+            .pending_configs
+            .try_borrow()
             .map_err(|err| anyhow!("{err}"))?
-            .take()
-        {
-            tx.send(command)
-                .map_err(|_| anyhow!("Could not confirm config"))
-        } else {
-            Err(anyhow!("No pending config changes"))
+            .contains_key(id);
+        if has_pending_config {
+            info!("reverting unconfirmed settings of GPU {id}");
+            self.confirm_pending_config(Some(id), ConfirmCommand::Revert)?;
+            return Ok(true);
+        }
+
+        let config = self.config.read().await;
+        let has_clocks_settings = config
+            .gpus()?
+            .get(id)
+            .is_some_and(config::Gpu::is_core_clocks_used);
+        let Some(crash_guard) = &self.crash_guard else {
+            return Ok(false);
+        };
+        if !has_clocks_settings {
+            return Ok(false);
+        }
+
+        info!("skipping clock and voltage settings of GPU {id}");
+        crash_guard.skip_gpu(id.to_owned());
+
+        let gpu_config = self
+            .safe_gpus(config.gpus()?)
+            .get(id)
+            .cloned()
+            .unwrap_or_default();
+        let controller = self.controller_by_id(id).await?;
+        cleanup_controller(id, &*controller, config.daemon.disable_clocks_cleanup).await;
+        controller.apply_config(&gpu_config).await?;
+
+        Ok(true)
+    }
+
+    pub fn list_gpu_resets(&self) -> anyhow::Result<Vec<GpuResetEvent>> {
+        let gpu_resets = self
+            .gpu_resets
+            .try_borrow()
+            .map_err(|err| anyhow!("{err}"))?;
+        Ok(gpu_resets.iter().cloned().collect())
+    }
+
+    /// Replaces the settings that are skipped after a crash
+    fn safe_gpus<'c>(
+        &self,
+        gpus: Cow<'c, IndexMap<String, config::Gpu>>,
+    ) -> Cow<'c, IndexMap<String, config::Gpu>> {
+        match &self.crash_guard {
+            Some(crash_guard) => crash_guard.safe_gpus(gpus),
+            None => gpus,
+        }
+    }
+
+    /// Writes the crash marker before applying clock and voltage settings which have not stayed stable yet,
+    /// and removes it once the system has stayed up with them
+    fn arm_crash_guard<'c>(&self, gpus: impl IntoIterator<Item = (&'c String, &'c config::Gpu)>) {
+        let Some(crash_guard) = &self.crash_guard else {
+            return;
+        };
+
+        let gpu_ids: Vec<String> = gpus
+            .into_iter()
+            .filter(|(id, gpu)| {
+                gpu.is_core_clocks_used()
+                    && !crash_guard.is_known_good(id, &gpu.clocks_configuration)
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        if gpu_ids.is_empty() {
+            return;
+        }
+
+        match crash_guard.write_marker(gpu_ids) {
+            Ok(generation) => {
+                tokio::task::spawn_local(crash_guard::wait_until_stable(
+                    self.clone(),
+                    crash_guard.clone(),
+                    generation,
+                ));
+            }
+            Err(err) => error!("could not write apply marker: {err:#}"),
+        }
+    }
+
+    pub fn list_pending_configs(&self) -> anyhow::Result<Vec<PendingConfig>> {
+        let now = Instant::now();
+        let pending_configs = self
+            .pending_configs
+            .try_borrow()
+            .map_err(|err| anyhow!("{err}"))?
+            .iter()
+            .map(|(id, pending)| PendingConfig {
+                id: id.clone(),
+                seconds_left: pending.deadline.saturating_duration_since(now).as_secs(),
+            })
+            .collect();
+        Ok(pending_configs)
+    }
+
+    /// Confirms or reverts the pending change of the given GPU, or of all GPUs if no id is specified
+    pub fn confirm_pending_config(
+        &self,
+        id: Option<&str>,
+        command: ConfirmCommand,
+    ) -> anyhow::Result<()> {
+        let pending_configs: Vec<PendingConfigChange> = {
+            let mut pending_configs = self
+                .pending_configs
+                .try_borrow_mut()
+                .map_err(|err| anyhow!("{err}"))?;
+            match id {
+                Some(id) => pending_configs.shift_remove(id).into_iter().collect(),
+                None => pending_configs
+                    .drain(..)
+                    .map(|(_, pending)| pending)
+                    .collect(),
+            }
+        };
+
+        if pending_configs.is_empty() {
+            return match id {
+                Some(id) => Err(anyhow!("No pending config changes for GPU {id}")),
+                None => Err(anyhow!("No pending config changes")),
+            };
+        }
+
+        for pending in pending_configs {
+            pending
+                .tx
+                .send(command)
+                .map_err(|_| anyhow!("Could not confirm config"))?;
         }
+        Ok(())
     }
 
     pub async fn reset_config(&self) {
         self.cleanup().await;
+        if let Some(crash_guard) = &self.crash_guard {
+            crash_guard.clear_recovery(None);
+        }
 
         let mut config = self.config.write().await;
         config.clear();
@@ -920,22 +1570,53 @@ impl<'a> Handler {
 
         let controllers = self.gpu_controllers.read().await;
         for (id, controller) in controllers.iter() {
-            if !disable_clocks_cleanup {
-                debug!("resetting clocks table");
-                if let Err(err) = controller.cleanup_clocks() {
-                    error!("could not reset the clocks table: {err}");
-                }
-            }
+            cleanup_controller(id, controller, disable_clocks_cleanup).await;
+        }
 
-            controller.reset_pmfw_settings();
+        self.disarm_crash_guard();
+    }
 
-            if let Err(err) = controller.apply_config(&config::Gpu::default()).await {
-                error!("Could not reset settings for controller {id}: {err:#}");
-            }
+    /// Removes the crash marker without marking the settings as stable
+    pub fn disarm_crash_guard(&self) {
+        if let Some(crash_guard) = &self.crash_guard {
+            crash_guard.disarm();
         }
     }
 }
 
+/// Resets all of the settings of the GPU to defaults
+async fn cleanup_controller(
+    id: &str,
+    controller: &dyn GpuController,
+    disable_clocks_cleanup: bool,
+) {
+    if !disable_clocks_cleanup {
+        debug!("resetting clocks table");
+        if let Err(err) = controller.cleanup_clocks() {
+            error!("could not reset the clocks table: {err}");
+        }
+    }
+
+    controller.reset_pmfw_settings();
+
+    if let Err(err) = controller.apply_config(&config::Gpu::default()).await {
+        error!("Could not reset settings for controller {id}: {err:#}");
+    }
+}
+
+/// Gets the limits that the settings of the GPU are validated against
+fn gpu_limits(controller: &dyn GpuController) -> GpuLimits {
+    let stats = controller.get_stats(None);
+    GpuLimits {
+        clocks_info: controller.get_clocks_info().ok(),
+        power: stats.power,
+        performance_level: stats.performance_level,
+        power_states: controller.get_power_states(None),
+        power_profile_modes: controller.get_power_profile_modes().ok(),
+        pmfw_info: stats.fan.pmfw_info,
+    }
+}
+
 /// `sysfs_only` disables initialization of any external data sources, such as libdrm and nvml
 fn load_controllers(base_path: &Path) -> anyhow::Result<BTreeMap<String, DynGpuController>> {
     let mut controllers = BTreeMap::new();
@@ -1099,3 +1780,193 @@ fn drm_base_path() -> PathBuf {
         Err(_) => PathBuf::from("/sys/class/drm"),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Handler;
+    use crate::config::{
+        ClocksConfiguration, Config, FanControlSettings, Gpu, Profile, ProfileAssignment,
+    };
+    use crate::server::gpu_controller::DriftedSetting;
+    use indexmap::IndexMap;
+    use serde_json::json;
+    use std::{
+        env, fs,
+        path::{Path, PathBuf},
+        process,
+    };
+
+    /// Copies the files of a test GPU to a temporary directory, so that settings can be written to them
+    fn copy_test_gpu(name: &str, test_key: &str) -> PathBuf {
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests/data")
+            .join(test_key);
+        let target = env::temp_dir().join(format!("lact-handler-{name}-{}", process::id()));
+        copy_dir(&source, &target);
+        target
+    }
+
+    fn copy_dir(source: &Path, target: &Path) {
+        fs::create_dir_all(target).unwrap();
+        for entry in fs::read_dir(source).unwrap().flatten() {
+            let target = target.join(entry.file_name());
+            if entry.path().is_dir() {
+                copy_dir(&entry.path(), &target);
+            } else {
+                fs::copy(entry.path(), target).unwrap();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn profile_switch_keeps_unchanged_clocks() {
+        let base_path = copy_test_gpu("profile-switch", "amd/rx6900xt");
+        let handler = Handler::with_base_path(&base_path, Config::default(), None)
+            .await
+            .unwrap();
+        let id = handler.list_devices().await[0].id.clone();
+
+        let profile = |power_cap| Profile {
+            gpus: IndexMap::from([(
+                id.clone(),
+                Gpu {
+                    power_cap: Some(power_cap),
+                    clocks_configuration: ClocksConfiguration {
+                        max_core_clock: Some(2400),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+            )]),
+            ..Default::default()
+        };
+        {
+            let mut config = handler.config.write().await;
+            *config.profiles_mut() =
+                IndexMap::from([("a".into(), profile(250.0)), ("b".into(), profile(200.0))]);
+            config.set_profile_assignment(ProfileAssignment {
+                default: Some("a".into()),
+                gpus: IndexMap::new(),
+            });
+        }
+        handler.apply_current_config().await.unwrap();
+
+        // Any write to the clocks table, including a reset, replaces this
+        let clocks_table_path = base_path.join("card0/device/pp_od_clk_voltage");
+        fs::write(&clocks_table_path, "untouched").unwrap();
+
+        handler
+            .set_profile_assignment(
+                ProfileAssignment {
+                    default: Some("b".into()),
+                    gpus: IndexMap::new(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+
+        let stats = handler.get_gpu_stats(&id).await.unwrap();
+        assert_eq!(Some(200.0), stats.power.cap_current);
+        assert_eq!("untouched", fs::read_to_string(&clocks_table_path).unwrap());
+
+        fs::remove_dir_all(base_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn power_cap_drift_reapplies_only_power_cap() {
+        let base_path = copy_test_gpu("power-cap-drift", "amd/rx6900xt");
+        let handler = Handler::with_base_path(&base_path, Config::default(), None)
+            .await
+            .unwrap();
+        let controllers = handler.gpu_controllers.read().await;
+        let controller = controllers.values().next().unwrap();
+
+        // The fixture has a 289W cap
+        let gpu_config = Gpu {
+            power_cap: Some(250.0),
+            ..Default::default()
+        };
+        let drifted = controller.get_drifted_settings(&gpu_config);
+        assert_eq!(vec![DriftedSetting::PowerCap], drifted);
+
+        let clocks_table_path = base_path.join("card0/device/pp_od_clk_voltage");
+        fs::write(&clocks_table_path, "untouched").unwrap();
+
+        controller
+            .reapply_settings(&gpu_config, &drifted)
+            .await
+            .unwrap();
+
+        assert!(controller.get_drifted_settings(&gpu_config).is_empty());
+        assert_eq!("untouched", fs::read_to_string(&clocks_table_path).unwrap());
+
+        fs::remove_dir_all(base_path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn fan_mode_drift_is_detected() {
+        let base_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+        let handler = Handler::with_base_path(&base_path, Config::default(), None)
+            .await
+            .unwrap();
+        let controllers = handler.gpu_controllers.read().await;
+        let controller = controllers.values().next().unwrap();
+
+        // The fixture has no PMFW fan curve and its fan is in automatic mode
+        let mut gpu_config = Gpu {
+            fan_control_enabled: true,
+            fan_control_settings: Some(FanControlSettings::default()),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![DriftedSetting::FanControl],
+            controller.get_drifted_settings(&gpu_config)
+        );
+
+        gpu_config.fan_control_enabled = false;
+        assert!(controller.get_drifted_settings(&gpu_config).is_empty());
+    }
+
+    #[tokio::test]
+    async fn validate_config_checks_inherited_profile_settings() {
+        let base_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+        let handler = Handler::with_base_path(&base_path, Config::default(), None)
+            .await
+            .unwrap();
+        let id = handler.list_devices().await[0].id.clone();
+
+        let config = json!({
+            "gpus": {
+                id.clone(): { "fan_control_enabled": false, "power_cap": 250.0 }
+            },
+            "profiles": {
+                "base": {
+                    "gpus": {
+                        id.clone(): { "fan_control_enabled": false, "power_cap": 5000.0 }
+                    }
+                },
+                "child": { "parent": "base" },
+                "orphan": { "parent": "missing" }
+            }
+        });
+        let fields: Vec<String> = handler
+            .validate_config(config, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|problem| problem.field)
+            .collect();
+
+        assert_eq!(
+            vec![
+                format!("profiles.base.gpus.{id}.power_cap"),
+                format!("profiles.child.gpus.{id}.power_cap"),
+                "profiles.orphan.gpus".to_owned(),
+            ],
+            fields
+        );
+    }
+}
@@ -0,0 +1,472 @@
+use super::handler::Handler;
+use amdgpu_sysfs::gpu_handle::PerformanceLevel;
+use anyhow::Context;
+use lact_schema::DeviceStats;
+use std::{fmt::Write, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+    time::timeout,
+};
+use tracing::{debug, error, warn};
+
+const CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_HEADER_LINES: usize = 100;
+
+pub async fn listen(listener: TcpListener, handler: Handler) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let handler = handler.clone();
+                tokio::task::spawn_local(async move {
+                    if let Err(err) = handle_connection(stream, &handler).await {
+                        debug!("could not handle metrics request: {err:#}");
+                    }
+                });
+            }
+            Err(err) => {
+                error!("failed to handle metrics connection: {err}");
+            }
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, handler: &Handler) -> anyhow::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let request_line = timeout(REQUEST_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .context("Request timed out")??;
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", CONTENT_TYPE, render(handler).await),
+        ("GET", _) => ("404 Not Found", "text/plain", "Not found\n".to_owned()),
+        _ => (
+            "405 Method Not Allowed",
+            "text/plain",
+            "Method not allowed\n".to_owned(),
+        ),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+
+    Ok(())
+}
+
+/// Reads the request line and skips all headers, as they are not needed
+async fn read_request_head(stream: &mut BufReader<TcpStream>) -> anyhow::Result<String> {
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+
+    let mut header = String::new();
+    for _ in 0..MAX_HEADER_LINES {
+        header.clear();
+        if stream.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            return Ok(request_line);
+        }
+    }
+
+    Err(anyhow::anyhow!("Too many headers"))
+}
+
+async fn render(handler: &Handler) -> String {
+    let mut gpus = Vec::new();
+    for entry in handler.list_devices().await {
+        match handler.get_gpu_stats(&entry.id).await {
+            Ok(stats) => gpus.push((entry.id, stats)),
+            Err(err) => warn!("could not get stats for GPU {}: {err:#}", entry.id),
+        }
+    }
+
+    let mut writer = MetricsWriter::default();
+    writer.write_all(&gpus);
+    writer.finish()
+}
+
+#[derive(Default)]
+struct MetricsWriter {
+    output: String,
+}
+
+impl MetricsWriter {
+    #[allow(clippy::too_many_lines, clippy::cast_precision_loss)]
+    fn write_all(&mut self, gpus: &[(String, DeviceStats)]) {
+        self.family(
+            "lact_gpu_temperature_celsius",
+            "gauge",
+            "GPU temperature sensor reading",
+            gpus,
+            |stats| {
+                let mut temps: Vec<_> = stats
+                    .temps
+                    .iter()
+                    .filter_map(|(sensor, temp)| {
+                        temp.current
+                            .map(|value| (vec![("sensor", sensor.as_str())], f64::from(value)))
+                    })
+                    .collect();
+                temps.sort_by(|(a, _), (b, _)| a.cmp(b));
+                temps
+            },
+        );
+        self.family(
+            "lact_gpu_temperature_critical_celsius",
+            "gauge",
+            "Critical temperature of the sensor",
+            gpus,
+            |stats| {
+                let mut temps: Vec<_> = stats
+                    .temps
+                    .iter()
+                    .filter_map(|(sensor, temp)| {
+                        temp.crit
+                            .map(|value| (vec![("sensor", sensor.as_str())], f64::from(value)))
+                    })
+                    .collect();
+                temps.sort_by(|(a, _), (b, _)| a.cmp(b));
+                temps
+            },
+        );
+
+        self.gauge(
+            "lact_gpu_fan_control_enabled",
+            "Whether custom fan control is enabled",
+            gpus,
+            |stats| Some(f64::from(u8::from(stats.fan.control_enabled))),
+        );
+        self.gauge(
+            "lact_gpu_fan_speed_rpm",
+            "Current fan speed",
+            gpus,
+            |stats| stats.fan.speed_current.map(f64::from),
+        );
+        self.gauge(
+            "lact_gpu_fan_speed_max_rpm",
+            "Maximum fan speed",
+            gpus,
+            |stats| stats.fan.speed_max.map(f64::from),
+        );
+        self.gauge(
+            "lact_gpu_fan_speed_min_rpm",
+            "Minimum fan speed",
+            gpus,
+            |stats| stats.fan.speed_min.map(f64::from),
+        );
+        self.gauge(
+            "lact_gpu_fan_pwm",
+            "Current fan PWM value, from 0 to 255",
+            gpus,
+            |stats| stats.fan.pwm_current.map(f64::from),
+        );
+
+        self.gauge(
+            "lact_gpu_core_clock_mhz",
+            "Current GPU core clockspeed",
+            gpus,
+            |stats| stats.clockspeed.gpu_clockspeed.map(|value| value as f64),
+        );
+        self.gauge(
+            "lact_gpu_core_clock_target_mhz",
+            "Target GPU core clockspeed",
+            gpus,
+            |stats| stats.clockspeed.current_gfxclk.map(|value| value as f64),
+        );
+        self.gauge(
+            "lact_gpu_memory_clock_mhz",
+            "Current VRAM clockspeed",
+            gpus,
+            |stats| stats.clockspeed.vram_clockspeed.map(|value| value as f64),
+        );
+        self.gauge(
+            "lact_gpu_voltage_volts",
+            "Current GPU voltage",
+            gpus,
+            |stats| stats.voltage.gpu.map(|value| value as f64 / 1000.0),
+        );
+        self.gauge(
+            "lact_gpu_northbridge_voltage_volts",
+            "Current northbridge voltage",
+            gpus,
+            |stats| stats.voltage.northbridge.map(|value| value as f64 / 1000.0),
+        );
+
+        self.gauge("lact_gpu_vram_total_bytes", "Total VRAM", gpus, |stats| {
+            stats.vram.total.map(|value| value as f64)
+        });
+        self.gauge("lact_gpu_vram_used_bytes", "Used VRAM", gpus, |stats| {
+            stats.vram.used.map(|value| value as f64)
+        });
+
+        self.gauge(
+            "lact_gpu_power_average_watts",
+            "Average power usage",
+            gpus,
+            |stats| stats.power.average,
+        );
+        self.gauge(
+            "lact_gpu_power_current_watts",
+            "Current power usage",
+            gpus,
+            |stats| stats.power.current,
+        );
+        self.gauge(
+            "lact_gpu_power_cap_watts",
+            "Current power cap",
+            gpus,
+            |stats| stats.power.cap_current,
+        );
+        self.gauge(
+            "lact_gpu_power_cap_max_watts",
+            "Maximum allowed power cap",
+            gpus,
+            |stats| stats.power.cap_max,
+        );
+        self.gauge(
+            "lact_gpu_power_cap_min_watts",
+            "Minimum allowed power cap",
+            gpus,
+            |stats| stats.power.cap_min,
+        );
+        self.gauge(
+            "lact_gpu_power_cap_default_watts",
+            "Default power cap",
+            gpus,
+            |stats| stats.power.cap_default,
+        );
+
+        self.gauge("lact_gpu_busy_percent", "GPU usage", gpus, |stats| {
+            stats.busy_percent.map(f64::from)
+        });
+        self.gauge(
+            "lact_gpu_core_power_state",
+            "Index of the active core power state",
+            gpus,
+            |stats| stats.core_power_state.map(|value| value as f64),
+        );
+        self.gauge(
+            "lact_gpu_memory_power_state",
+            "Index of the active memory power state",
+            gpus,
+            |stats| stats.memory_power_state.map(|value| value as f64),
+        );
+        self.gauge(
+            "lact_gpu_pcie_power_state",
+            "Index of the active PCIe power state",
+            gpus,
+            |stats| stats.pcie_power_state.map(|value| value as f64),
+        );
+
+        self.family(
+            "lact_gpu_performance_level",
+            "info",
+            "Current performance level",
+            gpus,
+            |stats| {
+                stats
+                    .performance_level
+                    .map(|level| {
+                        let level = match level {
+                            PerformanceLevel::Auto => "auto",
+                            PerformanceLevel::Low => "low",
+                            PerformanceLevel::High => "high",
+                            PerformanceLevel::Manual => "manual",
+                        };
+                        (vec![("level", level)], 1.0)
+                    })
+                    .into_iter()
+                    .collect()
+            },
+        );
+        self.family(
+            "lact_gpu_throttled",
+            "gauge",
+            "Active throttling reasons",
+            gpus,
+            |stats| {
+                stats
+                    .throttle_info
+                    .iter()
+                    .flatten()
+                    .flat_map(|(kind, reasons)| {
+                        // Nvidia only reports the kind of throttling, without separate reasons
+                        if reasons.is_empty() {
+                            return vec![(vec![("type", kind.as_str())], 1.0)];
+                        }
+                        reasons
+                            .iter()
+                            .map(|reason| {
+                                (
+                                    vec![("type", kind.as_str()), ("reason", reason.as_str())],
+                                    1.0,
+                                )
+                            })
+                            .collect()
+                    })
+                    .collect()
+            },
+        );
+    }
+
+    /// A metric with a single value per GPU
+    fn gauge(
+        &mut self,
+        name: &str,
+        help: &str,
+        gpus: &[(String, DeviceStats)],
+        f: impl Fn(&DeviceStats) -> Option<f64>,
+    ) {
+        self.family(name, "gauge", help, gpus, |stats| {
+            f(stats).map(|value| (vec![], value)).into_iter().collect()
+        });
+    }
+
+    /// A metric with any number of labelled values per GPU.
+    /// All samples of a metric family have to be grouped together, so this iterates over every GPU.
+    fn family<'a>(
+        &mut self,
+        name: &str,
+        metric_type: &str,
+        help: &str,
+        gpus: &'a [(String, DeviceStats)],
+        f: impl Fn(&'a DeviceStats) -> Vec<(Vec<(&'a str, &'a str)>, f64)>,
+    ) {
+        let samples: Vec<_> = gpus
+            .iter()
+            .flat_map(|(id, stats)| {
+                f(stats)
+                    .into_iter()
+                    .map(move |(labels, value)| (id.as_str(), labels, value))
+            })
+            .collect();
+        if samples.is_empty() {
+            return;
+        }
+
+        let output = &mut self.output;
+        let _ = writeln!(output, "# TYPE {name} {metric_type}");
+        if let Some(unit) = ["celsius", "rpm", "mhz", "volts", "bytes", "watts"]
+            .into_iter()
+            .find(|unit| name.ends_with(&format!("_{unit}")))
+        {
+            let _ = writeln!(output, "# UNIT {name} {unit}");
+        }
+        let _ = writeln!(output, "# HELP {name} {help}");
+
+        let sample_name = if metric_type == "info" {
+            format!("{name}_info")
+        } else {
+            name.to_owned()
+        };
+
+        for (id, labels, value) in samples {
+            let _ = write!(output, "{sample_name}{{gpu_id=\"{}\"", escape_label(id));
+            for (key, label_value) in labels {
+                let _ = write!(output, ",{key}=\"{}\"", escape_label(label_value));
+            }
+            let _ = writeln!(output, "}} {value}");
+        }
+    }
+
+    fn finish(mut self) -> String {
+        self.output.push_str("# EOF\n");
+        self.output
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MetricsWriter;
+    use amdgpu_sysfs::{gpu_handle::PerformanceLevel, hw_mon::Temperature};
+    use lact_schema::{DeviceStats, FanStats, PowerStats};
+    use pretty_assertions::assert_eq;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn write_metrics() {
+        let stats = DeviceStats {
+            fan: FanStats {
+                control_enabled: true,
+                pwm_current: Some(100),
+                speed_current: Some(1500),
+                ..Default::default()
+            },
+            power: PowerStats {
+                average: Some(150.5),
+                cap_current: Some(250.0),
+                ..Default::default()
+            },
+            temps: HashMap::from([(
+                "edge".to_owned(),
+                Temperature {
+                    current: Some(55.0),
+                    crit: None,
+                    crit_hyst: None,
+                },
+            )]),
+            busy_percent: Some(42),
+            performance_level: Some(PerformanceLevel::Auto),
+            throttle_info: Some(BTreeMap::from([
+                ("PL1".to_owned(), vec!["power\"limit".to_owned()]),
+                ("SW_POWER_CAP".to_owned(), vec![]),
+            ])),
+            ..Default::default()
+        };
+
+        let mut writer = MetricsWriter::default();
+        writer.write_all(&[("1002:73BF".to_owned(), stats)]);
+        let output = writer.finish();
+
+        let expected = r#"# TYPE lact_gpu_temperature_celsius gauge
+# UNIT lact_gpu_temperature_celsius celsius
+# HELP lact_gpu_temperature_celsius GPU temperature sensor reading
+lact_gpu_temperature_celsius{gpu_id="1002:73BF",sensor="edge"} 55
+# TYPE lact_gpu_fan_control_enabled gauge
+# HELP lact_gpu_fan_control_enabled Whether custom fan control is enabled
+lact_gpu_fan_control_enabled{gpu_id="1002:73BF"} 1
+# TYPE lact_gpu_fan_speed_rpm gauge
+# UNIT lact_gpu_fan_speed_rpm rpm
+# HELP lact_gpu_fan_speed_rpm Current fan speed
+lact_gpu_fan_speed_rpm{gpu_id="1002:73BF"} 1500
+# TYPE lact_gpu_fan_pwm gauge
+# HELP lact_gpu_fan_pwm Current fan PWM value, from 0 to 255
+lact_gpu_fan_pwm{gpu_id="1002:73BF"} 100
+# TYPE lact_gpu_power_average_watts gauge
+# UNIT lact_gpu_power_average_watts watts
+# HELP lact_gpu_power_average_watts Average power usage
+lact_gpu_power_average_watts{gpu_id="1002:73BF"} 150.5
+# TYPE lact_gpu_power_cap_watts gauge
+# UNIT lact_gpu_power_cap_watts watts
+# HELP lact_gpu_power_cap_watts Current power cap
+lact_gpu_power_cap_watts{gpu_id="1002:73BF"} 250
+# TYPE lact_gpu_busy_percent gauge
+# HELP lact_gpu_busy_percent GPU usage
+lact_gpu_busy_percent{gpu_id="1002:73BF"} 42
+# TYPE lact_gpu_performance_level info
+# HELP lact_gpu_performance_level Current performance level
+lact_gpu_performance_level_info{gpu_id="1002:73BF",level="auto"} 1
+# TYPE lact_gpu_throttled gauge
+# HELP lact_gpu_throttled Active throttling reasons
+lact_gpu_throttled{gpu_id="1002:73BF",type="PL1",reason="power\"limit"} 1
+lact_gpu_throttled{gpu_id="1002:73BF",type="SW_POWER_CAP"} 1
+# EOF
+"#;
+        assert_eq!(expected, output);
+    }
+}
@@ -1,11 +1,32 @@
 mod gamemode;
+pub mod hooks;
+mod patterns;
+mod power_supply;
 mod process;
+mod schedule;
+mod sensor;
+mod switching;
 
-use crate::server::handler::Handler;
+use hooks::HookTrigger;
+pub use patterns::RulePatterns;
+pub use sensor::SensorTracker;
+pub use switching::ProfileSwitchDebouncer;
+use switching::SwitchDecision;
+
+use crate::{
+    config::{Profile, ProfileAssignment},
+    server::handler::Handler,
+};
+use chrono::{Local, NaiveDateTime};
 use copes::solver::PEvent;
 use futures::StreamExt;
-use lact_schema::{ProfileRule, ProfileWatcherState};
+use indexmap::IndexMap;
+use lact_schema::{MatchMode, ProcessInfo, ProcessProfileRule, ProfileRule, ProfileWatcherState};
 use std::{
+    collections::HashMap,
+    iter,
+    path::Path,
+    pin::Pin,
     process::Command,
     rc::Rc,
     time::{Duration, Instant},
@@ -13,7 +34,7 @@ use std::{
 use tokio::{
     runtime, select,
     sync::{mpsc, Mutex, Notify},
-    time::sleep,
+    time::{interval, sleep, MissedTickBehavior, Sleep},
 };
 use tracing::{debug, error, info, trace, warn};
 use zbus::AsyncDrop;
@@ -23,6 +44,10 @@ static PROFILE_WATCHER_LOCK: Mutex<()> = Mutex::const_new(());
 const PROFILE_WATCHER_MIN_DELAY_MS: u64 = 50;
 const PROFILE_WATCHER_MAX_DELAY_MS: u64 = 500;
 const SUSPICIOUS_INACTIVITY_PERIOD_SECS: u64 = 30;
+/// The system clock can be changed and the system can be suspended,
+/// so schedule rules are re-checked at least this often even if no boundary was reached
+const SCHEDULE_MAX_DELAY_SECS: u64 = 60;
+const SENSOR_SAMPLE_INTERVAL_MS: u64 = 1000;
 
 #[derive(Debug)]
 enum ProfileWatcherEvent {
@@ -47,6 +72,7 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
     let mut state = ProfileWatcherState::default();
     process::load_full_process_list(&mut state);
     info!("loaded {} processes", state.process_list.len());
+    state.power_supply = power_supply::read_state(Path::new(power_supply::POWER_SUPPLY_PATH));
 
     let (event_tx, mut event_rx) = mpsc::channel(128);
 
@@ -119,7 +145,9 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
     }
 
     *handler.profile_watcher_state.borrow_mut() = Some(state);
+    *handler.profile_switch_debouncer.borrow_mut() = ProfileSwitchDebouncer::default();
 
+    reload_rules(&handler).await;
     update_profile(&handler).await;
 
     let mut should_reload = false;
@@ -128,16 +156,55 @@ pub async fn run_watcher(handler: Handler, mut command_rx: mpsc::Receiver<Profil
     let inactivity_timer = sleep(Duration::from_secs(SUSPICIOUS_INACTIVITY_PERIOD_SECS));
     tokio::pin!(inactivity_timer);
 
+    let schedule_timer = sleep(Duration::ZERO);
+    tokio::pin!(schedule_timer);
+    let mut schedule_active = reset_schedule_timer(&handler, schedule_timer.as_mut()).await;
+
+    let power_supply_notify = handler.power_supply_notify.clone();
+
+    let mut sensor_interval = interval(Duration::from_millis(SENSOR_SAMPLE_INTERVAL_MS));
+    sensor_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let switch_timer = sleep(Duration::ZERO);
+    tokio::pin!(switch_timer);
+
     loop {
+        // A profile switch may be waiting for its activation delay or the minimum active time
+        let switch_deadline = handler.profile_switch_debouncer.borrow().deadline();
+        if let Some(deadline) = switch_deadline {
+            switch_timer.as_mut().reset(deadline.into());
+        }
+
         select! {
             Some(cmd) = command_rx.recv() => {
                 match cmd {
                     ProfileWatcherCommand::Stop => break,
                     ProfileWatcherCommand::Update => {
+                        reload_rules(&handler).await;
                         update_profile(&handler).await;
+                        schedule_active = reset_schedule_timer(&handler, schedule_timer.as_mut()).await;
                     }
                 }
             }
+            _ = sensor_interval.tick(), if !handler.profile_sensor_tracker.borrow().is_empty() => {
+                if sample_sensors(&handler).await {
+                    update_profile(&handler).await;
+                }
+            }
+            () = power_supply_notify.notified() => {
+                if update_power_supply_state(&handler) {
+                    update_profile(&handler).await;
+                }
+            }
+            () = &mut switch_timer, if switch_deadline.is_some() => {
+                trace!("profile switch delay reached, re-evaluating profile rules");
+                update_profile(&handler).await;
+            }
+            () = &mut schedule_timer, if schedule_active => {
+                trace!("schedule timer reached, re-evaluating profile rules");
+                update_profile(&handler).await;
+                schedule_active = reset_schedule_timer(&handler, schedule_timer.as_mut()).await;
+            }
             Some(event) = event_rx.recv() => {
                 suspiciously_quiet = false;
                 inactivity_timer.as_mut().reset(tokio::time::Instant::now() + Duration::from_secs(SUSPICIOUS_INACTIVITY_PERIOD_SECS));
@@ -252,45 +319,219 @@ fn handle_profile_event(event: &ProfileWatcherEvent, handler: &Handler, should_r
     }
 }
 
+/// Compiles the patterns and sets up the sensor tracking for the current rules
+async fn reload_rules(handler: &Handler) {
+    let config = handler.config.read().await;
+    let rules = || {
+        config
+            .profiles()
+            .values()
+            .filter_map(|profile| profile.rule.as_ref())
+    };
+    handler.profile_rule_patterns.borrow_mut().reload(rules());
+    handler.profile_sensor_tracker.borrow_mut().reload(rules());
+}
+
+/// Samples the stats used by sensor rules.
+/// Returns `true` if a sensor rule has started or stopped matching.
+async fn sample_sensors(handler: &Handler) -> bool {
+    let all_gpu_ids: Vec<String> = handler
+        .gpu_controllers
+        .read()
+        .await
+        .keys()
+        .cloned()
+        .collect();
+
+    let gpu_ids = handler
+        .profile_sensor_tracker
+        .borrow()
+        .gpu_ids(&all_gpu_ids);
+
+    let mut stats = HashMap::with_capacity(gpu_ids.len());
+    for id in gpu_ids {
+        match handler.get_gpu_stats(&id).await {
+            Ok(gpu_stats) => {
+                stats.insert(id, gpu_stats);
+            }
+            Err(err) => debug!("could not get stats of GPU {id} for sensor rules: {err:#}"),
+        }
+    }
+
+    handler
+        .profile_sensor_tracker
+        .borrow_mut()
+        .update(&stats, Instant::now())
+}
+
+/// Returns `true` if the power supply state has changed
+fn update_power_supply_state(handler: &Handler) -> bool {
+    let new_state = power_supply::read_state(Path::new(power_supply::POWER_SUPPLY_PATH));
+
+    let mut state_guard = handler.profile_watcher_state.borrow_mut();
+    match state_guard.as_mut() {
+        Some(state) if state.power_supply != new_state => {
+            debug!("power supply state changed to {new_state:?}");
+            state.power_supply = new_state;
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Sets the timer to the next point in time when a schedule rule can change.
+/// Returns `false` if there are no schedule rules.
+async fn reset_schedule_timer(handler: &Handler, timer: Pin<&mut Sleep>) -> bool {
+    let config = handler.config.read().await;
+    let rules = config
+        .profiles()
+        .values()
+        .filter_map(|profile| profile.rule.as_ref());
+
+    let now = Local::now().naive_local();
+    match schedule::next_boundary(rules, now) {
+        Some(boundary) => {
+            let delay = (boundary - now)
+                .to_std()
+                .unwrap_or_default()
+                .min(Duration::from_secs(SCHEDULE_MAX_DELAY_SECS));
+            trace!("next schedule check in {delay:?}");
+            timer.reset(tokio::time::Instant::now() + delay);
+            true
+        }
+        None => false,
+    }
+}
+
 async fn update_profile(handler: &Handler) {
-    let new_profile = {
-        let config = handler.config.read().await;
-        let profile_rules = config
-            .profiles
-            .iter()
-            .filter_map(|(name, profile)| Some((name, profile.rule.as_ref()?)));
+    let gpu_ids: Vec<String> = handler
+        .gpu_controllers
+        .read()
+        .await
+        .keys()
+        .cloned()
+        .collect();
 
+    let config = handler.config.read().await;
+    let (new_assignment, trigger) = {
         let state_guard = handler.profile_watcher_state.borrow();
         if let Some(state) = state_guard.as_ref() {
+            let patterns = handler.profile_rule_patterns.borrow();
+            let sensors = handler.profile_sensor_tracker.borrow();
+            let ctx = RuleContext {
+                state,
+                patterns: &patterns,
+                sensors: &sensors,
+                gpu_id: None,
+                now: Local::now().naive_local(),
+            };
             let started_at = Instant::now();
-            let new_profile = evaluate_current_profile(state, profile_rules);
+            let new_assignment = evaluate_profile_assignment(&ctx, config.profiles(), &gpu_ids);
             trace!("evaluated profile rules in {:?}", started_at.elapsed());
-            new_profile.cloned()
+
+            let trigger = iter::once(&new_assignment.default)
+                .chain(new_assignment.gpus.values())
+                .flatten()
+                .filter_map(|name| config.profiles().get(name)?.rule.as_ref())
+                .find_map(|rule| find_trigger_process(&ctx, rule))
+                .map(|(pid, info)| HookTrigger {
+                    pid,
+                    info: info.clone(),
+                });
+            (new_assignment, trigger)
         } else {
-            None
+            (ProfileAssignment::default(), None)
         }
     };
 
-    if handler.config.read().await.current_profile != new_profile {
-        if let Some(name) = &new_profile {
-            info!("setting current profile to '{name}'");
-        } else {
-            info!("setting default profile");
+    let current_assignment = config.profile_assignment();
+    let decision = handler.profile_switch_debouncer.borrow_mut().decide(
+        &current_assignment,
+        &new_assignment,
+        &gpu_ids,
+        config.profiles(),
+        Instant::now(),
+    );
+    drop(config);
+
+    match decision {
+        SwitchDecision::Keep => (),
+        SwitchDecision::Wait(delay) => {
+            debug!("delaying switching profiles to {new_assignment} by {delay:?}");
         }
+        SwitchDecision::Switch(new_assignment) => {
+            info!("setting profiles to {new_assignment}");
 
-        if let Err(err) = handler.set_current_profile(new_profile).await {
-            error!("failed to apply profile: {err:#}");
+            if let Err(err) = handler
+                .set_profile_assignment(new_assignment, trigger.as_ref())
+                .await
+            {
+                error!("failed to apply profile: {err:#}");
+            }
         }
     }
 }
 
+/// Everything that the profile rules are evaluated against
+pub struct RuleContext<'a> {
+    pub state: &'a ProfileWatcherState,
+    pub patterns: &'a RulePatterns,
+    pub sensors: &'a SensorTracker,
+    /// GPU whose profile is being chosen, used by sensor rules without their own GPU id.
+    /// When not set, such rules match if they match on any GPU.
+    pub gpu_id: Option<&'a str>,
+    /// Current local time
+    pub now: NaiveDateTime,
+}
+
+/// Returns the new active profile of every GPU.
+/// Profiles limited to specific GPUs are only considered for those GPUs.
+fn evaluate_profile_assignment(
+    ctx: &RuleContext,
+    profiles: &IndexMap<Rc<str>, Profile>,
+    gpu_ids: &[String],
+) -> ProfileAssignment {
+    let profile_rules = |gpu_id: Option<&str>| {
+        profiles
+            .iter()
+            .filter(move |(_, profile)| match gpu_id {
+                Some(gpu_id) => profile.applies_to(gpu_id),
+                None => profile.gpu_ids.is_empty(),
+            })
+            .filter_map(|(name, profile)| Some((name, profile.rule.as_ref()?)))
+    };
+
+    let mut assignment = ProfileAssignment {
+        default: evaluate_current_profile(ctx, profile_rules(None)).cloned(),
+        gpus: IndexMap::new(),
+    };
+
+    // Without GPU-specific profiles or sensor rules, every GPU uses the default profile
+    if profiles.values().any(|profile| !profile.gpu_ids.is_empty())
+        || ctx.sensors.has_rules_for_every_gpu()
+    {
+        for gpu_id in gpu_ids {
+            let gpu_ctx = RuleContext {
+                gpu_id: Some(gpu_id),
+                ..*ctx
+            };
+            let profile = evaluate_current_profile(&gpu_ctx, profile_rules(Some(gpu_id))).cloned();
+            if profile != assignment.default {
+                assignment.gpus.insert(gpu_id.clone(), profile);
+            }
+        }
+    }
+
+    assignment
+}
+
 /// Returns the new active profile
 fn evaluate_current_profile<'a>(
-    state: &ProfileWatcherState,
+    ctx: &RuleContext,
     profile_rules: impl Iterator<Item = (&'a Rc<str>, &'a ProfileRule)>,
 ) -> Option<&'a Rc<str>> {
     for (profile_name, rule) in profile_rules {
-        if profile_rule_matches(state, rule) {
+        if profile_rule_matches(ctx, rule) {
             return Some(profile_name);
         }
     }
@@ -299,66 +540,264 @@ fn evaluate_current_profile<'a>(
 }
 
 #[inline]
-pub(crate) fn profile_rule_matches(state: &ProfileWatcherState, rule: &ProfileRule) -> bool {
+pub(crate) fn profile_rule_matches(ctx: &RuleContext, rule: &ProfileRule) -> bool {
     match rule {
-        ProfileRule::Process(process_rule) => {
-            if let Some(pids) = state.process_names_map.get(&process_rule.name) {
-                match &process_rule.args {
-                    Some(args_filter) => {
-                        for pid in pids {
-                            if let Some(process_info) = state.process_list.get(pid) {
-                                if process_info.cmdline.contains(args_filter) {
-                                    return true;
-                                }
-                            } else {
-                                error!("process {pid} not found in process map");
-                            }
-                        }
-                    }
-                    None => return true,
-                }
-            }
+        ProfileRule::Process(process_rule) => process_rule_matches(ctx, process_rule, |_| true),
+        ProfileRule::Gamemode(None) => !ctx.state.gamemode_games.is_empty(),
+        ProfileRule::Gamemode(Some(gamemode_rule)) => {
+            process_rule_matches(ctx, gamemode_rule, |pid| {
+                ctx.state.gamemode_games.contains(&pid)
+            })
         }
-        ProfileRule::Gamemode(None) => return !state.gamemode_games.is_empty(),
+        ProfileRule::All(rules) => rules.iter().all(|rule| profile_rule_matches(ctx, rule)),
+        ProfileRule::Any(rules) => rules.iter().any(|rule| profile_rule_matches(ctx, rule)),
+        ProfileRule::Not(rule) => !profile_rule_matches(ctx, rule),
+        ProfileRule::Schedule(schedule_rule) => schedule::schedule_matches(schedule_rule, ctx.now),
+        ProfileRule::PowerSource(power_source_rule) => {
+            power_supply::power_source_matches(power_source_rule, &ctx.state.power_supply)
+        }
+        ProfileRule::Sensor(sensor_rule) => ctx.sensors.is_active(sensor_rule, ctx.gpu_id),
+    }
+}
+
+/// Finds a process that makes the rule match, to be passed to the profile hooks
+fn find_trigger_process<'a>(
+    ctx: &RuleContext<'a>,
+    rule: &ProfileRule,
+) -> Option<(i32, &'a ProcessInfo)> {
+    let state = ctx.state;
+    let find_process = |rule: &ProcessProfileRule, pid_filter: &dyn Fn(i32) -> bool| {
+        let mode = rule.match_mode.unwrap_or_default();
+        state
+            .process_list
+            .iter()
+            .filter(|(pid, _)| pid_filter(**pid))
+            .find(|(_, info)| process_matches(ctx.patterns, mode, rule, info))
+            .map(|(pid, info)| (*pid, info))
+    };
+
+    match rule {
+        ProfileRule::Process(process_rule) => find_process(process_rule, &|_| true),
         ProfileRule::Gamemode(Some(gamemode_rule)) => {
-            if let Some(pids) = state.process_names_map.get(&gamemode_rule.name) {
-                for pid in pids {
-                    if state.gamemode_games.contains(pid) {
-                        match &gamemode_rule.args {
-                            Some(args_filter) => {
-                                if let Some(process_info) = state.process_list.get(pid) {
-                                    if process_info.cmdline.contains(args_filter) {
-                                        return true;
-                                    }
-                                } else {
-                                    error!("process {pid} not found in process map");
-                                }
-                            }
-                            None => return true,
-                        }
-                    }
+            find_process(gamemode_rule, &|pid| state.gamemode_games.contains(&pid))
+        }
+        ProfileRule::Gamemode(None) => state
+            .gamemode_games
+            .iter()
+            .find_map(|pid| Some((*pid, state.process_list.get(pid)?))),
+        ProfileRule::All(rules) | ProfileRule::Any(rules) => rules
+            .iter()
+            .filter(|rule| profile_rule_matches(ctx, rule))
+            .find_map(|rule| find_trigger_process(ctx, rule)),
+        ProfileRule::Not(_)
+        | ProfileRule::Schedule(_)
+        | ProfileRule::PowerSource(_)
+        | ProfileRule::Sensor(_) => None,
+    }
+}
+
+/// Checks if there is a process matching the rule, out of the processes allowed by `pid_filter`
+fn process_rule_matches(
+    ctx: &RuleContext,
+    rule: &ProcessProfileRule,
+    pid_filter: impl Fn(i32) -> bool,
+) -> bool {
+    let mode = rule.match_mode.unwrap_or_default();
+
+    if mode == MatchMode::Exact {
+        // Exact names can be looked up directly instead of checking every process
+        let Some(pids) = ctx.state.process_names_map.get(&rule.name) else {
+            return false;
+        };
+
+        pids.iter().filter(|pid| pid_filter(**pid)).any(|pid| {
+            match ctx.state.process_list.get(pid) {
+                Some(info) => process_matches(ctx.patterns, mode, rule, info),
+                None => {
+                    error!("process {pid} not found in process map");
+                    false
                 }
             }
-        }
+        })
+    } else {
+        ctx.state
+            .process_list
+            .iter()
+            .filter(|(pid, _)| pid_filter(**pid))
+            .any(|(_, info)| process_matches(ctx.patterns, mode, rule, info))
     }
-    false
+}
+
+fn process_matches(
+    patterns: &RulePatterns,
+    mode: MatchMode,
+    rule: &ProcessProfileRule,
+    info: &ProcessInfo,
+) -> bool {
+    let field_matches = |pattern: Option<&str>, value: Option<&str>, substring: bool| {
+        let Some(pattern) = pattern else {
+            return true;
+        };
+        value.is_some_and(|value| patterns.matches(mode, pattern, value, substring))
+    };
+
+    patterns.matches(mode, &rule.name, &info.name, false)
+        && field_matches(rule.args.as_deref(), Some(&*info.cmdline), true)
+        && field_matches(rule.exe.as_deref(), info.exe.as_deref(), false)
+        && field_matches(rule.cwd.as_deref(), info.cwd.as_deref(), false)
 }
 
 #[cfg(test)]
 mod tests {
-    use super::evaluate_current_profile;
-    use lact_schema::{ProcessInfo, ProcessProfileRule, ProfileRule, ProfileWatcherState};
+    use super::{
+        evaluate_current_profile, evaluate_profile_assignment, profile_rule_matches, RuleContext,
+        RulePatterns, SensorTracker,
+    };
+    use crate::config::{Profile, ProfileAssignment};
+    use amdgpu_sysfs::hw_mon::Temperature;
+    use chrono::{NaiveDate, NaiveDateTime};
+    use indexmap::IndexMap;
+    use lact_schema::{
+        DeviceStats, MatchMode, ProcessInfo, ProcessProfileRule, ProfileRule, ProfileSensor,
+        ProfileWatcherState, ScheduleProfileRule, SensorComparison, SensorProfileRule, Weekday,
+    };
     use pretty_assertions::assert_eq;
-    use std::rc::Rc;
+    use std::{collections::HashMap, rc::Rc, time::Instant};
+
+    fn context<'a>(
+        state: &'a ProfileWatcherState,
+        patterns: &'a RulePatterns,
+        sensors: &'a SensorTracker,
+    ) -> RuleContext<'a> {
+        RuleContext {
+            state,
+            patterns,
+            sensors,
+            gpu_id: None,
+            now: NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn evaluate_gpu_profiles() {
+        let patterns = RulePatterns::default();
+        let sensors = SensorTracker::default();
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProcessInfo {
+                name: "game".into(),
+                cmdline: "".into(),
+                exe: None,
+                cwd: None,
+            },
+        );
+        let ctx = context(&state, &patterns, &sensors);
+
+        let game_rule = ProfileRule::Process(ProcessProfileRule {
+            name: "game".into(),
+            ..Default::default()
+        });
+        let mut profiles: IndexMap<Rc<str>, Profile> = IndexMap::from([
+            (
+                "compute".into(),
+                Profile {
+                    rule: Some(game_rule.clone()),
+                    gpu_ids: vec!["gpu2".to_owned()],
+                    ..Default::default()
+                },
+            ),
+            (
+                "game".into(),
+                Profile {
+                    rule: Some(game_rule),
+                    ..Default::default()
+                },
+            ),
+        ]);
+        let gpu_ids = ["gpu1".to_owned(), "gpu2".to_owned()];
+
+        let expected = ProfileAssignment {
+            default: Some("game".into()),
+            gpus: IndexMap::from([("gpu2".to_owned(), Some("compute".into()))]),
+        };
+        assert_eq!(
+            expected,
+            evaluate_profile_assignment(&ctx, &profiles, &gpu_ids)
+        );
+
+        profiles.shift_remove("compute");
+        let expected = ProfileAssignment {
+            default: Some("game".into()),
+            gpus: IndexMap::new(),
+        };
+        assert_eq!(
+            expected,
+            evaluate_profile_assignment(&ctx, &profiles, &gpu_ids)
+        );
+    }
+
+    #[test]
+    fn evaluate_sensor_rule_per_gpu() {
+        let rule = SensorProfileRule {
+            sensor: ProfileSensor::Temperature,
+            comparison: SensorComparison::Above,
+            threshold: 90.0,
+            ..Default::default()
+        };
+        let stats = |temp| {
+            let mut stats = DeviceStats::default();
+            stats.temps.insert(
+                "edge".to_owned(),
+                Temperature {
+                    current: Some(temp),
+                    crit: None,
+                    crit_hyst: None,
+                },
+            );
+            stats
+        };
+
+        let patterns = RulePatterns::default();
+        let mut sensors = SensorTracker::default();
+        sensors.reload([&ProfileRule::Sensor(rule.clone())]);
+        sensors.update(
+            &HashMap::from([
+                ("gpu1".to_owned(), stats(40.0)),
+                ("gpu2".to_owned(), stats(95.0)),
+            ]),
+            Instant::now(),
+        );
+        let state = ProfileWatcherState::default();
+        let ctx = context(&state, &patterns, &sensors);
+
+        let profiles: IndexMap<Rc<str>, Profile> = IndexMap::from([(
+            "cool".into(),
+            Profile {
+                rule: Some(ProfileRule::Sensor(rule)),
+                ..Default::default()
+            },
+        )]);
+        let gpu_ids = ["gpu1".to_owned(), "gpu2".to_owned()];
+
+        // The rule is only matched on the GPU that is hot
+        let assignment = evaluate_profile_assignment(&ctx, &profiles, &gpu_ids);
+        assert_eq!(None, assignment.profile_for("gpu1"));
+        assert_eq!(Some(&Rc::from("cool")), assignment.profile_for("gpu2"));
+    }
 
     #[test]
     fn evaluate_basic_profile() {
+        let patterns = RulePatterns::default();
+        let sensors = SensorTracker::default();
         let mut state = ProfileWatcherState::default();
         state.push_process(
             1,
             ProcessInfo {
                 name: "game1".into(),
                 cmdline: "".into(),
+                exe: None,
+                cwd: None,
             },
         );
 
@@ -368,6 +807,7 @@ mod tests {
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game1".into(),
                     args: None,
+                    ..Default::default()
                 }),
             ),
             (
@@ -375,13 +815,17 @@ mod tests {
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game2".into(),
                     args: None,
+                    ..Default::default()
                 }),
             ),
         ];
 
         assert_eq!(
             Some(&Rc::from("1")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &context(&state, &patterns, &sensors),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
         );
 
         state.push_process(
@@ -389,11 +833,16 @@ mod tests {
             ProcessInfo {
                 name: "game2".into(),
                 cmdline: "".into(),
+                exe: None,
+                cwd: None,
             },
         );
         assert_eq!(
             Some(&Rc::from("2")),
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &context(&state, &patterns, &sensors),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
         );
 
         state.push_process(
@@ -401,18 +850,179 @@ mod tests {
             ProcessInfo {
                 name: "game3".into(),
                 cmdline: "".into(),
+                exe: None,
+                cwd: None,
             },
         );
         assert_eq!(
             None,
-            evaluate_current_profile(&state, profile_rules.iter().map(|(key, rule)| (key, rule)))
+            evaluate_current_profile(
+                &context(&state, &patterns, &sensors),
+                profile_rules.iter().map(|(key, rule)| (key, rule))
+            )
+        );
+    }
+
+    fn process_rule(name: &str) -> ProfileRule {
+        ProfileRule::Process(ProcessProfileRule {
+            name: name.into(),
+            args: None,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn evaluate_combined_rules() {
+        let patterns = RulePatterns::default();
+        let sensors = SensorTracker::default();
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProcessInfo {
+                name: "blender".into(),
+                cmdline: "".into(),
+                exe: None,
+                cwd: None,
+            },
+        );
+
+        let rule = ProfileRule::All(vec![
+            process_rule("blender"),
+            ProfileRule::Not(Box::new(process_rule("obs"))),
+        ]);
+        assert!(profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &rule
+        ));
+
+        state.push_process(
+            2,
+            ProcessInfo {
+                name: "obs".into(),
+                cmdline: "".into(),
+                exe: None,
+                cwd: None,
+            },
+        );
+        assert!(!profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &rule
+        ));
+
+        let rule = ProfileRule::Any(vec![
+            process_rule("game1"),
+            process_rule("game2"),
+            process_rule("obs"),
+        ]);
+        assert!(profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &rule
+        ));
+
+        assert!(profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &ProfileRule::All(vec![])
+        ));
+        assert!(!profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &ProfileRule::Any(vec![])
+        ));
+    }
+
+    #[test]
+    fn evaluate_pattern_rules() {
+        let mut state = ProfileWatcherState::default();
+        state.push_process(
+            1,
+            ProcessInfo {
+                name: "Cyberpunk2077.exe".into(),
+                cmdline: "Cyberpunk2077.exe --launcher-skip".into(),
+                exe: Some("/usr/bin/wine64-preloader".into()),
+                cwd: Some("/home/user/Games/cyberpunk".into()),
+            },
         );
+
+        let glob_rule = ProfileRule::Process(ProcessProfileRule {
+            name: "*.exe".into(),
+            cwd: Some("/home/*/Games/*".into()),
+            match_mode: Some(MatchMode::Glob),
+            ..Default::default()
+        });
+        let regex_rule = ProfileRule::Process(ProcessProfileRule {
+            name: "^Cyberpunk\\d+".into(),
+            args: Some("--launcher-(skip|run)".into()),
+            exe: Some("wine".into()),
+            match_mode: Some(MatchMode::Regex),
+            ..Default::default()
+        });
+        let exact_rule = ProfileRule::Process(ProcessProfileRule {
+            name: "Cyberpunk2077.exe".into(),
+            exe: Some("/usr/bin/wine".into()),
+            ..Default::default()
+        });
+        let glob_args_rule = ProfileRule::Process(ProcessProfileRule {
+            name: "*.exe".into(),
+            args: Some("--launcher".into()),
+            match_mode: Some(MatchMode::Glob),
+            ..Default::default()
+        });
+
+        let sensors = SensorTracker::default();
+        let mut patterns = RulePatterns::default();
+        patterns.reload([&glob_rule, &regex_rule, &exact_rule, &glob_args_rule]);
+
+        assert!(profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &glob_rule
+        ));
+        assert!(profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &regex_rule
+        ));
+        assert!(!profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &exact_rule
+        ));
+        // Globs have to match the whole value
+        assert!(!profile_rule_matches(
+            &context(&state, &patterns, &sensors),
+            &glob_args_rule
+        ));
+
+        // Patterns are only compiled when the rules are loaded
+        assert!(!profile_rule_matches(
+            &context(&state, &RulePatterns::default(), &sensors),
+            &glob_rule
+        ));
+    }
+
+    #[test]
+    fn evaluate_schedule_rule() {
+        let state = ProfileWatcherState::default();
+        let patterns = RulePatterns::default();
+        let sensors = SensorTracker::default();
+        let rule = ProfileRule::Schedule(ScheduleProfileRule {
+            days: vec![Weekday::Monday],
+            start: "09:00".parse().unwrap(),
+            end: "17:00".parse().unwrap(),
+        });
+
+        // 2024-01-01 was a Monday
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut ctx = context(&state, &patterns, &sensors);
+
+        ctx.now = monday.and_hms_opt(12, 0, 0).unwrap();
+        assert!(profile_rule_matches(&ctx, &rule));
+
+        ctx.now = monday.and_hms_opt(18, 0, 0).unwrap();
+        assert!(!profile_rule_matches(&ctx, &rule));
     }
 }
 
 #[cfg(feature = "bench")]
 mod benches {
-    use super::evaluate_current_profile;
+    use super::{evaluate_current_profile, RuleContext, RulePatterns, SensorTracker};
+    use chrono::NaiveDateTime;
     use divan::Bencher;
     use lact_schema::{ProcessInfo, ProcessProfileRule, ProfileRule, ProfileWatcherState};
     use std::hint::black_box;
@@ -424,7 +1034,15 @@ mod benches {
         for pid in 1..2000 {
             let name = format!("process-{pid}").into();
             let cmdline = format!("{name} arg1 arg2 --arg3").into();
-            state.push_process(pid, ProcessInfo { name, cmdline });
+            state.push_process(
+                pid,
+                ProcessInfo {
+                    name,
+                    cmdline,
+                    exe: None,
+                    cwd: None,
+                },
+            );
         }
 
         let profile_rules = [
@@ -433,6 +1051,7 @@ mod benches {
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game-abc".into(),
                     args: None,
+                    ..Default::default()
                 }),
             ),
             (
@@ -440,13 +1059,24 @@ mod benches {
                 ProfileRule::Process(ProcessProfileRule {
                     name: "game-1034".into(),
                     args: None,
+                    ..Default::default()
                 }),
             ),
         ];
 
+        let patterns = RulePatterns::default();
+        let sensors = SensorTracker::default();
+
         bencher.bench_local(move || {
+            let ctx = RuleContext {
+                state: &state,
+                patterns: &patterns,
+                sensors: &sensors,
+                gpu_id: None,
+                now: NaiveDateTime::default(),
+            };
             evaluate_current_profile(
-                black_box(&state),
+                black_box(&ctx),
                 black_box(profile_rules.iter().map(|(key, rule)| (key, rule))),
             );
         });
@@ -0,0 +1,248 @@
+use crate::config::{Profile, ProfileAssignment};
+use indexmap::IndexMap;
+use lact_schema::ProcessInfo;
+use nix::{
+    sys::signal::{killpg, Signal},
+    unistd::Pid,
+};
+use std::{fmt, process::Stdio, rc::Rc, time::Duration};
+use tokio::{process::Command, time::timeout};
+use tracing::{debug, error, warn};
+
+const HOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Process that matched the rule of the activated profile
+#[derive(Debug, Clone)]
+pub struct HookTrigger {
+    pub pid: i32,
+    pub info: ProcessInfo,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookKind {
+    Activate,
+    Deactivate,
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookKind::Activate => write!(f, "on_activate"),
+            HookKind::Deactivate => write!(f, "on_deactivate"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ProfileHook {
+    pub profile: Rc<str>,
+    pub kind: HookKind,
+    pub command: String,
+    pub env: Vec<(&'static str, String)>,
+}
+
+/// Collects the hooks to run when switching from the `old` to the `new` profiles.
+///
+/// Deactivation hooks only run for profiles that are not active on any GPU anymore,
+/// and activation hooks only for profiles that were not active on any GPU before.
+pub fn collect_hooks(
+    profiles: &IndexMap<Rc<str>, Profile>,
+    old: &ProfileAssignment,
+    new: &ProfileAssignment,
+    trigger: Option<&HookTrigger>,
+) -> Vec<ProfileHook> {
+    let mut deactivate_hooks = Vec::new();
+    let mut activate_hooks = Vec::new();
+
+    for (old_profile, new_profile) in old.changes(new) {
+        let mut env = vec![
+            ("LACT_OLD_PROFILE", profile_env(old_profile)),
+            ("LACT_NEW_PROFILE", profile_env(new_profile)),
+        ];
+        if let Some(trigger) = trigger {
+            env.push(("LACT_TRIGGER_PID", trigger.pid.to_string()));
+            env.push(("LACT_TRIGGER_NAME", trigger.info.name.to_string()));
+            env.push(("LACT_TRIGGER_CMDLINE", trigger.info.cmdline.to_string()));
+        }
+
+        let changed_profiles = [
+            (old_profile, HookKind::Deactivate),
+            (new_profile, HookKind::Activate),
+        ];
+        for (name, kind) in changed_profiles {
+            let Some(name) = name else {
+                continue;
+            };
+            let (hooks, still_active) = match kind {
+                HookKind::Deactivate => (&mut deactivate_hooks, new.contains(name)),
+                HookKind::Activate => (&mut activate_hooks, old.contains(name)),
+            };
+            if still_active || hooks.iter().any(|hook| hook.profile == *name) {
+                continue;
+            }
+
+            let command = profiles.get(name).and_then(|profile| match kind {
+                HookKind::Activate => profile.on_activate.as_ref(),
+                HookKind::Deactivate => profile.on_deactivate.as_ref(),
+            });
+            if let Some(command) = command {
+                hooks.push(ProfileHook {
+                    profile: name.clone(),
+                    kind,
+                    command: command.clone(),
+                    env: env.clone(),
+                });
+            }
+        }
+    }
+
+    deactivate_hooks.extend(activate_hooks);
+    deactivate_hooks
+}
+
+/// Runs the hooks one after another in the background.
+/// Failures are only logged, as they should not affect the profile switch.
+pub fn run_hooks(hooks: Vec<ProfileHook>) {
+    if hooks.is_empty() {
+        return;
+    }
+
+    tokio::task::spawn_local(async move {
+        for hook in hooks {
+            run_hook(&hook).await;
+        }
+    });
+}
+
+async fn run_hook(hook: &ProfileHook) {
+    let ProfileHook {
+        profile,
+        kind,
+        command,
+        env,
+    } = hook;
+    debug!("running {kind} hook of profile '{profile}': {command}");
+
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(key, value)| (*key, value)))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // Everything started by the hook is in its own process group, so it can be stopped together
+        .process_group(0)
+        .kill_on_drop(true)
+        .spawn();
+    let child = match child {
+        Ok(child) => child,
+        Err(err) => {
+            error!("could not run {kind} hook of profile '{profile}': {err}");
+            return;
+        }
+    };
+    let process_group = child.id();
+
+    match timeout(HOOK_TIMEOUT, child.wait_with_output()).await {
+        Ok(Ok(output)) if output.status.success() => {
+            debug!("{kind} hook of profile '{profile}' finished");
+        }
+        Ok(Ok(output)) => {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            warn!(
+                "{kind} hook of profile '{profile}' exited with {}: {}",
+                output.status,
+                stderr.trim()
+            );
+        }
+        Ok(Err(err)) => error!("could not run {kind} hook of profile '{profile}': {err}"),
+        Err(_) => {
+            error!("{kind} hook of profile '{profile}' timed out after {HOOK_TIMEOUT:?}");
+            if let Some(process_group) = process_group {
+                kill_process_group(process_group);
+            }
+        }
+    }
+}
+
+fn kill_process_group(process_group: u32) {
+    let Ok(pgid) = i32::try_from(process_group) else {
+        return;
+    };
+    if let Err(err) = killpg(Pid::from_raw(pgid), Signal::SIGKILL) {
+        debug!("could not kill hook process group {pgid}: {err}");
+    }
+}
+
+/// The default profile is represented by an empty value
+fn profile_env(profile: Option<&Rc<str>>) -> String {
+    profile.map(Rc::to_string).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collect_hooks, HookKind, HookTrigger};
+    use crate::config::{Profile, ProfileAssignment};
+    use indexmap::IndexMap;
+    use lact_schema::ProcessInfo;
+    use pretty_assertions::assert_eq;
+    use std::rc::Rc;
+
+    #[test]
+    fn collect_switch_hooks() {
+        let profile = |name: &str| Profile {
+            on_activate: Some(format!("activate {name}")),
+            on_deactivate: Some(format!("deactivate {name}")),
+            ..Default::default()
+        };
+        let profiles: IndexMap<Rc<str>, Profile> = IndexMap::from([
+            ("desktop".into(), profile("desktop")),
+            ("game".into(), profile("game")),
+            ("render".into(), Profile::default()),
+        ]);
+        let trigger = HookTrigger {
+            pid: 1234,
+            info: ProcessInfo {
+                name: "game".into(),
+                cmdline: "game --fullscreen".into(),
+                exe: None,
+                cwd: None,
+            },
+        };
+
+        let old = ProfileAssignment {
+            default: Some("desktop".into()),
+            gpus: IndexMap::from([("gpu2".to_owned(), Some("render".into()))]),
+        };
+        let new = ProfileAssignment {
+            default: Some("game".into()),
+            gpus: IndexMap::from([("gpu2".to_owned(), Some("render".into()))]),
+        };
+        let hooks = collect_hooks(&profiles, &old, &new, Some(&trigger));
+
+        let summary: Vec<_> = hooks
+            .iter()
+            .map(|hook| (hook.kind, hook.command.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                (HookKind::Deactivate, "deactivate desktop"),
+                (HookKind::Activate, "activate game"),
+            ],
+            summary
+        );
+        assert!(hooks[1]
+            .env
+            .contains(&("LACT_OLD_PROFILE", "desktop".to_owned())));
+        assert!(hooks[1]
+            .env
+            .contains(&("LACT_TRIGGER_PID", "1234".to_owned())));
+
+        // Profiles that are still active on another GPU are not deactivated
+        let new = ProfileAssignment {
+            default: None,
+            gpus: IndexMap::from([("gpu2".to_owned(), Some("desktop".into()))]),
+        };
+        let hooks = collect_hooks(&profiles, &old, &new, None);
+        assert!(hooks.is_empty());
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::Context;
+use lact_schema::{MatchMode, ProcessProfileRule, ProfileRule};
+use regex::Regex;
+use std::collections::HashMap;
+use tracing::error;
+
+/// Compiled glob and regex patterns used by profile rules.
+///
+/// Patterns are compiled when the rules are loaded or edited, so that evaluating the rules
+/// on every process event only has to look them up.
+#[derive(Default)]
+pub struct RulePatterns {
+    globs: HashMap<Box<str>, Regex>,
+    regexes: HashMap<Box<str>, Regex>,
+}
+
+impl RulePatterns {
+    /// Replaces all of the patterns with the ones used in the given rules.
+    /// Rules with invalid patterns are logged and will never match.
+    pub fn reload<'a>(&mut self, rules: impl IntoIterator<Item = &'a ProfileRule>) {
+        self.globs.clear();
+        self.regexes.clear();
+
+        for rule in rules {
+            if let Err(err) = self.add_rule(rule) {
+                error!("invalid profile rule '{rule}': {err:#}");
+            }
+        }
+    }
+
+    /// Compiles the patterns of the given rule that are not known yet
+    pub fn add_rule(&mut self, rule: &ProfileRule) -> anyhow::Result<()> {
+        match rule {
+            ProfileRule::Process(process_rule) | ProfileRule::Gamemode(Some(process_rule)) => {
+                self.add_process_rule(process_rule)
+            }
+            ProfileRule::Gamemode(None)
+            | ProfileRule::Schedule(_)
+            | ProfileRule::PowerSource(_)
+            | ProfileRule::Sensor(_) => Ok(()),
+            ProfileRule::All(rules) | ProfileRule::Any(rules) => {
+                for rule in rules {
+                    self.add_rule(rule)?;
+                }
+                Ok(())
+            }
+            ProfileRule::Not(rule) => self.add_rule(rule),
+        }
+    }
+
+    fn add_process_rule(&mut self, rule: &ProcessProfileRule) -> anyhow::Result<()> {
+        let mode = rule.match_mode.unwrap_or_default();
+        let patterns = [
+            Some(rule.name.as_ref()),
+            rule.args.as_deref(),
+            rule.exe.as_deref(),
+            rule.cwd.as_deref(),
+        ];
+
+        for pattern in patterns.into_iter().flatten() {
+            match mode {
+                MatchMode::Exact => (),
+                MatchMode::Glob => {
+                    if !self.globs.contains_key(pattern) {
+                        let regex = Regex::new(&glob_to_regex(pattern))
+                            .with_context(|| format!("Invalid glob pattern '{pattern}'"))?;
+                        self.globs.insert(pattern.into(), regex);
+                    }
+                }
+                MatchMode::Regex => {
+                    if !self.regexes.contains_key(pattern) {
+                        let regex = Regex::new(pattern)
+                            .with_context(|| format!("Invalid regular expression '{pattern}'"))?;
+                        self.regexes.insert(pattern.into(), regex);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the value matches the pattern.
+    /// In the exact mode, `substring` allows the pattern to only be contained in the value.
+    pub fn matches(&self, mode: MatchMode, pattern: &str, value: &str, substring: bool) -> bool {
+        match mode {
+            MatchMode::Exact if substring => value.contains(pattern),
+            MatchMode::Exact => value == pattern,
+            MatchMode::Glob => self
+                .globs
+                .get(pattern)
+                .is_some_and(|regex| regex.is_match(value)),
+            MatchMode::Regex => self
+                .regexes
+                .get(pattern)
+                .is_some_and(|regex| regex.is_match(value)),
+        }
+    }
+}
+
+/// Converts a glob with `*` and `?` wildcards into a regex matching the whole value
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::with_capacity(glob.len() + 2);
+    regex.push('^');
+    for c in glob.chars() {
+        match c {
+            '*' => regex.push_str(".*"),
+            '?' => regex.push('.'),
+            _ => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_to_regex, RulePatterns};
+    use lact_schema::{MatchMode, ProcessProfileRule, ProfileRule};
+
+    #[test]
+    fn convert_glob() {
+        assert_eq!("^.*\\.exe$", glob_to_regex("*.exe"));
+        assert_eq!("^game.\\+$", glob_to_regex("game?+"));
+    }
+
+    #[test]
+    fn regex_matches_part_of_value() {
+        let rule = |name: &str, match_mode| {
+            ProfileRule::Process(ProcessProfileRule {
+                name: name.into(),
+                match_mode: Some(match_mode),
+                ..Default::default()
+            })
+        };
+
+        let mut patterns = RulePatterns::default();
+        for rule in [
+            rule("game", MatchMode::Glob),
+            rule("game", MatchMode::Regex),
+            rule("^game$", MatchMode::Regex),
+        ] {
+            patterns.add_rule(&rule).unwrap();
+        }
+
+        // Unlike globs, regular expressions are not anchored to the whole value
+        assert!(!patterns.matches(MatchMode::Glob, "game", "game-launcher", false));
+        assert!(patterns.matches(MatchMode::Regex, "game", "game-launcher", false));
+        assert!(!patterns.matches(MatchMode::Regex, "^game$", "game-launcher", false));
+        assert!(patterns.matches(MatchMode::Regex, "^game$", "game", false));
+    }
+
+    #[test]
+    fn invalid_regex_is_rejected() {
+        let rule = ProfileRule::Process(ProcessProfileRule {
+            name: "game(".into(),
+            match_mode: Some(MatchMode::Regex),
+            ..Default::default()
+        });
+
+        let mut patterns = RulePatterns::default();
+        assert!(patterns.add_rule(&rule).is_err());
+        assert!(!patterns.matches(MatchMode::Regex, "game(", "game(", false));
+    }
+}
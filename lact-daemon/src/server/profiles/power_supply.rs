@@ -0,0 +1,153 @@
+use lact_schema::{PowerSource, PowerSourceProfileRule, PowerSupplyState};
+use std::{fs, path::Path};
+use tracing::error;
+
+pub const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+
+pub fn read_state(path: &Path) -> PowerSupplyState {
+    let mut ac_online = false;
+    let mut capacities = Vec::new();
+
+    let entries = fs::read_dir(path)
+        .inspect_err(|err| error!("could not read {path:?}: {err}"))
+        .into_iter()
+        .flatten()
+        .flatten();
+
+    for entry in entries {
+        let supply_path = entry.path();
+        let read_value = |name: &str| {
+            fs::read_to_string(supply_path.join(name))
+                .ok()
+                .map(|value| value.trim().to_owned())
+        };
+
+        // Batteries of peripherals such as mice are not used to power the system
+        if read_value("scope").as_deref() == Some("Device") {
+            continue;
+        }
+
+        match read_value("type").as_deref() {
+            Some("Battery") => {
+                if let Some(capacity) =
+                    read_value("capacity").and_then(|value| value.parse::<u8>().ok())
+                {
+                    capacities.push(capacity);
+                }
+            }
+            Some("Mains" | "USB") => {
+                if read_value("online").as_deref() == Some("1") {
+                    ac_online = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let battery_capacity = if capacities.is_empty() {
+        None
+    } else {
+        let total: usize = capacities
+            .iter()
+            .map(|capacity| usize::from(*capacity))
+            .sum();
+        u8::try_from(total / capacities.len()).ok()
+    };
+
+    PowerSupplyState {
+        ac_online: ac_online || capacities.is_empty(),
+        battery_capacity,
+    }
+}
+
+pub fn power_source_matches(rule: &PowerSourceProfileRule, state: &PowerSupplyState) -> bool {
+    let source_matches = match rule.source {
+        Some(PowerSource::Ac) => state.ac_online,
+        Some(PowerSource::Battery) => !state.ac_online,
+        None => true,
+    };
+
+    let capacity_matches = |threshold: Option<u8>, check: fn(u8, u8) -> bool| match threshold {
+        Some(threshold) => state
+            .battery_capacity
+            .is_some_and(|capacity| check(capacity, threshold)),
+        None => true,
+    };
+
+    source_matches
+        && capacity_matches(rule.min_battery, |capacity, min| capacity >= min)
+        && capacity_matches(rule.max_battery, |capacity, max| capacity <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{power_source_matches, read_state};
+    use lact_schema::{PowerSource, PowerSourceProfileRule, PowerSupplyState};
+    use pretty_assertions::assert_eq;
+    use std::path::{Path, PathBuf};
+
+    fn fixture_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/tests/power_supply")
+            .join(name)
+    }
+
+    #[test]
+    fn read_ac_state() {
+        let state = read_state(&fixture_path("ac"));
+        let expected = PowerSupplyState {
+            ac_online: true,
+            battery_capacity: Some(80),
+        };
+        assert_eq!(expected, state);
+    }
+
+    #[test]
+    fn read_battery_state() {
+        let state = read_state(&fixture_path("battery"));
+        let expected = PowerSupplyState {
+            ac_online: false,
+            battery_capacity: Some(35),
+        };
+        assert_eq!(expected, state);
+    }
+
+    #[test]
+    fn read_missing_state() {
+        let state = read_state(Path::new("/nonexistent/power_supply"));
+        let expected = PowerSupplyState {
+            ac_online: true,
+            battery_capacity: None,
+        };
+        assert_eq!(expected, state);
+    }
+
+    #[test]
+    fn match_power_source() {
+        let ac_state = read_state(&fixture_path("ac"));
+        let battery_state = read_state(&fixture_path("battery"));
+
+        let low_battery_rule = PowerSourceProfileRule {
+            source: Some(PowerSource::Battery),
+            min_battery: None,
+            max_battery: Some(40),
+        };
+        assert!(power_source_matches(&low_battery_rule, &battery_state));
+        assert!(!power_source_matches(&low_battery_rule, &ac_state));
+
+        let charged_rule = PowerSourceProfileRule {
+            source: None,
+            min_battery: Some(50),
+            max_battery: None,
+        };
+        assert!(power_source_matches(&charged_rule, &ac_state));
+        assert!(!power_source_matches(&charged_rule, &battery_state));
+        assert!(!power_source_matches(
+            &charged_rule,
+            &PowerSupplyState {
+                ac_online: true,
+                battery_capacity: None,
+            }
+        ));
+    }
+}
@@ -1,7 +1,7 @@
 use super::ProfileWatcherEvent;
 use copes::{io::connector::ProcessEventsConnector, solver::PID};
 use lact_schema::{ProcessInfo, ProfileWatcherState};
-use std::fs;
+use std::{fs, path::Path};
 use tokio::sync::mpsc;
 use tracing::{debug, error};
 
@@ -67,11 +67,20 @@ pub fn get_pid_info(pid: PID) -> std::io::Result<ProcessInfo> {
         .to_string()
         .into();
 
+    let proc_path = Path::new("/proc").join(pid.to_string());
+
     Ok(ProcessInfo {
         name,
         cmdline: cmdline
             .to_string()
             .trim_matches(|c| c == '[' || c == ']')
             .into(),
+        exe: read_link_str(&proc_path.join("exe")),
+        cwd: read_link_str(&proc_path.join("cwd")),
     })
 }
+
+fn read_link_str(path: &Path) -> Option<Box<str>> {
+    let target = fs::read_link(path).ok()?;
+    target.to_str().map(Into::into)
+}
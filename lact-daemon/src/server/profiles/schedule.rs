@@ -0,0 +1,148 @@
+use chrono::{Datelike, Days, NaiveDateTime, NaiveTime};
+use lact_schema::{ProfileRule, ScheduleProfileRule, TimeOfDay, Weekday};
+
+pub fn schedule_matches(rule: &ScheduleProfileRule, now: NaiveDateTime) -> bool {
+    let time = now.time();
+    let start = naive_time(rule.start);
+    let end = naive_time(rule.end);
+
+    let today = now.weekday().num_days_from_monday() as usize;
+    let yesterday = (today + 6) % 7;
+    let day_matches = |day: usize| rule.days.is_empty() || rule.days.contains(&Weekday::ALL[day]);
+
+    if start < end {
+        day_matches(today) && start <= time && time < end
+    } else {
+        // The window continues into the next day, or lasts the whole day if it ends when it starts
+        (day_matches(today) && time >= start) || (day_matches(yesterday) && time < end)
+    }
+}
+
+/// Returns the next point in time after `now` when the result of a schedule rule can change
+pub fn next_boundary<'a>(
+    rules: impl IntoIterator<Item = &'a ProfileRule>,
+    now: NaiveDateTime,
+) -> Option<NaiveDateTime> {
+    let mut schedules = Vec::new();
+    for rule in rules {
+        collect_schedules(rule, &mut schedules);
+    }
+
+    schedules
+        .into_iter()
+        .flat_map(|rule| [rule.start, rule.end])
+        .map(|time| {
+            let time = naive_time(time);
+            if time > now.time() {
+                now.date().and_time(time)
+            } else {
+                (now.date() + Days::new(1)).and_time(time)
+            }
+        })
+        .min()
+}
+
+fn collect_schedules<'a>(rule: &'a ProfileRule, schedules: &mut Vec<&'a ScheduleProfileRule>) {
+    match rule {
+        ProfileRule::Schedule(schedule) => schedules.push(schedule),
+        ProfileRule::All(rules) | ProfileRule::Any(rules) => {
+            for rule in rules {
+                collect_schedules(rule, schedules);
+            }
+        }
+        ProfileRule::Not(rule) => collect_schedules(rule, schedules),
+        ProfileRule::Process(_)
+        | ProfileRule::Gamemode(_)
+        | ProfileRule::PowerSource(_)
+        | ProfileRule::Sensor(_) => (),
+    }
+}
+
+fn naive_time(time: TimeOfDay) -> NaiveTime {
+    NaiveTime::from_hms_opt(time.hour().into(), time.minute().into(), 0)
+        .expect("Time of day should always be valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{next_boundary, schedule_matches};
+    use chrono::{NaiveDate, NaiveDateTime};
+    use lact_schema::{ProfileRule, ScheduleProfileRule, TimeOfDay, Weekday};
+
+    fn rule(days: Vec<Weekday>, start: &str, end: &str) -> ScheduleProfileRule {
+        ScheduleProfileRule {
+            days,
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+        }
+    }
+
+    /// 2024-01-01 was a Monday
+    fn datetime(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn day_window() {
+        let rule = rule(vec![Weekday::Monday, Weekday::Friday], "09:00", "17:30");
+
+        assert!(schedule_matches(&rule, datetime(1, 9, 0)));
+        assert!(schedule_matches(&rule, datetime(5, 17, 29)));
+        assert!(!schedule_matches(&rule, datetime(1, 17, 30)));
+        assert!(!schedule_matches(&rule, datetime(1, 8, 59)));
+        assert!(!schedule_matches(&rule, datetime(2, 12, 0)));
+    }
+
+    #[test]
+    fn overnight_window() {
+        let rule = rule(vec![Weekday::Friday], "22:00", "06:00");
+
+        assert!(schedule_matches(&rule, datetime(5, 23, 0)));
+        assert!(schedule_matches(&rule, datetime(6, 5, 59)));
+        assert!(!schedule_matches(&rule, datetime(6, 22, 0)));
+        assert!(!schedule_matches(&rule, datetime(5, 5, 0)));
+    }
+
+    #[test]
+    fn whole_day_window() {
+        let rule = rule(vec![Weekday::Sunday], "00:00", "00:00");
+
+        assert!(schedule_matches(&rule, datetime(7, 0, 0)));
+        assert!(schedule_matches(&rule, datetime(7, 23, 59)));
+        assert!(!schedule_matches(&rule, datetime(8, 0, 0)));
+        assert!(!schedule_matches(&rule, datetime(6, 23, 59)));
+    }
+
+    #[test]
+    fn next_schedule_boundary() {
+        let rules = [
+            ProfileRule::Not(Box::new(ProfileRule::Schedule(rule(
+                vec![],
+                "09:00",
+                "17:00",
+            )))),
+            ProfileRule::Schedule(ScheduleProfileRule {
+                days: vec![Weekday::Saturday],
+                start: TimeOfDay::new(12, 15).unwrap(),
+                end: TimeOfDay::new(13, 0).unwrap(),
+            }),
+        ];
+
+        assert_eq!(
+            Some(datetime(1, 9, 0)),
+            next_boundary(&rules, datetime(1, 7, 0))
+        );
+        assert_eq!(
+            Some(datetime(1, 12, 15)),
+            next_boundary(&rules, datetime(1, 9, 0))
+        );
+        assert_eq!(
+            Some(datetime(2, 9, 0)),
+            next_boundary(&rules, datetime(1, 17, 0))
+        );
+        assert_eq!(None, next_boundary(std::iter::empty(), datetime(1, 17, 0)));
+    }
+}
@@ -0,0 +1,286 @@
+use lact_schema::{DeviceStats, ProfileRule, ProfileSensor, SensorComparison, SensorProfileRule};
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+/// Tracks the state of sensor rules between stats samples,
+/// as they depend on how long a value has stayed past the threshold.
+#[derive(Default)]
+pub struct SensorTracker {
+    rules: Vec<TrackedRule>,
+}
+
+struct TrackedRule {
+    rule: SensorProfileRule,
+    /// State of the rule on each GPU it is evaluated for.
+    /// Rules without a GPU id are evaluated for every GPU separately.
+    gpus: HashMap<String, RuleState>,
+}
+
+#[derive(Default)]
+struct RuleState {
+    active: bool,
+    /// When the value has started to indicate that the state should be changed
+    pending_since: Option<Instant>,
+}
+
+impl RuleState {
+    /// Returns `true` if the rule has started or stopped matching
+    fn update(&mut self, rule: &SensorProfileRule, value: Option<f64>, now: Instant) -> bool {
+        let should_change = value.is_some_and(|value| match (rule.comparison, self.active) {
+            (SensorComparison::Above, false) => value > rule.threshold,
+            (SensorComparison::Below, false) => value < rule.threshold,
+            // Going back past the threshold needs to overcome the hysteresis
+            (SensorComparison::Above, true) => value < rule.threshold - rule.hysteresis,
+            (SensorComparison::Below, true) => value > rule.threshold + rule.hysteresis,
+        });
+
+        if should_change {
+            let pending_since = *self.pending_since.get_or_insert(now);
+            if now.duration_since(pending_since) >= Duration::from_secs(rule.duration_secs) {
+                self.active = !self.active;
+                self.pending_since = None;
+                return true;
+            }
+        } else {
+            self.pending_since = None;
+        }
+        false
+    }
+}
+
+impl SensorTracker {
+    /// Replaces the tracked rules with the sensor rules found in the given rules.
+    /// The state of the rules that were already tracked is kept.
+    pub fn reload<'a>(&mut self, rules: impl IntoIterator<Item = &'a ProfileRule>) {
+        let mut sensor_rules = Vec::new();
+        for rule in rules {
+            collect_sensor_rules(rule, &mut sensor_rules);
+        }
+
+        let mut old_rules = std::mem::take(&mut self.rules);
+        for rule in sensor_rules {
+            if self.rules.iter().any(|tracked| tracked.rule == *rule) {
+                continue;
+            }
+
+            let tracked = match old_rules.iter().position(|tracked| tracked.rule == *rule) {
+                Some(index) => old_rules.swap_remove(index),
+                None => TrackedRule {
+                    rule: rule.clone(),
+                    gpus: HashMap::new(),
+                },
+            };
+            self.rules.push(tracked);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Ids of the GPUs which need to be sampled, out of all of the GPUs in `gpu_ids`
+    pub fn gpu_ids(&self, gpu_ids: &[String]) -> Vec<String> {
+        let mut ids = Vec::new();
+        for tracked in &self.rules {
+            match &tracked.rule.gpu_id {
+                Some(id) => ids.push(id.clone()),
+                None => ids.extend_from_slice(gpu_ids),
+            }
+        }
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Whether there are rules without a GPU id, which can match on some GPUs but not others
+    pub fn has_rules_for_every_gpu(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|tracked| tracked.rule.gpu_id.is_none())
+    }
+
+    /// Updates the rule states with new stats of each GPU.
+    /// Returns `true` if any rule has started or stopped matching.
+    pub fn update(&mut self, stats: &HashMap<String, DeviceStats>, now: Instant) -> bool {
+        let mut changed = false;
+
+        for TrackedRule { rule, gpus } in &mut self.rules {
+            match &rule.gpu_id {
+                Some(gpu_id) => {
+                    let value = stats
+                        .get(gpu_id)
+                        .and_then(|stats| sensor_value(rule, stats));
+                    let state = gpus.entry(gpu_id.clone()).or_default();
+                    changed |= state.update(rule, value, now);
+                }
+                None => {
+                    for (gpu_id, stats) in stats {
+                        let value = sensor_value(rule, stats);
+                        let state = gpus.entry(gpu_id.clone()).or_default();
+                        changed |= state.update(rule, value, now);
+                    }
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Checks if the rule matches on the given GPU.
+    /// Rules with their own GPU id always use that GPU, and without a given GPU the rule can match on any GPU.
+    pub fn is_active(&self, rule: &SensorProfileRule, gpu_id: Option<&str>) -> bool {
+        let Some(tracked) = self.rules.iter().find(|tracked| tracked.rule == *rule) else {
+            return false;
+        };
+
+        match rule.gpu_id.as_deref().or(gpu_id) {
+            Some(gpu_id) => tracked.gpus.get(gpu_id).is_some_and(|state| state.active),
+            None => tracked.gpus.values().any(|state| state.active),
+        }
+    }
+}
+
+fn sensor_value(rule: &SensorProfileRule, stats: &DeviceStats) -> Option<f64> {
+    match rule.sensor {
+        ProfileSensor::Temperature => {
+            let temperature = match &rule.temperature_key {
+                Some(key) => stats.temps.get(key)?.current,
+                None => stats
+                    .temps
+                    .values()
+                    .filter_map(|temp| temp.current)
+                    .max_by(f32::total_cmp),
+            };
+            temperature.map(f64::from)
+        }
+        ProfileSensor::BusyPercent => stats.busy_percent.map(f64::from),
+        ProfileSensor::PowerDraw => stats.power.average.or(stats.power.current),
+    }
+}
+
+fn collect_sensor_rules<'a>(rule: &'a ProfileRule, sensor_rules: &mut Vec<&'a SensorProfileRule>) {
+    match rule {
+        ProfileRule::Sensor(sensor_rule) => sensor_rules.push(sensor_rule),
+        ProfileRule::All(rules) | ProfileRule::Any(rules) => {
+            for rule in rules {
+                collect_sensor_rules(rule, sensor_rules);
+            }
+        }
+        ProfileRule::Not(rule) => collect_sensor_rules(rule, sensor_rules),
+        ProfileRule::Process(_)
+        | ProfileRule::Gamemode(_)
+        | ProfileRule::Schedule(_)
+        | ProfileRule::PowerSource(_) => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SensorTracker;
+    use amdgpu_sysfs::hw_mon::Temperature;
+    use lact_schema::{
+        DeviceStats, ProfileRule, ProfileSensor, SensorComparison, SensorProfileRule,
+    };
+    use std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    };
+
+    fn stats(junction: f32) -> HashMap<String, DeviceStats> {
+        let mut stats = DeviceStats::default();
+        stats.temps.insert(
+            "junction".to_owned(),
+            Temperature {
+                current: Some(junction),
+                crit: None,
+                crit_hyst: None,
+            },
+        );
+        HashMap::from([("gpu".to_owned(), stats)])
+    }
+
+    #[test]
+    fn sensor_rule_duration_and_hysteresis() {
+        let rule = SensorProfileRule {
+            gpu_id: None,
+            sensor: ProfileSensor::Temperature,
+            temperature_key: Some("junction".to_owned()),
+            comparison: SensorComparison::Above,
+            threshold: 90.0,
+            duration_secs: 10,
+            hysteresis: 5.0,
+        };
+        let mut tracker = SensorTracker::default();
+        tracker.reload([&ProfileRule::Sensor(rule.clone())]);
+        assert_eq!(vec!["gpu".to_owned()], tracker.gpu_ids(&["gpu".to_owned()]));
+
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert!(!tracker.update(&stats(95.0), at(0)));
+        // A short drop resets the duration
+        assert!(!tracker.update(&stats(80.0), at(5)));
+        assert!(!tracker.update(&stats(95.0), at(6)));
+        assert!(!tracker.update(&stats(95.0), at(15)));
+        assert!(!tracker.is_active(&rule, Some("gpu")));
+
+        assert!(tracker.update(&stats(95.0), at(16)));
+        assert!(tracker.is_active(&rule, Some("gpu")));
+
+        // Dropping below the threshold but within the hysteresis keeps the rule active
+        assert!(!tracker.update(&stats(87.0), at(20)));
+        assert!(!tracker.update(&stats(87.0), at(40)));
+        assert!(tracker.is_active(&rule, Some("gpu")));
+
+        assert!(!tracker.update(&stats(80.0), at(41)));
+        assert!(tracker.update(&stats(80.0), at(51)));
+        assert!(!tracker.is_active(&rule, Some("gpu")));
+    }
+
+    #[test]
+    fn reload_keeps_state() {
+        let rule = SensorProfileRule {
+            sensor: ProfileSensor::Temperature,
+            comparison: SensorComparison::Below,
+            threshold: 50.0,
+            ..Default::default()
+        };
+        let mut tracker = SensorTracker::default();
+        tracker.reload([&ProfileRule::Sensor(rule.clone())]);
+        assert!(tracker.update(&stats(40.0), Instant::now()));
+
+        let combined_rule = ProfileRule::Not(Box::new(ProfileRule::Sensor(rule.clone())));
+        tracker.reload([&combined_rule]);
+        assert!(tracker.is_active(&rule, Some("gpu")));
+
+        tracker.reload(std::iter::empty());
+        assert!(tracker.is_empty());
+    }
+
+    #[test]
+    fn rule_without_gpu_id_is_tracked_per_gpu() {
+        let rule = SensorProfileRule {
+            sensor: ProfileSensor::Temperature,
+            comparison: SensorComparison::Above,
+            threshold: 90.0,
+            ..Default::default()
+        };
+        let mut tracker = SensorTracker::default();
+        tracker.reload([&ProfileRule::Sensor(rule.clone())]);
+        let gpu_ids = ["gpu1".to_owned(), "gpu2".to_owned()];
+        assert_eq!(gpu_ids.to_vec(), tracker.gpu_ids(&gpu_ids));
+
+        let mut stats = stats(40.0);
+        let cool = stats.remove("gpu").unwrap();
+        let mut hot = cool.clone();
+        hot.temps.get_mut("junction").unwrap().current = Some(95.0);
+        let stats = HashMap::from([("gpu1".to_owned(), cool), ("gpu2".to_owned(), hot)]);
+
+        assert!(tracker.update(&stats, Instant::now()));
+        assert!(!tracker.is_active(&rule, Some("gpu1")));
+        assert!(tracker.is_active(&rule, Some("gpu2")));
+        assert!(tracker.is_active(&rule, None));
+    }
+}
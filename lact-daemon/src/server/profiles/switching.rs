@@ -0,0 +1,326 @@
+use crate::config::{Profile, ProfileAssignment};
+use indexmap::IndexMap;
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// Delays automatic profile switches so that short-lived rule matches don't cause switching back and forth
+pub struct SwitchDebouncer<T> {
+    /// The active profiles and when they were activated, if that is known
+    active: Option<(T, Option<Instant>)>,
+    /// The profiles that the rules evaluate to and since when
+    pending: Option<(T, Instant)>,
+    /// When the pending switch can happen
+    deadline: Option<Instant>,
+}
+
+impl<T> Default for SwitchDebouncer<T> {
+    fn default() -> Self {
+        Self {
+            active: None,
+            pending: None,
+            deadline: None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SwitchDecision<T> {
+    Keep,
+    Switch(T),
+    /// The switch can happen after this long if the rules still evaluate to the same profile
+    Wait(Duration),
+}
+
+impl<T: PartialEq + Clone> SwitchDebouncer<T> {
+    /// Decides whether to switch from `current` to the `evaluated` profile.
+    ///
+    /// `activation_delay` is how long the rules of the evaluated profiles need to keep matching,
+    /// and `min_active` is how long the current profiles need to stay active before switching away from them.
+    pub fn decide(
+        &mut self,
+        current: &T,
+        evaluated: &T,
+        activation_delay: Duration,
+        min_active: Duration,
+        now: Instant,
+    ) -> SwitchDecision<T> {
+        // The profile can also be changed outside of the watcher.
+        // Profiles that were already active when the debouncer was created can be switched away from right away.
+        let active_since = match &self.active {
+            Some((active, since)) if active == current => *since,
+            Some(_) => {
+                self.active = Some((current.clone(), Some(now)));
+                Some(now)
+            }
+            None => {
+                self.active = Some((current.clone(), None));
+                None
+            }
+        };
+
+        self.deadline = None;
+
+        if evaluated == current {
+            self.pending = None;
+            return SwitchDecision::Keep;
+        }
+
+        let pending_since = match &self.pending {
+            Some((pending, since)) if pending == evaluated => *since,
+            _ => {
+                self.pending = Some((evaluated.clone(), now));
+                now
+            }
+        };
+
+        let active_for = active_since.map_or(min_active, |since| now.duration_since(since));
+        let wait = activation_delay
+            .saturating_sub(now.duration_since(pending_since))
+            .max(min_active.saturating_sub(active_for));
+
+        if wait.is_zero() {
+            self.pending = None;
+            self.active = Some((evaluated.clone(), Some(now)));
+            SwitchDecision::Switch(evaluated.clone())
+        } else {
+            self.deadline = Some(now + wait);
+            SwitchDecision::Wait(wait)
+        }
+    }
+
+    /// When the rules should be evaluated again to make the pending switch
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+/// Debounces the profile of every GPU separately,
+/// so that a switch on one GPU doesn't affect the delays of the other GPUs
+#[derive(Default)]
+pub struct ProfileSwitchDebouncer {
+    gpus: HashMap<String, SwitchDebouncer<Option<Rc<str>>>>,
+}
+
+impl ProfileSwitchDebouncer {
+    /// Decides which GPUs switch from the `current` to the `evaluated` assignment.
+    /// When only some of the GPUs can switch, the other GPUs keep their current profiles in the returned assignment.
+    pub fn decide(
+        &mut self,
+        current: &ProfileAssignment,
+        evaluated: &ProfileAssignment,
+        gpu_ids: &[String],
+        profiles: &IndexMap<Rc<str>, Profile>,
+        now: Instant,
+    ) -> SwitchDecision<ProfileAssignment> {
+        let profile_secs = |name: Option<&Rc<str>>, secs: fn(&Profile) -> Option<u64>| {
+            let secs = name
+                .and_then(|name| profiles.get(name))
+                .and_then(secs)
+                .unwrap_or(0);
+            Duration::from_secs(secs)
+        };
+
+        let mut gpus = IndexMap::with_capacity(gpu_ids.len());
+        let mut switched = false;
+        let mut wait: Option<Duration> = None;
+
+        for gpu_id in gpu_ids {
+            let current_profile = current.profile_for(gpu_id).cloned();
+            let evaluated_profile = evaluated.profile_for(gpu_id).cloned();
+
+            let decision = self.gpus.entry(gpu_id.clone()).or_default().decide(
+                &current_profile,
+                &evaluated_profile,
+                profile_secs(evaluated_profile.as_ref(), |profile| {
+                    profile.activation_delay_secs
+                }),
+                profile_secs(current_profile.as_ref(), |profile| profile.min_active_secs),
+                now,
+            );
+
+            let profile = match decision {
+                SwitchDecision::Keep => current_profile,
+                SwitchDecision::Wait(gpu_wait) => {
+                    wait = Some(wait.map_or(gpu_wait, |wait| wait.min(gpu_wait)));
+                    current_profile
+                }
+                SwitchDecision::Switch(profile) => {
+                    switched = true;
+                    profile
+                }
+            };
+            gpus.insert(gpu_id.clone(), profile);
+        }
+
+        // The default profile only changes once every GPU that uses it has switched
+        let default_switched = gpus
+            .iter()
+            .filter(|(gpu_id, _)| !evaluated.gpus.contains_key(*gpu_id))
+            .all(|(_, profile)| *profile == evaluated.default);
+        let assignment = ProfileAssignment {
+            default: if default_switched {
+                evaluated.default.clone()
+            } else {
+                current.default.clone()
+            },
+            gpus,
+        };
+
+        if switched || assignment.default != current.default {
+            SwitchDecision::Switch(assignment)
+        } else if let Some(wait) = wait {
+            SwitchDecision::Wait(wait)
+        } else {
+            SwitchDecision::Keep
+        }
+    }
+
+    /// When the rules should be evaluated again to make the earliest pending switch
+    pub fn deadline(&self) -> Option<Instant> {
+        self.gpus
+            .values()
+            .filter_map(SwitchDebouncer::deadline)
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProfileSwitchDebouncer, SwitchDebouncer, SwitchDecision};
+    use crate::config::{Profile, ProfileAssignment};
+    use indexmap::IndexMap;
+    use std::{
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn activation_delay() {
+        let game: Option<Rc<str>> = Some("game".into());
+        let delay = Duration::from_secs(5);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut debouncer = SwitchDebouncer::default();
+        let default = None;
+        assert_eq!(
+            SwitchDecision::Wait(delay),
+            debouncer.decide(&default, &game, delay, Duration::ZERO, at(0))
+        );
+        // The rule stopped matching before the delay passed, so it has to start over
+        assert_eq!(
+            SwitchDecision::Keep,
+            debouncer.decide(&default, &default, delay, Duration::ZERO, at(2))
+        );
+        assert_eq!(
+            SwitchDecision::Wait(Duration::from_secs(2)),
+            debouncer.decide(&default, &game, delay, Duration::ZERO, at(6))
+        );
+        assert_eq!(
+            SwitchDecision::Switch(game.clone()),
+            debouncer.decide(&default, &game, delay, Duration::ZERO, at(8))
+        );
+    }
+
+    #[test]
+    fn min_active_time() {
+        let game: Option<Rc<str>> = Some("game".into());
+        let min_active = Duration::from_secs(30);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut debouncer = SwitchDebouncer::default();
+        let default = None;
+        assert_eq!(
+            SwitchDecision::Keep,
+            debouncer.decide(&default, &default, Duration::ZERO, min_active, at(0))
+        );
+        assert_eq!(
+            SwitchDecision::Switch(game.clone()),
+            debouncer.decide(&default, &game, Duration::ZERO, min_active, at(0))
+        );
+        assert_eq!(
+            SwitchDecision::Wait(Duration::from_secs(20)),
+            debouncer.decide(&game, &default, Duration::ZERO, min_active, at(10))
+        );
+        assert_eq!(Some(at(30)), debouncer.deadline());
+        assert_eq!(
+            SwitchDecision::Switch(None),
+            debouncer.decide(&game, &default, Duration::ZERO, min_active, at(30))
+        );
+        assert_eq!(None, debouncer.deadline());
+    }
+
+    #[test]
+    fn min_active_time_unknown_on_start() {
+        let game: Option<Rc<str>> = Some("game".into());
+        let min_active = Duration::from_secs(30);
+        let now = Instant::now();
+
+        // It's not known for how long the profile was active before the debouncer was created
+        let mut debouncer = SwitchDebouncer::default();
+        assert_eq!(
+            SwitchDecision::Switch(None),
+            debouncer.decide(&game, &None, Duration::ZERO, min_active, now)
+        );
+    }
+
+    #[test]
+    fn gpus_debounced_separately() {
+        let game: Rc<str> = "game".into();
+        let compute: Rc<str> = "compute".into();
+        let profiles = IndexMap::from([
+            (
+                game.clone(),
+                Profile {
+                    activation_delay_secs: Some(10),
+                    ..Default::default()
+                },
+            ),
+            (compute.clone(), Profile::default()),
+        ]);
+        let gpu_ids = ["gpu1".to_owned(), "gpu2".to_owned()];
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut debouncer = ProfileSwitchDebouncer::default();
+        let initial = ProfileAssignment::default();
+        let game_on_gpu1 = ProfileAssignment {
+            default: None,
+            gpus: IndexMap::from([("gpu1".to_owned(), Some(game.clone()))]),
+        };
+        assert_eq!(
+            SwitchDecision::Wait(Duration::from_secs(10)),
+            debouncer.decide(&initial, &game_on_gpu1, &gpu_ids, &profiles, at(0))
+        );
+
+        // Switching the other GPU doesn't restart the delay of the first one
+        let both = ProfileAssignment {
+            default: None,
+            gpus: IndexMap::from([
+                ("gpu1".to_owned(), Some(game.clone())),
+                ("gpu2".to_owned(), Some(compute.clone())),
+            ]),
+        };
+        let compute_on_gpu2 = ProfileAssignment {
+            default: None,
+            gpus: IndexMap::from([
+                ("gpu1".to_owned(), None),
+                ("gpu2".to_owned(), Some(compute.clone())),
+            ]),
+        };
+        assert_eq!(
+            SwitchDecision::Switch(compute_on_gpu2.clone()),
+            debouncer.decide(&initial, &both, &gpu_ids, &profiles, at(5))
+        );
+        assert_eq!(Some(at(10)), debouncer.deadline());
+        assert_eq!(
+            SwitchDecision::Switch(both.clone()),
+            debouncer.decide(&compute_on_gpu2, &both, &gpu_ids, &profiles, at(10))
+        );
+        assert_eq!(None, debouncer.deadline());
+    }
+}
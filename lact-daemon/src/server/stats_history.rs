@@ -0,0 +1,625 @@
+use super::handler::Handler;
+use crate::config;
+use anyhow::{anyhow, Context};
+use lact_schema::StatsHistorySample;
+use std::{
+    collections::{hash_map::Entry, BTreeMap, BTreeSet, HashMap},
+    fs::{self, File, OpenOptions},
+    os::unix::fs::FileExt,
+    path::Path,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::time::{interval, MissedTickBehavior};
+use tracing::{debug, error, info, warn};
+
+const MAGIC: &[u8; 8] = b"LACTHST1";
+/// Magic, slot size (u32), capacity (u32), total written samples count (u64)
+const HEADER_SIZE: usize = 24;
+const WRITTEN_COUNT_OFFSET: u64 = 16;
+/// Every sample takes a fixed amount of space, consisting of the payload length (u16) and a JSON payload
+const SLOT_SIZE: u32 = 512;
+const RAW_FILE_NAME: &str = "raw.bin";
+const DOWNSAMPLED_FILE_NAME: &str = "downsampled.bin";
+
+/// Records stats samples into a bounded on-disk ring buffer per GPU.
+/// Full resolution samples are kept for `raw_retention_secs`, and are additionally averaged into
+/// a second ring buffer with one sample per `downsample_interval_secs` for longer term storage.
+/// The files are accessed on the blocking thread pool, so that disk I/O doesn't stall the daemon.
+pub struct StatsHistory {
+    settings: config::StatsHistory,
+    files: Arc<Mutex<HistoryFiles>>,
+}
+
+struct HistoryFiles {
+    settings: config::StatsHistory,
+    gpus: HashMap<String, GpuHistory>,
+}
+
+struct GpuHistory {
+    raw: RingFile,
+    downsampled: RingFile,
+    /// Samples which have not been downsampled yet.
+    /// These are also stored in the raw file, so they can be restored after a restart.
+    pending: Vec<StatsHistorySample>,
+}
+
+impl GpuHistory {
+    fn add_pending(
+        &mut self,
+        sample: StatsHistorySample,
+        downsample_interval: i64,
+    ) -> anyhow::Result<()> {
+        if let Some(first) = self.pending.first() {
+            if sample.timestamp - first.timestamp >= downsample_interval {
+                let merged = merge_samples(first.timestamp, &self.pending);
+                self.pending.clear();
+                self.downsampled.push(&merged)?;
+            }
+        }
+        self.pending.push(sample);
+        Ok(())
+    }
+}
+
+impl StatsHistory {
+    pub fn new(settings: config::StatsHistory) -> Self {
+        Self {
+            files: Arc::new(Mutex::new(HistoryFiles::new(settings.clone()))),
+            settings,
+        }
+    }
+
+    async fn with_files<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut HistoryFiles) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let files = self.files.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut files = files.lock().map_err(|err| anyhow!("{err}"))?;
+            f(&mut files)
+        })
+        .await
+        .context("Stats history task failed")?
+    }
+
+    pub async fn record(&self, id: &str, sample: StatsHistorySample) -> anyhow::Result<()> {
+        let id = id.to_owned();
+        self.with_files(move |files| files.record(&id, sample))
+            .await
+    }
+
+    pub async fn query(
+        &self,
+        id: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        resolution: Option<u64>,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        let id = id.to_owned();
+        self.with_files(move |files| files.query(&id, since, until, resolution))
+            .await
+    }
+}
+
+impl HistoryFiles {
+    fn new(settings: config::StatsHistory) -> Self {
+        Self {
+            settings,
+            gpus: HashMap::new(),
+        }
+    }
+
+    fn with_gpu<T>(
+        &mut self,
+        id: &str,
+        f: impl FnOnce(&mut GpuHistory) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let gpu = match self.gpus.entry(id.to_owned()) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(open_gpu(&self.settings, id)?),
+        };
+        f(gpu)
+    }
+
+    fn record(&mut self, id: &str, sample: StatsHistorySample) -> anyhow::Result<()> {
+        let downsample_interval = downsample_interval(&self.settings);
+
+        self.with_gpu(id, |gpu| {
+            gpu.raw.push(&sample)?;
+            gpu.add_pending(sample, downsample_interval)
+        })
+    }
+
+    fn query(
+        &mut self,
+        id: &str,
+        since: Option<i64>,
+        until: Option<i64>,
+        resolution: Option<u64>,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        let samples = self.with_gpu(id, |gpu| {
+            // Prefer full resolution samples where they are available
+            let raw_start = gpu
+                .raw
+                .read_first()?
+                .map_or(i64::MAX, |sample| sample.timestamp);
+            let downsampled_until = until.map_or(raw_start, |until| until.min(raw_start));
+
+            let mut samples = gpu.downsampled.read_range(since, downsampled_until)?;
+            samples.retain(|sample| sample.timestamp < raw_start);
+            samples.extend(gpu.raw.read_range(since, until.unwrap_or(i64::MAX))?);
+            Ok(samples)
+        })?;
+
+        match resolution {
+            Some(resolution) if resolution > 0 => Ok(aggregate(samples, resolution)),
+            _ => Ok(samples),
+        }
+    }
+}
+
+fn open_gpu(settings: &config::StatsHistory, id: &str) -> anyhow::Result<GpuHistory> {
+    let dir = settings.path.join(id);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Could not create history directory {dir:?}"))?;
+
+    let interval_ms = settings.interval_ms.max(1);
+    let raw_capacity = (settings.raw_retention_secs * 1000 / interval_ms).max(1);
+    let downsampled_capacity =
+        (settings.retention_secs / settings.downsample_interval_secs.max(1)).max(1);
+
+    let mut gpu = GpuHistory {
+        raw: RingFile::open(&dir.join(RAW_FILE_NAME), raw_capacity)?,
+        downsampled: RingFile::open(&dir.join(DOWNSAMPLED_FILE_NAME), downsampled_capacity)?,
+        pending: Vec::new(),
+    };
+    restore_pending(&mut gpu, downsample_interval(settings))?;
+
+    Ok(gpu)
+}
+
+/// Restores the samples that were recorded after the last downsampled one from the raw file
+fn restore_pending(gpu: &mut GpuHistory, downsample_interval: i64) -> anyhow::Result<()> {
+    // A downsampled sample covers less than one interval starting from its timestamp
+    let restore_since = gpu.downsampled.read_last()?.map_or(i64::MIN, |last| {
+        last.timestamp.saturating_add(downsample_interval)
+    });
+
+    let samples = gpu.raw.read_range(Some(restore_since), i64::MAX)?;
+    for sample in samples {
+        gpu.add_pending(sample, downsample_interval)?;
+    }
+
+    if !gpu.pending.is_empty() {
+        debug!("restored {} pending history samples", gpu.pending.len());
+    }
+    Ok(())
+}
+
+fn downsample_interval(settings: &config::StatsHistory) -> i64 {
+    i64::try_from(settings.downsample_interval_secs * 1000).unwrap_or(i64::MAX)
+}
+
+pub async fn run_recorder(handler: Handler, history: Rc<StatsHistory>) {
+    info!("recording stats history to {:?}", history.settings.path);
+
+    let mut interval = interval(Duration::from_millis(history.settings.interval_ms.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+        let timestamp = now_millis();
+
+        for entry in handler.list_devices().await {
+            match handler.get_gpu_stats(&entry.id).await {
+                Ok(stats) => {
+                    let sample = StatsHistorySample::from_stats(timestamp, &stats);
+                    if let Err(err) = history.record(&entry.id, sample).await {
+                        error!(
+                            "could not record stats history for GPU {}: {err:#}",
+                            entry.id
+                        );
+                    }
+                }
+                Err(err) => debug!("could not get stats for GPU {}: {err:#}", entry.id),
+            }
+        }
+    }
+}
+
+fn now_millis() -> i64 {
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    i64::try_from(duration.as_millis()).unwrap_or(i64::MAX)
+}
+
+/// Groups samples into buckets of `resolution` milliseconds, each bucket is averaged into a single sample
+fn aggregate(samples: Vec<StatsHistorySample>, resolution: u64) -> Vec<StatsHistorySample> {
+    let resolution = i64::try_from(resolution).unwrap_or(i64::MAX);
+
+    let mut buckets: BTreeMap<i64, Vec<StatsHistorySample>> = BTreeMap::new();
+    for sample in samples {
+        let bucket = sample.timestamp.div_euclid(resolution) * resolution;
+        buckets.entry(bucket).or_default().push(sample);
+    }
+
+    buckets
+        .into_iter()
+        .map(|(bucket, samples)| merge_samples(bucket, &samples))
+        .collect()
+}
+
+#[allow(
+    clippy::cast_precision_loss,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn merge_samples(timestamp: i64, samples: &[StatsHistorySample]) -> StatsHistorySample {
+    fn mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+        let (sum, count) = values.fold((0.0, 0u32), |(sum, count), value| (sum + value, count + 1));
+        (count > 0).then_some(sum / f64::from(count))
+    }
+
+    let temp_keys: BTreeSet<&String> = samples
+        .iter()
+        .flat_map(|sample| sample.temps.keys())
+        .collect();
+    let temps = temp_keys
+        .into_iter()
+        .filter_map(|key| {
+            let value = mean(
+                samples
+                    .iter()
+                    .filter_map(|sample| sample.temps.get(key).copied().map(f64::from)),
+            )?;
+            Some((key.clone(), value as f32))
+        })
+        .collect();
+
+    StatsHistorySample {
+        timestamp,
+        temps,
+        fan_speed: mean(samples.iter().filter_map(|s| s.fan_speed.map(f64::from)))
+            .map(|value| value.round() as u32),
+        fan_pwm: mean(samples.iter().filter_map(|s| s.fan_pwm.map(f64::from)))
+            .map(|value| value.round() as u8),
+        gpu_clockspeed: mean(
+            samples
+                .iter()
+                .filter_map(|s| s.gpu_clockspeed.map(|v| v as f64)),
+        )
+        .map(|value| value.round() as u64),
+        vram_clockspeed: mean(
+            samples
+                .iter()
+                .filter_map(|s| s.vram_clockspeed.map(|v| v as f64)),
+        )
+        .map(|value| value.round() as u64),
+        voltage: mean(samples.iter().filter_map(|s| s.voltage.map(|v| v as f64)))
+            .map(|value| value.round() as u64),
+        vram_used: mean(samples.iter().filter_map(|s| s.vram_used.map(|v| v as f64)))
+            .map(|value| value.round() as u64),
+        power_average: mean(samples.iter().filter_map(|s| s.power_average)),
+        power_current: mean(samples.iter().filter_map(|s| s.power_current)),
+        power_cap: mean(samples.iter().filter_map(|s| s.power_cap)),
+        busy_percent: mean(samples.iter().filter_map(|s| s.busy_percent.map(f64::from)))
+            .map(|value| value.round() as u8),
+    }
+}
+
+/// A file with a fixed amount of sample slots, where the oldest samples get overwritten
+struct RingFile {
+    file: File,
+    capacity: u64,
+    written: u64,
+}
+
+impl RingFile {
+    fn open(path: &Path, capacity: u64) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Could not open history file {path:?}"))?;
+
+        let mut header = [0; HEADER_SIZE];
+        if file.read_exact_at(&mut header, 0).is_ok() {
+            let slot_size = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            let file_capacity = u32::from_le_bytes(header[12..16].try_into().unwrap());
+            let written = u64::from_le_bytes(header[16..24].try_into().unwrap());
+
+            if &header[0..8] == MAGIC
+                && slot_size == SLOT_SIZE
+                && u64::from(file_capacity) == capacity
+            {
+                debug!("loaded history file {path:?} with {written} samples written");
+                return Ok(Self {
+                    file,
+                    capacity,
+                    written,
+                });
+            }
+
+            warn!("history file {path:?} has a different format or capacity, recreating it");
+        }
+
+        let file_capacity =
+            u32::try_from(capacity).map_err(|_| anyhow!("History capacity is too large"))?;
+        file.set_len(0)?;
+        file.set_len(HEADER_SIZE as u64 + capacity * u64::from(SLOT_SIZE))?;
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&SLOT_SIZE.to_le_bytes());
+        header.extend_from_slice(&file_capacity.to_le_bytes());
+        header.extend_from_slice(&0u64.to_le_bytes());
+        file.write_all_at(&header, 0)?;
+
+        Ok(Self {
+            file,
+            capacity,
+            written: 0,
+        })
+    }
+
+    fn slot_offset(&self, index: u64) -> u64 {
+        HEADER_SIZE as u64 + (index % self.capacity) * u64::from(SLOT_SIZE)
+    }
+
+    fn push(&mut self, sample: &StatsHistorySample) -> anyhow::Result<()> {
+        let payload = serde_json::to_vec(sample)?;
+        let len = u16::try_from(payload.len())
+            .ok()
+            .filter(|len| u32::from(*len) + 2 <= SLOT_SIZE)
+            .ok_or_else(|| anyhow!("Sample is too large ({} bytes)", payload.len()))?;
+
+        let mut slot = vec![0; SLOT_SIZE as usize];
+        slot[0..2].copy_from_slice(&len.to_le_bytes());
+        slot[2..2 + payload.len()].copy_from_slice(&payload);
+
+        self.file
+            .write_all_at(&slot, self.slot_offset(self.written))?;
+        self.written += 1;
+        self.file
+            .write_all_at(&self.written.to_le_bytes(), WRITTEN_COUNT_OFFSET)?;
+
+        Ok(())
+    }
+
+    /// Index of the oldest stored sample
+    fn first_index(&self) -> u64 {
+        self.written - self.written.min(self.capacity)
+    }
+
+    /// Reads all stored samples, from oldest to newest
+    #[cfg(test)]
+    fn read_all(&self) -> anyhow::Result<Vec<StatsHistorySample>> {
+        self.read_range(None, i64::MAX)
+    }
+
+    /// Reads the stored samples with a timestamp from `since` up to and including `until`, from oldest to newest.
+    /// Samples are stored in the order they were recorded, so the slots before `since` are skipped with a binary search.
+    fn read_range(
+        &self,
+        since: Option<i64>,
+        until: i64,
+    ) -> anyhow::Result<Vec<StatsHistorySample>> {
+        let mut slot = vec![0; SLOT_SIZE as usize];
+
+        let mut start = self.first_index();
+        if let Some(since) = since {
+            let mut end = self.written;
+            while start < end {
+                let middle = start + (end - start) / 2;
+                // Invalid samples are skipped anyway
+                let is_before = self
+                    .read_slot(middle, &mut slot)?
+                    .map_or(true, |sample| sample.timestamp < since);
+                if is_before {
+                    start = middle + 1;
+                } else {
+                    end = middle;
+                }
+            }
+        }
+
+        let mut samples = Vec::new();
+        for index in start..self.written {
+            if let Some(sample) = self.read_slot(index, &mut slot)? {
+                if sample.timestamp > until {
+                    break;
+                }
+                samples.push(sample);
+            }
+        }
+
+        Ok(samples)
+    }
+
+    /// Reads the oldest stored sample
+    fn read_first(&self) -> anyhow::Result<Option<StatsHistorySample>> {
+        if self.written == 0 {
+            return Ok(None);
+        }
+
+        let mut slot = vec![0; SLOT_SIZE as usize];
+        self.read_slot(self.first_index(), &mut slot)
+    }
+
+    /// Reads the newest stored sample
+    fn read_last(&self) -> anyhow::Result<Option<StatsHistorySample>> {
+        if self.written == 0 {
+            return Ok(None);
+        }
+
+        let mut slot = vec![0; SLOT_SIZE as usize];
+        self.read_slot(self.written - 1, &mut slot)
+    }
+
+    fn read_slot(&self, index: u64, slot: &mut [u8]) -> anyhow::Result<Option<StatsHistorySample>> {
+        self.file.read_exact_at(slot, self.slot_offset(index))?;
+        let len = usize::from(u16::from_le_bytes([slot[0], slot[1]])).min(slot.len() - 2);
+
+        match serde_json::from_slice(&slot[2..2 + len]) {
+            Ok(sample) => Ok(Some(sample)),
+            Err(err) => {
+                warn!("skipping invalid history sample: {err}");
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate, HistoryFiles, RingFile};
+    use crate::config;
+    use lact_schema::StatsHistorySample;
+    use pretty_assertions::assert_eq;
+    use std::{collections::BTreeMap, env, fs};
+
+    fn sample(timestamp: i64, temp: f32, busy_percent: u8) -> StatsHistorySample {
+        StatsHistorySample {
+            timestamp,
+            temps: BTreeMap::from([("edge".to_owned(), temp)]),
+            busy_percent: Some(busy_percent),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ring_file_wraps_around() {
+        let path = env::temp_dir().join(format!("lact-history-test-{}", std::process::id()));
+
+        let mut file = RingFile::open(&path, 3).unwrap();
+        for i in 0..5 {
+            file.push(&sample(i, 50.0, 10)).unwrap();
+        }
+        let timestamps: Vec<i64> = file
+            .read_all()
+            .unwrap()
+            .iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(vec![2, 3, 4], timestamps);
+
+        // Data should persist after reopening
+        let file = RingFile::open(&path, 3).unwrap();
+        assert_eq!(3, file.read_all().unwrap().len());
+
+        // Changing the capacity discards the old data
+        let file = RingFile::open(&path, 5).unwrap();
+        assert!(file.read_all().unwrap().is_empty());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn ring_file_reads_range() {
+        let path = env::temp_dir().join(format!("lact-history-range-{}", std::process::id()));
+
+        let mut file = RingFile::open(&path, 5).unwrap();
+        for i in 0..8 {
+            file.push(&sample(i * 1000, 50.0, 10)).unwrap();
+        }
+        let timestamps = |samples: Vec<StatsHistorySample>| -> Vec<i64> {
+            samples.iter().map(|sample| sample.timestamp).collect()
+        };
+
+        assert_eq!(
+            vec![4000, 5000, 6000],
+            timestamps(file.read_range(Some(3500), 6000).unwrap())
+        );
+        assert_eq!(
+            vec![3000, 4000],
+            timestamps(file.read_range(None, 4500).unwrap())
+        );
+        assert!(file.read_range(Some(8000), i64::MAX).unwrap().is_empty());
+        assert_eq!(Some(3000), file.read_first().unwrap().map(|s| s.timestamp));
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn query_prefers_raw_samples() {
+        let path = env::temp_dir().join(format!("lact-history-query-{}", std::process::id()));
+        let settings = config::StatsHistory {
+            path: path.clone(),
+            interval_ms: 1000,
+            raw_retention_secs: 3,
+            downsample_interval_secs: 2,
+            retention_secs: 60,
+        };
+
+        let mut history = HistoryFiles::new(settings);
+        for timestamp in 0..6 {
+            history
+                .record("gpu", sample(timestamp * 1000, 50.0, 10))
+                .unwrap();
+        }
+
+        // Downsampled samples are only used before the oldest raw sample at 3000
+        let timestamps: Vec<i64> = history
+            .query("gpu", Some(1000), Some(4000), None)
+            .unwrap()
+            .iter()
+            .map(|sample| sample.timestamp)
+            .collect();
+        assert_eq!(vec![2000, 3000, 4000], timestamps);
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn pending_samples_survive_restart() {
+        let path = env::temp_dir().join(format!("lact-history-restart-{}", std::process::id()));
+        let settings = config::StatsHistory {
+            path: path.clone(),
+            interval_ms: 1000,
+            raw_retention_secs: 60,
+            downsample_interval_secs: 2,
+            retention_secs: 60,
+        };
+
+        let mut history = HistoryFiles::new(settings.clone());
+        for (timestamp, temp) in [(0, 40.0), (1000, 50.0), (2000, 60.0)] {
+            history.record("gpu", sample(timestamp, temp, 10)).unwrap();
+        }
+        drop(history);
+
+        // The sample at 2000 was not downsampled before the restart
+        let mut history = HistoryFiles::new(settings);
+        history.record("gpu", sample(3000, 70.0, 30)).unwrap();
+        history.record("gpu", sample(4000, 80.0, 10)).unwrap();
+
+        let downsampled = history
+            .with_gpu("gpu", |gpu| gpu.downsampled.read_all())
+            .unwrap();
+        assert_eq!(
+            vec![sample(0, 45.0, 10), sample(2000, 65.0, 20)],
+            downsampled
+        );
+
+        fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn aggregate_samples() {
+        let samples = vec![
+            sample(1000, 40.0, 10),
+            sample(1500, 50.0, 20),
+            sample(2000, 60.0, 30),
+        ];
+
+        let aggregated = aggregate(samples, 1000);
+        assert_eq!(
+            vec![sample(1000, 45.0, 15), sample(2000, 60.0, 30)],
+            aggregated
+        );
+    }
+}
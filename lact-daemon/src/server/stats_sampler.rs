@@ -0,0 +1,226 @@
+use super::handler::Handler;
+use lact_schema::Response;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{broadcast, Notify},
+    time::sleep,
+};
+use tracing::{debug, error};
+
+pub const MIN_STATS_INTERVAL_MS: u64 = 50;
+const FRAME_CHANNEL_SIZE: usize = 4;
+/// Allowed deviation from the requested interval when deciding whether to forward a frame to a slower subscriber
+const INTERVAL_TOLERANCE: Duration = Duration::from_millis(10);
+
+/// Serialized `Response<DeviceStats>`, shared between all subscribers
+pub type StatsFrame = Rc<[u8]>;
+
+/// Samples GPU stats for streaming subscriptions.
+/// There is at most one sampling task per GPU, which runs at the interval of the fastest subscriber
+/// and stops when there are no subscribers left.
+#[derive(Clone, Default)]
+pub struct StatsSampler {
+    samplers: Rc<RefCell<HashMap<String, Rc<GpuSampler>>>>,
+}
+
+struct GpuSampler {
+    tx: broadcast::Sender<StatsFrame>,
+    /// Requested interval -> subscriber count
+    intervals: RefCell<BTreeMap<u64, usize>>,
+    interval_changed: Notify,
+}
+
+impl GpuSampler {
+    fn current_interval(&self) -> Duration {
+        let interval_ms = self
+            .intervals
+            .borrow()
+            .keys()
+            .next()
+            .copied()
+            .unwrap_or(MIN_STATS_INTERVAL_MS);
+        Duration::from_millis(interval_ms)
+    }
+}
+
+impl StatsSampler {
+    pub fn subscribe(&self, handler: Handler, id: &str, interval_ms: u64) -> StatsSubscription {
+        let interval_ms = interval_ms.max(MIN_STATS_INTERVAL_MS);
+
+        let mut samplers = self.samplers.borrow_mut();
+        let sampler = match samplers.get(id) {
+            Some(sampler) => sampler.clone(),
+            None => {
+                let (tx, _) = broadcast::channel(FRAME_CHANNEL_SIZE);
+                let sampler = Rc::new(GpuSampler {
+                    tx,
+                    intervals: RefCell::default(),
+                    interval_changed: Notify::new(),
+                });
+                samplers.insert(id.to_owned(), sampler.clone());
+
+                debug!("starting stats sampler for GPU {id}");
+                tokio::task::spawn_local(run_sampler(
+                    self.clone(),
+                    handler,
+                    id.to_owned(),
+                    sampler.clone(),
+                ));
+                sampler
+            }
+        };
+
+        let rx = sampler.tx.subscribe();
+
+        let previous_interval = sampler.current_interval();
+        *sampler
+            .intervals
+            .borrow_mut()
+            .entry(interval_ms)
+            .or_default() += 1;
+        if sampler.current_interval() != previous_interval {
+            sampler.interval_changed.notify_one();
+        }
+
+        StatsSubscription {
+            rx,
+            interval: Duration::from_millis(interval_ms),
+            interval_ms,
+            last_sent: None,
+            sampler,
+        }
+    }
+}
+
+async fn run_sampler(
+    sampler_map: StatsSampler,
+    handler: Handler,
+    id: String,
+    sampler: Rc<GpuSampler>,
+) {
+    loop {
+        let response = match handler.get_gpu_stats(&id).await {
+            Ok(stats) => serde_json::to_vec(&Response::Ok(stats)),
+            Err(err) => serde_json::to_vec(&Response::<()>::from(err)),
+        };
+
+        match response {
+            Ok(frame) => {
+                // Sending only fails when there are no receivers left
+                let _ = sampler.tx.send(frame.into());
+            }
+            Err(err) => error!("could not serialize stats for GPU {id}: {err}"),
+        }
+
+        tokio::select! {
+            () = sleep(sampler.current_interval()) => {},
+            () = sampler.interval_changed.notified() => {},
+        }
+
+        if sampler.tx.receiver_count() == 0 {
+            let mut samplers = sampler_map.samplers.borrow_mut();
+            if samplers
+                .get(&id)
+                .is_some_and(|current| Rc::ptr_eq(current, &sampler))
+            {
+                samplers.remove(&id);
+            }
+            debug!("stopping stats sampler for GPU {id}");
+            break;
+        }
+    }
+}
+
+pub struct StatsSubscription {
+    rx: broadcast::Receiver<StatsFrame>,
+    interval: Duration,
+    interval_ms: u64,
+    last_sent: Option<Instant>,
+    sampler: Rc<GpuSampler>,
+}
+
+impl StatsSubscription {
+    /// Wait for the next frame according to the subscription interval.
+    /// Returns `None` if the sampler has stopped.
+    pub async fn recv(&mut self) -> Option<StatsFrame> {
+        loop {
+            match self.rx.recv().await {
+                Ok(frame) => {
+                    // The sampler runs at the interval of the fastest subscriber, skip frames that arrive too early
+                    if let Some(last_sent) = self.last_sent {
+                        if last_sent.elapsed() + INTERVAL_TOLERANCE < self.interval {
+                            continue;
+                        }
+                    }
+                    self.last_sent = Some(Instant::now());
+                    return Some(frame);
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+impl Drop for StatsSubscription {
+    fn drop(&mut self) {
+        let previous_interval = self.sampler.current_interval();
+        let mut intervals = self.sampler.intervals.borrow_mut();
+        if let Some(count) = intervals.get_mut(&self.interval_ms) {
+            *count -= 1;
+            if *count == 0 {
+                intervals.remove(&self.interval_ms);
+            }
+        }
+        let is_empty = intervals.is_empty();
+        drop(intervals);
+
+        // Wake up the sampler so it can either adjust the interval or stop if there are no subscribers left
+        if is_empty || self.sampler.current_interval() != previous_interval {
+            self.sampler.interval_changed.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StatsSampler;
+    use crate::{config::Config, server::handler::Handler};
+    use lact_schema::{DeviceStats, Response};
+    use std::{path::PathBuf, time::Duration};
+    use tokio::{task::LocalSet, time::sleep};
+
+    #[tokio::test]
+    async fn shared_sampler() {
+        LocalSet::new()
+            .run_until(async {
+                let base_path =
+                    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/data/amd/rx6900xt");
+                let handler = Handler::with_base_path(&base_path, Config::default(), None)
+                    .await
+                    .unwrap();
+                let id = handler.list_devices().await.remove(0).id;
+
+                let sampler = StatsSampler::default();
+                let mut first = sampler.subscribe(handler.clone(), &id, 50);
+                let mut second = sampler.subscribe(handler.clone(), &id, 100);
+                assert_eq!(1, sampler.samplers.borrow().len());
+
+                let frame = first.recv().await.unwrap();
+                let response: Response<DeviceStats> = serde_json::from_slice(&frame).unwrap();
+                assert!(matches!(response, Response::Ok(_)));
+                second.recv().await.unwrap();
+
+                drop(first);
+                drop(second);
+                sleep(Duration::from_millis(10)).await;
+                assert!(sampler.samplers.borrow().is_empty());
+            })
+            .await;
+    }
+}
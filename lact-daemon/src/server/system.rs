@@ -12,7 +12,10 @@ use std::{
     process,
     sync::atomic::{AtomicBool, Ordering},
 };
-use tokio::{process::Command, sync::Notify};
+use tokio::{
+    process::Command,
+    sync::{mpsc, Notify},
+};
 use tracing::{debug, error, info, warn};
 
 static OC_TOGGLED: AtomicBool = AtomicBool::new(false);
@@ -195,7 +198,20 @@ async fn run_command(exec: &str, args: &[&str]) -> anyhow::Result<()> {
     }
 }
 
-pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()> {
+/// A GPU reset reported by the driver in a drm uevent
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResetUevent {
+    pub pci_slot_name: String,
+    /// The uevent property which reported the reset
+    pub reason: String,
+}
+
+/// Listens to kernel uevents, notifying the listener of the matching subsystem for each event.
+/// GPU resets are additionally sent to `reset_tx`.
+pub(crate) fn listen_netlink_kernel_event(
+    listeners: &[(&str, &Notify)],
+    reset_tx: &mpsc::UnboundedSender<ResetUevent>,
+) -> anyhow::Result<()> {
     let socket = socket(
         AddressFamily::Netlink,
         SockType::Raw,
@@ -223,6 +239,7 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
         recv(socket.as_raw_fd(), &mut buf, MsgFlags::empty())
             .context("Could not read netlink message")?;
 
+        let mut lines = Vec::new();
         for raw_line in buf.split(|c| *c == b'\0') {
             match std::str::from_utf8(raw_line) {
                 Ok(line) => {
@@ -232,10 +249,13 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
 
                     debug!("kernel event line: '{line}'");
                     if let Some(subsystem) = line.strip_prefix("SUBSYSTEM=") {
-                        if subsystem == "drm" {
-                            notify.notify_one();
+                        for (name, notify) in listeners {
+                            if subsystem == *name {
+                                notify.notify_one();
+                            }
                         }
                     }
+                    lines.push(line);
                 }
                 Err(_) => {
                     error!(
@@ -245,12 +265,49 @@ pub(crate) fn listen_netlink_kernel_event(notify: &Notify) -> anyhow::Result<()>
                 }
             }
         }
+
+        if let Some(event) = parse_reset_uevent(&lines) {
+            if reset_tx.send(event).is_err() {
+                error!("GPU reset listener is not running");
+            }
+        }
     }
 }
 
+/// Drivers report GPU resets with either the `RESET=1` property, or `WEDGED=<recovery method>` on newer kernels.
+/// There is no reset counter to poll as a fallback: reading `amdgpu_gpu_recover` in debugfs triggers a reset instead.
+fn parse_reset_uevent(lines: &[&str]) -> Option<ResetUevent> {
+    let property = |name: &str| {
+        lines
+            .iter()
+            .find_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+    };
+
+    if property("SUBSYSTEM")? != "drm" {
+        return None;
+    }
+
+    let reason = if let Some(recovery) = property("WEDGED") {
+        format!("WEDGED={recovery}")
+    } else if property("RESET") == Some("1") {
+        "RESET=1".to_owned()
+    } else {
+        return None;
+    };
+
+    // The drm device is a child of the PCI device, e.g. `/devices/pci0000:00/0000:00:01.1/0000:03:00.0/drm/card1`
+    let (pci_path, _) = property("DEVPATH")?.split_once("/drm/")?;
+    let pci_slot_name = pci_path.rsplit('/').next()?.to_owned();
+
+    Some(ResetUevent {
+        pci_slot_name,
+        reason,
+    })
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::server::system::detect_initramfs_type;
+    use crate::server::system::{detect_initramfs_type, parse_reset_uevent, ResetUevent};
     use lact_schema::InitramfsType;
     use os_release::OsRelease;
 
@@ -294,4 +351,35 @@ UBUNTU_CODENAME=jammy
             detect_initramfs_type(&os_release).await
         );
     }
+
+    #[test]
+    fn parse_reset_uevents() {
+        let devpath = "DEVPATH=/devices/pci0000:00/0000:00:01.1/0000:01:00.0/0000:02:00.0/0000:03:00.0/drm/card1";
+        let wedged = [
+            "change@/devices/pci0000:00/0000:00:01.1/0000:01:00.0/0000:02:00.0/0000:03:00.0/drm/card1",
+            "ACTION=change",
+            devpath,
+            "SUBSYSTEM=drm",
+            "WEDGED=none",
+            "DEVNAME=dri/card1",
+        ];
+        assert_eq!(
+            Some(ResetUevent {
+                pci_slot_name: "0000:03:00.0".to_owned(),
+                reason: "WEDGED=none".to_owned(),
+            }),
+            parse_reset_uevent(&wedged)
+        );
+
+        let reset = ["ACTION=change", devpath, "SUBSYSTEM=drm", "RESET=1"];
+        assert_eq!(
+            Some("RESET=1"),
+            parse_reset_uevent(&reset)
+                .as_ref()
+                .map(|event| event.reason.as_str())
+        );
+
+        let hotplug = ["ACTION=change", devpath, "SUBSYSTEM=drm", "HOTPLUG=1"];
+        assert_eq!(None, parse_reset_uevent(&hotplug));
+    }
 }
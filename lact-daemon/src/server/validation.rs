@@ -0,0 +1,508 @@
+use crate::config::{ClocksConfiguration, Gpu};
+use amdgpu_sysfs::gpu_handle::{
+    overdrive::{ClocksTable as _, ClocksTableGen},
+    power_profile_mode::PowerProfileModesTable,
+    PerformanceLevel, PowerLevelKind,
+};
+use lact_schema::{
+    ClocksInfo, ClocksTable, ConfigProblem, FanControlMode, PmfwInfo, PowerStates, PowerStats,
+};
+use std::fmt;
+
+const NOT_SUPPORTED: &str = "Not supported on this GPU";
+
+/// The limits of a GPU that the settings are checked against
+pub struct GpuLimits {
+    pub clocks_info: Option<ClocksInfo>,
+    pub power: PowerStats,
+    pub performance_level: Option<PerformanceLevel>,
+    pub power_states: PowerStates,
+    pub power_profile_modes: Option<PowerProfileModesTable>,
+    pub pmfw_info: PmfwInfo,
+}
+
+#[derive(Default)]
+struct Problems(Vec<ConfigProblem>);
+
+impl Problems {
+    fn push(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ConfigProblem {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    fn check_range<T: PartialOrd + fmt::Display>(
+        &mut self,
+        field: impl Into<String>,
+        value: T,
+        min: Option<T>,
+        max: Option<T>,
+    ) {
+        match (min, max) {
+            (Some(min), _) if value < min => {
+                self.push(field, format!("{value} is below the minimum of {min}"));
+            }
+            (_, Some(max)) if value > max => {
+                self.push(field, format!("{value} is above the maximum of {max}"));
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Checks if the settings would apply to a GPU with the given limits, without applying them
+pub fn validate_gpu_config(config: &Gpu, limits: &GpuLimits) -> Vec<ConfigProblem> {
+    let mut problems = Problems::default();
+
+    if let Some(cap) = config.power_cap {
+        if limits.power.cap_current.is_some() {
+            problems.check_range("power_cap", cap, limits.power.cap_min, limits.power.cap_max);
+        } else {
+            problems.push("power_cap", NOT_SUPPORTED);
+        }
+    }
+
+    if config.performance_level.is_some() && limits.performance_level.is_none() {
+        problems.push("performance_level", NOT_SUPPORTED);
+    }
+
+    check_clocks(
+        &mut problems,
+        &config.clocks_configuration,
+        limits.clocks_info.as_ref(),
+    );
+    check_power_states(&mut problems, config, &limits.power_states);
+    check_power_profile_mode(&mut problems, config, limits.power_profile_modes.as_ref());
+    check_fan_control(&mut problems, config);
+    check_pmfw(&mut problems, config, &limits.pmfw_info);
+
+    problems.0
+}
+
+#[allow(clippy::too_many_lines)]
+fn check_clocks(problems: &mut Problems, clocks: &ClocksConfiguration, info: Option<&ClocksInfo>) {
+    if *clocks == ClocksConfiguration::default() {
+        return;
+    }
+
+    let Some(table) = info.and_then(|info| info.table.as_ref()) else {
+        problems.push(
+            "clocks",
+            "The clocks table is not available (is overdrive enabled?)",
+        );
+        return;
+    };
+
+    let min_max_pairs = [
+        (
+            "min_core_clock",
+            clocks.min_core_clock,
+            clocks.max_core_clock,
+        ),
+        (
+            "min_memory_clock",
+            clocks.min_memory_clock,
+            clocks.max_memory_clock,
+        ),
+        ("min_voltage", clocks.min_voltage, clocks.max_voltage),
+    ];
+    for (field, min, max) in min_max_pairs {
+        if let (Some(min), Some(max)) = (min, max) {
+            if min > max {
+                problems.push(field, format!("{min} is higher than the maximum of {max}"));
+            }
+        }
+    }
+
+    // (field, value, allowed range), where a missing range means that the value is not supported
+    let mut values = Vec::new();
+    let mut offsets = Vec::new();
+    match table {
+        ClocksTable::Amd(table) => {
+            values.extend([
+                (
+                    "min_core_clock",
+                    clocks.min_core_clock,
+                    table
+                        .get_min_sclk_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+                (
+                    "max_core_clock",
+                    clocks.max_core_clock,
+                    table
+                        .get_max_sclk_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+                (
+                    "min_memory_clock",
+                    clocks.min_memory_clock,
+                    table
+                        .get_min_mclk_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+                (
+                    "max_memory_clock",
+                    clocks.max_memory_clock,
+                    table
+                        .get_max_mclk_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+                (
+                    "min_voltage",
+                    clocks.min_voltage,
+                    table
+                        .get_min_voltage_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+                (
+                    "max_voltage",
+                    clocks.max_voltage,
+                    table
+                        .get_max_voltage_range()
+                        .map(|range| (range.min, range.max)),
+                ),
+            ]);
+
+            let voltage_offset_range = match table {
+                ClocksTableGen::Vega20(table) => table
+                    .od_range
+                    .voltage_offset
+                    .as_ref()
+                    .map(|range| (range.min, range.max)),
+                _ => None,
+            };
+            values.push((
+                "voltage_offset",
+                clocks.voltage_offset,
+                voltage_offset_range,
+            ));
+
+            offsets.push(("gpu_clock_offsets", &clocks.gpu_clock_offsets, None));
+            offsets.push(("mem_clock_offsets", &clocks.mem_clock_offsets, None));
+        }
+        ClocksTable::Nvidia(table) => {
+            values.extend([
+                ("min_core_clock", clocks.min_core_clock, None),
+                ("max_core_clock", clocks.max_core_clock, None),
+                ("min_memory_clock", clocks.min_memory_clock, None),
+                ("max_memory_clock", clocks.max_memory_clock, None),
+                ("min_voltage", clocks.min_voltage, None),
+                ("max_voltage", clocks.max_voltage, None),
+                ("voltage_offset", clocks.voltage_offset, None),
+            ]);
+
+            offsets.push((
+                "gpu_clock_offsets",
+                &clocks.gpu_clock_offsets,
+                Some(&table.gpu_offsets),
+            ));
+            offsets.push((
+                "mem_clock_offsets",
+                &clocks.mem_clock_offsets,
+                Some(&table.mem_offsets),
+            ));
+        }
+        ClocksTable::Intel(table) => {
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let range = table
+                .rpn_freq
+                .zip(table.rp0_freq)
+                .map(|(min, max)| (Some(min as i32), Some(max as i32)));
+            values.extend([
+                ("min_core_clock", clocks.min_core_clock, range),
+                ("max_core_clock", clocks.max_core_clock, range),
+                ("min_memory_clock", clocks.min_memory_clock, None),
+                ("max_memory_clock", clocks.max_memory_clock, None),
+                ("min_voltage", clocks.min_voltage, None),
+                ("max_voltage", clocks.max_voltage, None),
+                ("voltage_offset", clocks.voltage_offset, None),
+            ]);
+
+            offsets.push(("gpu_clock_offsets", &clocks.gpu_clock_offsets, None));
+            offsets.push(("mem_clock_offsets", &clocks.mem_clock_offsets, None));
+        }
+    }
+
+    for (field, value, range) in values {
+        let Some(value) = value else {
+            continue;
+        };
+        match range {
+            Some((min, max)) => problems.check_range(field, value, min, max),
+            None => problems.push(field, NOT_SUPPORTED),
+        }
+    }
+
+    for (field, configured_offsets, allowed_offsets) in offsets {
+        for (pstate, offset) in configured_offsets {
+            let field = format!("{field}.{pstate}");
+            match allowed_offsets {
+                Some(allowed_offsets) => match allowed_offsets.get(pstate) {
+                    Some(allowed) => {
+                        problems.check_range(field, *offset, Some(allowed.min), Some(allowed.max));
+                    }
+                    None => problems.push(
+                        field,
+                        format!("Power state {pstate} does not support clock offsets"),
+                    ),
+                },
+                None => problems.push(field, NOT_SUPPORTED),
+            }
+        }
+    }
+}
+
+fn check_power_states(problems: &mut Problems, config: &Gpu, power_states: &PowerStates) {
+    for (kind, indices) in &config.power_states {
+        let kind_name = serde_json::to_value(kind)
+            .ok()
+            .and_then(|value| value.as_str().map(str::to_owned))
+            .unwrap_or_else(|| format!("{kind:?}"));
+        let field = format!("power_states.{kind_name}");
+
+        let states = match kind {
+            PowerLevelKind::CoreClock => &power_states.core,
+            PowerLevelKind::MemoryClock => &power_states.vram,
+            _ => {
+                problems.push(field, NOT_SUPPORTED);
+                continue;
+            }
+        };
+        if states.is_empty() {
+            problems.push(field, NOT_SUPPORTED);
+            continue;
+        }
+
+        for index in indices {
+            if !states.iter().any(|state| state.index == Some(*index)) {
+                problems.push(field.clone(), format!("Power state {index} does not exist"));
+            }
+        }
+    }
+}
+
+fn check_power_profile_mode(
+    problems: &mut Problems,
+    config: &Gpu,
+    modes: Option<&PowerProfileModesTable>,
+) {
+    let Some(index) = config.power_profile_mode_index else {
+        return;
+    };
+
+    match modes.map(|table| table.modes.get(&index)) {
+        None => problems.push("power_profile_mode_index", NOT_SUPPORTED),
+        Some(None) => problems.push(
+            "power_profile_mode_index",
+            format!("Power profile mode {index} does not exist"),
+        ),
+        Some(Some(mode)) => {
+            if !config.custom_power_profile_mode_hueristics.is_empty() && !mode.is_custom() {
+                problems.push(
+                    "custom_power_profile_mode_hueristics",
+                    format!("Power profile mode {index} does not support custom heuristics"),
+                );
+            }
+        }
+    }
+
+    if config.performance_level != Some(PerformanceLevel::Manual) {
+        problems.push(
+            "performance_level",
+            "Performance level has to be set to `manual` to use power profile modes",
+        );
+    }
+}
+
+fn check_fan_control(problems: &mut Problems, config: &Gpu) {
+    if !config.fan_control_enabled {
+        return;
+    }
+    let Some(settings) = &config.fan_control_settings else {
+        problems.push(
+            "fan_control_settings",
+            "Fan control is enabled with no settings provided",
+        );
+        return;
+    };
+
+    problems.check_range(
+        "fan_control_settings.static_speed",
+        settings.static_speed,
+        Some(0.0),
+        Some(1.0),
+    );
+
+    if settings.mode == FanControlMode::Curve && settings.curve.0.is_empty() {
+        problems.push("fan_control_settings.curve", "Cannot use empty fan curve");
+    }
+    for (temperature, speed) in &settings.curve.0 {
+        problems.check_range(
+            format!("fan_control_settings.curve.{temperature}"),
+            *speed,
+            Some(0.0),
+            Some(1.0),
+        );
+    }
+}
+
+fn check_pmfw(problems: &mut Problems, config: &Gpu, info: &PmfwInfo) {
+    let pmfw = &config.pmfw_options;
+    let values = [
+        ("acoustic_limit", pmfw.acoustic_limit, info.acoustic_limit),
+        (
+            "acoustic_target",
+            pmfw.acoustic_target,
+            info.acoustic_target,
+        ),
+        ("minimum_pwm", pmfw.minimum_pwm, info.minimum_pwm),
+        (
+            "target_temperature",
+            pmfw.target_temperature,
+            info.target_temp,
+        ),
+        (
+            "zero_rpm_threshold",
+            pmfw.zero_rpm_threshold,
+            info.zero_rpm_temperature,
+        ),
+    ];
+
+    for (name, value, fan_info) in values {
+        let Some(value) = value else {
+            continue;
+        };
+        let field = format!("pmfw_options.{name}");
+        match fan_info {
+            Some(fan_info) => {
+                if let Some((min, max)) = fan_info.allowed_range {
+                    problems.check_range(field, value, Some(min), Some(max));
+                }
+            }
+            None => problems.push(field, NOT_SUPPORTED),
+        }
+    }
+
+    if pmfw.zero_rpm.is_some() && info.zero_rpm_enable.is_none() {
+        problems.push("pmfw_options.zero_rpm", NOT_SUPPORTED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_gpu_config, GpuLimits};
+    use crate::config::{ClocksConfiguration, Gpu};
+    use amdgpu_sysfs::gpu_handle::{fan_control::FanInfo, PowerLevelKind};
+    use indexmap::IndexMap;
+    use lact_schema::{
+        ClocksInfo, ClocksTable, NvidiaClockOffset, NvidiaClocksTable, PmfwInfo, PmfwOptions,
+        PowerState, PowerStates, PowerStats,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn validate_against_limits() {
+        let limits = GpuLimits {
+            clocks_info: Some(ClocksInfo {
+                table: Some(ClocksTable::Nvidia(NvidiaClocksTable {
+                    gpu_offsets: IndexMap::from([(
+                        0,
+                        NvidiaClockOffset {
+                            current: 0,
+                            min: -200,
+                            max: 200,
+                        },
+                    )]),
+                    mem_offsets: IndexMap::new(),
+                })),
+                ..Default::default()
+            }),
+            power: PowerStats {
+                cap_current: Some(200.0),
+                cap_min: Some(100.0),
+                cap_max: Some(250.0),
+                ..Default::default()
+            },
+            performance_level: None,
+            power_states: PowerStates {
+                core: (0..3)
+                    .map(|index| PowerState {
+                        enabled: true,
+                        min_value: None,
+                        value: 1000,
+                        index: Some(index),
+                    })
+                    .collect(),
+                vram: vec![],
+            },
+            power_profile_modes: None,
+            pmfw_info: PmfwInfo {
+                acoustic_limit: Some(FanInfo {
+                    current: 2000,
+                    allowed_range: Some((500, 3000)),
+                }),
+                ..Default::default()
+            },
+        };
+
+        let valid = Gpu {
+            power_cap: Some(250.0),
+            clocks_configuration: ClocksConfiguration {
+                gpu_clock_offsets: IndexMap::from([(0, 150)]),
+                ..Default::default()
+            },
+            power_states: IndexMap::from([(PowerLevelKind::CoreClock, vec![0, 2])]),
+            pmfw_options: PmfwOptions {
+                acoustic_limit: Some(2500),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            Vec::<(String, String)>::new(),
+            problem_list(&valid, &limits)
+        );
+
+        let invalid = Gpu {
+            power_cap: Some(300.0),
+            clocks_configuration: ClocksConfiguration {
+                max_core_clock: Some(2000),
+                gpu_clock_offsets: IndexMap::from([(0, 250), (1, 50)]),
+                ..Default::default()
+            },
+            power_states: IndexMap::from([(PowerLevelKind::CoreClock, vec![1, 3])]),
+            pmfw_options: PmfwOptions {
+                acoustic_limit: Some(100),
+                zero_rpm: Some(true),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let expected = [
+            ("power_cap", "300 is above the maximum of 250"),
+            ("max_core_clock", "Not supported on this GPU"),
+            ("gpu_clock_offsets.0", "250 is above the maximum of 200"),
+            (
+                "gpu_clock_offsets.1",
+                "Power state 1 does not support clock offsets",
+            ),
+            ("power_states.core_clock", "Power state 3 does not exist"),
+            (
+                "pmfw_options.acoustic_limit",
+                "100 is below the minimum of 500",
+            ),
+            ("pmfw_options.zero_rpm", "Not supported on this GPU"),
+        ]
+        .map(|(field, message)| (field.to_owned(), message.to_owned()));
+        assert_eq!(expected.to_vec(), problem_list(&invalid, &limits));
+    }
+
+    fn problem_list(config: &Gpu, limits: &GpuLimits) -> Vec<(String, String)> {
+        validate_gpu_config(config, limits)
+            .into_iter()
+            .map(|problem| (problem.field, problem.message))
+            .collect()
+    }
+}
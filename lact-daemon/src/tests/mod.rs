@@ -17,7 +17,7 @@ async fn snapshot_everything() {
                 device_dir.file_name().to_string_lossy()
             );
 
-            let handler = Handler::with_base_path(&device_dir.path(), Config::default())
+            let handler = Handler::with_base_path(&device_dir.path(), Config::default(), None)
                 .await
                 .unwrap();
             let device_info = handler
@@ -28,7 +28,7 @@ use lact_client::{ConnectionStatusMsg, DaemonClient};
 use lact_schema::{
     args::GuiArgs,
     request::{ConfirmCommand, SetClocksCommand},
-    FanOptions, GIT_COMMIT,
+    CrashRecoveryInfo, FanOptions, GIT_COMMIT,
 };
 use msg::AppMsg;
 use pages::{
@@ -226,6 +226,12 @@ impl AsyncComponent for AppModel {
             show_embedded_info(&root, err);
         }
 
+        match model.daemon_client.get_crash_recovery_info().await {
+            Ok(Some(info)) => show_crash_recovery_info(&root, &info),
+            Ok(None) => (),
+            Err(err) => warn!("could not get crash recovery info: {err:#}"),
+        }
+
         model
             .header
             .widgets()
@@ -342,7 +348,7 @@ impl AppModel {
                     .set_clocks_value(&gpu_id, SetClocksCommand::reset())
                     .await?;
                 self.daemon_client
-                    .confirm_pending_config(ConfirmCommand::Confirm)
+                    .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                     .await?;
                 sender.input(AppMsg::ReloadData { full: false });
             }
@@ -350,7 +356,7 @@ impl AppModel {
                 let gpu_id = self.current_gpu_id()?;
                 self.daemon_client.reset_pmfw(&gpu_id).await?;
                 self.daemon_client
-                    .confirm_pending_config(ConfirmCommand::Confirm)
+                    .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                     .await?;
                 sender.input(AppMsg::ReloadData { full: false });
             }
@@ -572,7 +578,7 @@ impl AppModel {
                 .context("Failed to set power cap")?;
 
             self.daemon_client
-                .confirm_pending_config(ConfirmCommand::Confirm)
+                .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                 .await
                 .context("Could not commit config")?;
         }
@@ -583,7 +589,7 @@ impl AppModel {
             .await
             .context("Could not set default power profile mode")?;
         self.daemon_client
-            .confirm_pending_config(ConfirmCommand::Confirm)
+            .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
             .await
             .context("Could not commit config")?;
 
@@ -594,7 +600,7 @@ impl AppModel {
                 .await
                 .context("Failed to set power profile")?;
             self.daemon_client
-                .confirm_pending_config(ConfirmCommand::Confirm)
+                .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                 .await
                 .context("Could not commit config")?;
 
@@ -614,7 +620,7 @@ impl AppModel {
                 .await
                 .context("Could not set active power profile mode")?;
             self.daemon_client
-                .confirm_pending_config(ConfirmCommand::Confirm)
+                .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                 .await
                 .context("Could not commit config")?;
         }
@@ -630,6 +636,7 @@ impl AppModel {
                 pmfw: thermals_settings.pmfw,
                 spindown_delay_ms: thermals_settings.spindown_delay_ms,
                 change_threshold: thermals_settings.change_threshold,
+                ..Default::default()
             };
 
             self.daemon_client
@@ -637,7 +644,7 @@ impl AppModel {
                 .await
                 .context("Could not set fan control")?;
             self.daemon_client
-                .confirm_pending_config(ConfirmCommand::Confirm)
+                .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                 .await
                 .context("Could not commit config")?;
         }
@@ -655,7 +662,7 @@ impl AppModel {
                 .context("Could not set power states")?;
 
             self.daemon_client
-                .confirm_pending_config(ConfirmCommand::Confirm)
+                .confirm_pending_config(Some(&gpu_id), ConfirmCommand::Confirm)
                 .await
                 .context("Could not commit config")?;
         }
@@ -666,7 +673,8 @@ impl AppModel {
                 .batch_set_clocks_value(&gpu_id, clocks_commands)
                 .await
                 .context("Could not commit clocks settings")?;
-            self.ask_settings_confirmation(delay, root, sender).await;
+            self.ask_settings_confirmation(gpu_id, delay, root, sender)
+                .await;
         }
 
         sender.input(AppMsg::ReloadData { full: false });
@@ -676,6 +684,7 @@ impl AppModel {
 
     async fn ask_settings_confirmation(
         &self,
+        gpu_id: String,
         mut delay: u64,
         window: &gtk::ApplicationWindow,
         sender: &AsyncComponentSender<AppModel>,
@@ -738,7 +747,10 @@ impl AppModel {
                 diag.close();
 
                 relm4::spawn_local(async move {
-                    if let Err(err) = daemon_client.confirm_pending_config(command).await {
+                    if let Err(err) = daemon_client
+                        .confirm_pending_config(Some(&gpu_id), command)
+                        .await
+                    {
                         show_error(&window, &err);
                     }
                     sender.input(AppMsg::ReloadData { full: false });
@@ -869,6 +881,35 @@ fn show_error(parent: &ApplicationWindow, err: &anyhow::Error) {
     })
 }
 
+fn show_crash_recovery_info(parent: &ApplicationWindow, info: &CrashRecoveryInfo) {
+    let applied_at = chrono::DateTime::from_timestamp_millis(info.applied_at)
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    let text = format!(
+        "The system did not stay up after clock and voltage settings were applied at {applied_at}, \
+        so they were skipped to avoid crashing again.\n\
+        The last known good settings are used instead for the following GPUs:\n{}\n\n\
+        Once new settings are applied and confirmed on a GPU, they replace the skipped settings.",
+        info.gpu_ids.join("\n")
+    );
+
+    let diag = MessageDialog::builder()
+        .title("Settings skipped")
+        .message_type(MessageType::Warning)
+        .text(text)
+        .buttons(ButtonsType::Close)
+        .transient_for(parent)
+        .build();
+    diag.run_async(|diag, _| {
+        diag.close();
+    });
+}
+
 fn show_embedded_info(parent: &ApplicationWindow, err: anyhow::Error) {
     let error_text = format!("Error info: {err:#}\n\n");
 
@@ -236,7 +236,10 @@ impl Component for Header {
                 widgets.menu_button.popdown();
             }
             HeaderMsg::Profiles(profiles_info) => self.set_profiles_info(*profiles_info),
-            HeaderMsg::SelectGpu => sender.output(AppMsg::ReloadData { full: true }).unwrap(),
+            HeaderMsg::SelectGpu => {
+                self.update_selected_profile();
+                sender.output(AppMsg::ReloadData { full: true }).unwrap();
+            }
             HeaderMsg::AutoProfileSwitch(auto_switch) => {
                 let msg = AppMsg::SelectProfile {
                     profile: self
@@ -250,7 +253,7 @@ impl Component for Header {
             HeaderMsg::SelectProfile => {
                 let profile = self.selected_profile();
 
-                if self.profiles_info.current_profile.as_deref() != profile {
+                if self.active_profile() != profile {
                     if self.profiles_info.auto_switch {
                         // Revert to the previous profile
                         self.update_selected_profile();
@@ -316,6 +319,7 @@ impl Header {
                 last: i == last,
                 auto: profiles_info.auto_switch,
                 rule: rule.clone(),
+                inheritance: profiles_info.inheritance.get(name).cloned(),
             };
             profiles.push_back(profile);
         }
@@ -334,8 +338,19 @@ impl Header {
         }
     }
 
+    /// Active profile of the selected GPU
+    fn active_profile(&self) -> Option<&str> {
+        let gpu_profile = self
+            .selected_gpu_id()
+            .and_then(|gpu_id| self.profiles_info.gpu_profiles.get(&gpu_id));
+        match gpu_profile {
+            Some(profile) => profile.as_deref(),
+            None => self.profiles_info.current_profile.as_deref(),
+        }
+    }
+
     fn update_selected_profile(&self) {
-        let selected_profile_index = self.profiles_info.current_profile.as_ref().map(|profile| {
+        let selected_profile_index = self.active_profile().map(|profile| {
             self.profiles_info
                 .profiles
                 .iter()
@@ -87,7 +87,8 @@ impl Component for NewProfileDialog {
         sender: ComponentSender<Self>,
     ) -> ComponentParts<Self> {
         let mut variants = vec![ProfileBase::Empty, ProfileBase::Default];
-        variants.extend(current_profiles.into_iter().map(ProfileBase::Profile));
+        variants.extend(current_profiles.iter().cloned().map(ProfileBase::Profile));
+        variants.extend(current_profiles.into_iter().map(ProfileBase::Inherit));
 
         let base_selector = SimpleComboBox::<ProfileBase>::builder()
             .launch(SimpleComboBox {
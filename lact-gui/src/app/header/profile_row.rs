@@ -1,7 +1,7 @@
 use super::HeaderMsg;
 use crate::app::{msg::AppMsg, APP_BROKER};
 use gtk::{pango, prelude::*};
-use lact_schema::ProfileRule;
+use lact_schema::{ProfileInheritance, ProfileRule};
 use relm4::{
     factory::{DynamicIndex, FactoryComponent},
     FactorySender, RelmWidgetExt,
@@ -20,6 +20,7 @@ pub enum ProfileRowType {
         last: bool,
         auto: bool,
         rule: Option<ProfileRule>,
+        inheritance: Option<ProfileInheritance>,
     },
 }
 
@@ -56,6 +57,13 @@ impl FactoryComponent for ProfileRow {
                 set_width_request: 200,
             },
 
+            gtk::Image {
+                set_icon_name: Some("emblem-shared-symbolic"),
+                set_margin_horizontal: 5,
+                set_visible: inheritance_tooltip(&self.row).is_some(),
+                set_tooltip: &inheritance_tooltip(&self.row).unwrap_or_default(),
+            },
+
             gtk::Button {
                 set_icon_name: "preferences-other-symbolic",
                 set_tooltip: "Edit Profile Rules",
@@ -109,6 +117,35 @@ impl FactoryComponent for ProfileRow {
     }
 }
 
+/// Describes which settings are inherited from the parent profile
+fn inheritance_tooltip(row: &ProfileRowType) -> Option<String> {
+    let ProfileRowType::Profile {
+        inheritance: Some(inheritance),
+        ..
+    } = row
+    else {
+        return None;
+    };
+
+    let mut text = format!("Inherits settings from {}", inheritance.parent);
+    let overridden = inheritance
+        .overridden
+        .iter()
+        .filter(|(_, settings)| !settings.is_empty());
+    for (gpu_id, settings) in overridden {
+        text.push_str(&format!("\n\nOverridden on {gpu_id}:"));
+        for setting in settings {
+            text.push_str(&format!("\n  {setting}"));
+        }
+    }
+    if inheritance.overridden.values().all(Vec::is_empty) {
+        text.push_str("\nAll settings are inherited");
+    } else {
+        text.push_str("\n\nAll other settings are inherited");
+    }
+    Some(text)
+}
+
 fn move_profile_msg(profile: &ProfileRowType, index: &DynamicIndex, offset: i64) -> AppMsg {
     let name = profile.name().expect("Default profile cannot be moved");
     let new_index = (index.current_index() as i64).saturating_add(offset);
@@ -3,14 +3,19 @@ use std::time::Duration;
 use crate::app::{msg::AppMsg, APP_BROKER};
 use gtk::{
     glib::{GStr, GString},
+    pango,
     prelude::{
-        BoxExt, CheckButtonExt, DialogExt, DialogExtManual, EditableExt, EntryBufferExtManual,
-        EntryExt, GridExt, GtkWindowExt, ObjectExt, OrientableExt, PopoverExt, SelectionModelExt,
-        WidgetExt,
+        BoxExt, ButtonExt, CheckButtonExt, DialogExt, DialogExtManual, EditableExt,
+        EntryBufferExtManual, EntryExt, GridExt, GtkWindowExt, ObjectExt, OrientableExt,
+        PopoverExt, SelectionModelExt, WidgetExt,
     },
     SingleSelection,
 };
-use lact_schema::{ProcessInfo, ProcessProfileRule, ProfileRule, ProfileWatcherState};
+use lact_schema::{
+    MatchMode, PowerSource, PowerSourceProfileRule, ProcessInfo, ProcessProfileRule, ProfileRule,
+    ProfileSensor, ProfileWatcherState, ScheduleProfileRule, SensorComparison, SensorProfileRule,
+    TimeOfDay, Weekday,
+};
 use relm4::{
     tokio::time::sleep,
     typed_view::list::{RelmListItem, TypedListView},
@@ -22,12 +27,25 @@ const EVALUATE_INTERVAL_MS: u64 = 250;
 
 const PROCESS_PAGE: &str = "process";
 const GAMEMODE_PAGE: &str = "gamemode";
+const SCHEDULE_PAGE: &str = "schedule";
+const POWER_SOURCE_PAGE: &str = "power_source";
+const SENSOR_PAGE: &str = "sensor";
+const COMBINED_PAGE: &str = "combined";
 
 pub struct ProfileRuleWindow {
     profile_name: String,
     process_name_buffer: gtk::EntryBuffer,
     args_buffer: gtk::EntryBuffer,
+    exe_buffer: gtk::EntryBuffer,
+    cwd_buffer: gtk::EntryBuffer,
+    /// The whole rule that is being edited
     rule: ProfileRule,
+    /// Location of the currently shown rule inside of `rule`, as indices of nested rule lists
+    path: Vec<usize>,
+    /// Nested rules of the currently shown `all`/`any` rule
+    sub_rules: Vec<ProfileRule>,
+    /// Checkbuttons for every day in `Weekday::ALL`
+    weekday_checkbuttons: Vec<gtk::CheckButton>,
     process_list_view: TypedListView<ProcessListItem, SingleSelection>,
     currently_matches: bool,
 }
@@ -41,6 +59,10 @@ pub enum ProfileRuleWindowMsg {
     ProcessFilterChanged(GString),
     WatcherState(ProfileWatcherState),
     SetFromSelectedProcess,
+    AddSubRule,
+    EditSubRule(usize),
+    RemoveSubRule(usize),
+    EditParentRule,
     Evaluate,
     EvaluationResult(bool),
     Save,
@@ -95,6 +117,14 @@ impl relm4::Component for ProfileRuleWindow {
                         set_margin_all: 10,
                         set_spacing: 10,
 
+                        gtk::Button {
+                            set_label: "Back to the parent condition",
+                            set_halign: gtk::Align::Start,
+                            #[watch]
+                            set_visible: !model.path.is_empty(),
+                            connect_clicked => ProfileRuleWindowMsg::EditParentRule,
+                        },
+
                         #[name = "stack"]
                         gtk::Stack {
                             connect_visible_child_name_notify => ProfileRuleWindowMsg::Evaluate,
@@ -164,6 +194,50 @@ impl relm4::Component for ProfileRuleWindow {
                                     set_sensitive: false,
                                     connect_changed => ProfileRuleWindowMsg::Evaluate,
                                 },
+
+                                attach[0, 2, 1, 1] = &gtk::Label {
+                                    set_label: "Executable Path:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 2, 1, 1]: filter_by_exe_checkbutton = &gtk::CheckButton {
+                                    connect_toggled => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[2, 2, 1, 1]: exe_entry = &gtk::Entry {
+                                    set_buffer: &model.exe_buffer,
+                                    set_hexpand: true,
+                                    set_placeholder_text: Some("/usr/bin/vkcube"),
+                                    set_sensitive: false,
+                                    connect_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 3, 1, 1] = &gtk::Label {
+                                    set_label: "Working Directory:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 3, 1, 1]: filter_by_cwd_checkbutton = &gtk::CheckButton {
+                                    connect_toggled => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[2, 3, 1, 1]: cwd_entry = &gtk::Entry {
+                                    set_buffer: &model.cwd_buffer,
+                                    set_hexpand: true,
+                                    set_sensitive: false,
+                                    connect_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 4, 1, 1] = &gtk::Label {
+                                    set_label: "Match Mode:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[2, 4, 1, 1]: match_mode_drop_down = &gtk::DropDown::from_strings(&["Exact", "Glob pattern", "Regular expression"]) {
+                                    set_halign: gtk::Align::Start,
+                                    set_tooltip_text: Some("Glob patterns need to match the whole value, while regular expressions only need to match a part of it. Use ^ and $ to match the whole value with a regular expression."),
+                                    connect_selected_notify => ProfileRuleWindowMsg::Evaluate,
+                                },
                             },
 
                             add_titled[Some(GAMEMODE_PAGE), "Gamemode is active"] = &gtk::Grid {
@@ -204,10 +278,231 @@ impl relm4::Component for ProfileRuleWindow {
                                 },
                             },
 
-                            set_visible_child_name: match &model.rule {
-                                ProfileRule::Process(_) => PROCESS_PAGE,
-                                ProfileRule::Gamemode(_) => GAMEMODE_PAGE,
-                            }
+                            add_titled[Some(SCHEDULE_PAGE), "At a specific time"] = &gtk::Grid {
+                                set_row_spacing: 5,
+                                set_column_spacing: 10,
+
+                                attach[0, 0, 1, 1] = &gtk::Label {
+                                    set_label: "Days:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 0, 1, 1]: weekdays_box = &gtk::Box {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_spacing: 5,
+                                },
+
+                                attach[0, 1, 1, 1] = &gtk::Label {
+                                    set_label: "From:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 1, 1, 1] = &gtk::Box {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_spacing: 5,
+
+                                    #[name = "start_hour_spin"]
+                                    gtk::SpinButton::with_range(0.0, 23.0, 1.0) {
+                                        connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                    gtk::Label {
+                                        set_label: ":",
+                                    },
+                                    #[name = "start_minute_spin"]
+                                    gtk::SpinButton::with_range(0.0, 59.0, 1.0) {
+                                        connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                },
+
+                                attach[0, 2, 1, 1] = &gtk::Label {
+                                    set_label: "To:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 2, 1, 1] = &gtk::Box {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_spacing: 5,
+
+                                    #[name = "end_hour_spin"]
+                                    gtk::SpinButton::with_range(0.0, 23.0, 1.0) {
+                                        connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                    gtk::Label {
+                                        set_label: ":",
+                                    },
+                                    #[name = "end_minute_spin"]
+                                    gtk::SpinButton::with_range(0.0, 59.0, 1.0) {
+                                        connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                },
+
+                                attach[0, 3, 2, 1] = &gtk::Label {
+                                    set_label: "The time is in the local timezone. When no days are selected, every day is used.\nIf the end time is before the start time, the period continues into the next day.",
+                                    set_halign: gtk::Align::Start,
+                                    set_wrap: true,
+                                },
+                            },
+
+                            add_titled[Some(POWER_SOURCE_PAGE), "Power source"] = &gtk::Grid {
+                                set_row_spacing: 5,
+                                set_column_spacing: 10,
+
+                                attach[0, 0, 1, 1] = &gtk::Label {
+                                    set_label: "Power Source:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[2, 0, 1, 1]: power_source_drop_down = &gtk::DropDown::from_strings(&["Any", "AC power", "Battery"]) {
+                                    set_halign: gtk::Align::Start,
+                                    connect_selected_notify => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 1, 1, 1] = &gtk::Label {
+                                    set_label: "Minimum Battery Charge (%):",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 1, 1, 1]: filter_by_min_battery_checkbutton = &gtk::CheckButton {
+                                    connect_toggled => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[2, 1, 1, 1]: min_battery_spin = &gtk::SpinButton::with_range(0.0, 100.0, 1.0) {
+                                    set_halign: gtk::Align::Start,
+                                    set_sensitive: false,
+                                    connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 2, 1, 1] = &gtk::Label {
+                                    set_label: "Maximum Battery Charge (%):",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 2, 1, 1]: filter_by_max_battery_checkbutton = &gtk::CheckButton {
+                                    connect_toggled => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[2, 2, 1, 1]: max_battery_spin = &gtk::SpinButton::with_range(0.0, 100.0, 1.0) {
+                                    set_halign: gtk::Align::Start,
+                                    set_sensitive: false,
+                                    connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+                            },
+
+                            add_titled[Some(SENSOR_PAGE), "GPU sensor"] = &gtk::Grid {
+                                set_row_spacing: 5,
+                                set_column_spacing: 10,
+
+                                attach[0, 0, 1, 1] = &gtk::Label {
+                                    set_label: "Sensor:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 0, 1, 1]: sensor_drop_down = &gtk::DropDown::from_strings(&["Temperature", "GPU usage (%)", "Power draw (W)"]) {
+                                    set_halign: gtk::Align::Start,
+                                    connect_selected_notify => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 1, 1, 1] = &gtk::Label {
+                                    set_label: "Temperature Sensor:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 1, 1, 1]: temperature_key_entry = &gtk::Entry {
+                                    set_hexpand: true,
+                                    set_placeholder_text: Some("Highest temperature"),
+                                    connect_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 2, 1, 1] = &gtk::Label {
+                                    set_label: "Condition:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 2, 1, 1] = &gtk::Box {
+                                    set_orientation: gtk::Orientation::Horizontal,
+                                    set_spacing: 5,
+
+                                    #[name = "sensor_comparison_drop_down"]
+                                    gtk::DropDown::from_strings(&["Above", "Below"]) {
+                                        connect_selected_notify => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                    #[name = "sensor_threshold_spin"]
+                                    gtk::SpinButton::with_range(0.0, 1000.0, 1.0) {
+                                        connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                    },
+                                },
+
+                                attach[0, 3, 1, 1] = &gtk::Label {
+                                    set_label: "For At Least (s):",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 3, 1, 1]: sensor_duration_spin = &gtk::SpinButton::with_range(0.0, 3600.0, 1.0) {
+                                    set_halign: gtk::Align::Start,
+                                    connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 4, 1, 1] = &gtk::Label {
+                                    set_label: "Hysteresis:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 4, 1, 1]: sensor_hysteresis_spin = &gtk::SpinButton::with_range(0.0, 100.0, 1.0) {
+                                    set_halign: gtk::Align::Start,
+                                    connect_value_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 5, 1, 1] = &gtk::Label {
+                                    set_label: "GPU ID:",
+                                    set_halign: gtk::Align::Start,
+                                },
+
+                                attach[1, 5, 1, 1]: sensor_gpu_id_entry = &gtk::Entry {
+                                    set_hexpand: true,
+                                    set_placeholder_text: Some("Each GPU separately"),
+                                    connect_changed => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                attach[0, 6, 2, 1] = &gtk::Label {
+                                    set_label: "Once the condition is met, the value needs to get past the threshold by the hysteresis for the same duration to stop matching.",
+                                    set_halign: gtk::Align::Start,
+                                    set_wrap: true,
+                                },
+                            },
+
+                            add_titled[Some(COMBINED_PAGE), "Multiple conditions"] = &gtk::Box {
+                                set_orientation: gtk::Orientation::Vertical,
+                                set_spacing: 5,
+
+                                #[name = "combination_drop_down"]
+                                gtk::DropDown::from_strings(&["All conditions are met", "Any condition is met"]) {
+                                    set_halign: gtk::Align::Start,
+                                    connect_selected_notify => ProfileRuleWindowMsg::Evaluate,
+                                },
+
+                                gtk::ScrolledWindow {
+                                    set_vexpand: true,
+
+                                    #[name = "sub_rules_listbox"]
+                                    gtk::ListBox {
+                                        set_selection_mode: gtk::SelectionMode::None,
+                                    },
+                                },
+
+                                gtk::Button {
+                                    set_label: "Add Condition",
+                                    set_halign: gtk::Align::Start,
+                                    connect_clicked => ProfileRuleWindowMsg::AddSubRule,
+                                },
+                            },
+
+                            set_visible_child_name: PROCESS_PAGE,
+                        },
+
+                        #[name = "negate_checkbutton"]
+                        gtk::CheckButton {
+                            set_label: Some("Invert (activate when this condition is not met)"),
+                            connect_toggled => ProfileRuleWindowMsg::Evaluate,
                         },
 
                         gtk::Separator {},
@@ -255,11 +550,28 @@ impl relm4::Component for ProfileRuleWindow {
             }
         });
 
+        let weekday_checkbuttons = Weekday::ALL
+            .iter()
+            .map(|day| {
+                let checkbutton = gtk::CheckButton::with_label(&day.to_string()[..3]);
+                let sender = sender.clone();
+                checkbutton.connect_toggled(move |_| {
+                    sender.input(ProfileRuleWindowMsg::Evaluate);
+                });
+                checkbutton
+            })
+            .collect();
+
         let mut model = Self {
             rule: ProfileRule::default(),
+            path: Vec::new(),
+            sub_rules: Vec::new(),
+            weekday_checkbuttons,
             profile_name: String::new(),
             process_name_buffer: gtk::EntryBuffer::new(GStr::NONE),
             args_buffer: gtk::EntryBuffer::new(GStr::NONE),
+            exe_buffer: gtk::EntryBuffer::new(GStr::NONE),
+            cwd_buffer: gtk::EntryBuffer::new(GStr::NONE),
             process_list_view: TypedListView::new(),
             currently_matches: false,
         };
@@ -272,6 +584,10 @@ impl relm4::Component for ProfileRuleWindow {
         let process_listview = &model.process_list_view.view;
         let widgets = view_output!();
 
+        for checkbutton in &model.weekday_checkbuttons {
+            widgets.weekdays_box.append(checkbutton);
+        }
+
         model.process_list_view.add_filter({
             let search_entry = widgets.process_search_entry.clone();
             move |process| process.0.cmdline.contains(search_entry.text().as_str())
@@ -289,6 +605,30 @@ impl relm4::Component for ProfileRuleWindow {
             .bidirectional()
             .build();
 
+        widgets
+            .filter_by_exe_checkbutton
+            .bind_property("active", &widgets.exe_entry, "sensitive")
+            .bidirectional()
+            .build();
+
+        widgets
+            .filter_by_cwd_checkbutton
+            .bind_property("active", &widgets.cwd_entry, "sensitive")
+            .bidirectional()
+            .build();
+
+        widgets
+            .filter_by_min_battery_checkbutton
+            .bind_property("active", &widgets.min_battery_spin, "sensitive")
+            .bidirectional()
+            .build();
+
+        widgets
+            .filter_by_max_battery_checkbutton
+            .bind_property("active", &widgets.max_battery_spin, "sensitive")
+            .bidirectional()
+            .build();
+
         widgets
             .gamemode_filter_by_process_checkbutton
             .bind_property("active", &widgets.gamemode_process_name_entry, "sensitive")
@@ -314,37 +654,9 @@ impl relm4::Component for ProfileRuleWindow {
         match msg {
             ProfileRuleWindowMsg::Show { profile_name, rule } => {
                 self.profile_name = profile_name;
-
-                let page = match rule {
-                    ProfileRule::Process(rule) => {
-                        self.process_name_buffer.set_text(rule.name.as_ref());
-                        self.args_buffer
-                            .set_text(rule.args.as_deref().unwrap_or_default());
-                        PROCESS_PAGE
-                    }
-                    ProfileRule::Gamemode(Some(rule)) => {
-                        self.process_name_buffer.set_text(rule.name.as_ref());
-                        self.args_buffer
-                            .set_text(rule.args.as_deref().unwrap_or_default());
-                        GAMEMODE_PAGE
-                    }
-                    ProfileRule::Gamemode(None) => {
-                        self.process_name_buffer.set_text("");
-                        self.args_buffer.set_text("");
-                        GAMEMODE_PAGE
-                    }
-                };
-                widgets.stack.set_visible_child_name(page);
-
-                widgets
-                    .filter_by_args_checkbutton
-                    .set_active(self.args_buffer.length() > 0);
-                widgets
-                    .gamemode_filter_by_process_checkbutton
-                    .set_active(self.process_name_buffer.length() > 0);
-                widgets
-                    .gamemode_filter_by_args_checkbutton
-                    .set_active(self.args_buffer.length() > 0);
+                self.path.clear();
+                self.set_rule(widgets, &sender, &rule);
+                self.rule = rule;
 
                 root.present();
             }
@@ -376,14 +688,35 @@ impl relm4::Component for ProfileRuleWindow {
                     let info = &item.borrow().0;
                     self.process_name_buffer.set_text(info.name.as_ref());
                     self.args_buffer.set_text(info.cmdline.as_ref());
+                    self.exe_buffer
+                        .set_text(info.exe.as_deref().unwrap_or_default());
+                    self.cwd_buffer
+                        .set_text(info.cwd.as_deref().unwrap_or_default());
                 }
 
                 self.process_list_view.selection_model.unselect_all();
                 widgets.process_filter_popover.popdown();
             }
+            ProfileRuleWindowMsg::AddSubRule => {
+                self.sub_rules.push(ProfileRule::default());
+                self.edit_sub_rule(widgets, &sender, self.sub_rules.len() - 1);
+            }
+            ProfileRuleWindowMsg::EditSubRule(index) => {
+                self.edit_sub_rule(widgets, &sender, index);
+            }
+            ProfileRuleWindowMsg::RemoveSubRule(index) => {
+                self.sub_rules.remove(index);
+                self.update_sub_rules_list(widgets, &sender);
+            }
+            ProfileRuleWindowMsg::EditParentRule => {
+                self.store_rule(widgets);
+                self.path.pop();
+                let rule = rule_at_path(&mut self.rule, &self.path).clone();
+                self.set_rule(widgets, &sender, &rule);
+            }
             ProfileRuleWindowMsg::Evaluate => {
                 if root.is_visible() {
-                    let rule = self.get_rule(widgets);
+                    let rule = self.get_full_rule(widgets);
                     APP_BROKER.send(AppMsg::EvaluateProfile(rule));
                 }
             }
@@ -393,7 +726,7 @@ impl relm4::Component for ProfileRuleWindow {
             ProfileRuleWindowMsg::Save => {
                 APP_BROKER.send(AppMsg::SetProfileRule {
                     name: self.profile_name.clone(),
-                    rule: Some(self.get_rule(widgets)),
+                    rule: Some(self.get_full_rule(widgets)),
                 });
             }
         }
@@ -403,7 +736,228 @@ impl relm4::Component for ProfileRuleWindow {
 }
 
 impl ProfileRuleWindow {
+    /// Shows the given rule in the editor
+    fn set_rule(
+        &mut self,
+        widgets: &ProfileRuleWindowWidgets,
+        sender: &ComponentSender<Self>,
+        mut rule: &ProfileRule,
+    ) {
+        let mut negated = false;
+        while let ProfileRule::Not(inner) = rule {
+            negated = !negated;
+            rule = inner;
+        }
+
+        self.sub_rules.clear();
+        self.exe_buffer.set_text("");
+        self.cwd_buffer.set_text("");
+        widgets.match_mode_drop_down.set_selected(0);
+
+        let page = match rule {
+            ProfileRule::Process(rule) => {
+                self.process_name_buffer.set_text(rule.name.as_ref());
+                self.args_buffer
+                    .set_text(rule.args.as_deref().unwrap_or_default());
+                self.exe_buffer
+                    .set_text(rule.exe.as_deref().unwrap_or_default());
+                self.cwd_buffer
+                    .set_text(rule.cwd.as_deref().unwrap_or_default());
+                let match_mode_index = match rule.match_mode.unwrap_or_default() {
+                    MatchMode::Exact => 0,
+                    MatchMode::Glob => 1,
+                    MatchMode::Regex => 2,
+                };
+                widgets.match_mode_drop_down.set_selected(match_mode_index);
+                PROCESS_PAGE
+            }
+            ProfileRule::Gamemode(Some(rule)) => {
+                self.process_name_buffer.set_text(rule.name.as_ref());
+                self.args_buffer
+                    .set_text(rule.args.as_deref().unwrap_or_default());
+                GAMEMODE_PAGE
+            }
+            ProfileRule::Gamemode(None) => {
+                self.process_name_buffer.set_text("");
+                self.args_buffer.set_text("");
+                GAMEMODE_PAGE
+            }
+            ProfileRule::All(rules) => {
+                self.sub_rules.clone_from(rules);
+                widgets.combination_drop_down.set_selected(0);
+                COMBINED_PAGE
+            }
+            ProfileRule::Any(rules) => {
+                self.sub_rules.clone_from(rules);
+                widgets.combination_drop_down.set_selected(1);
+                COMBINED_PAGE
+            }
+            ProfileRule::Schedule(rule) => {
+                for (day, checkbutton) in Weekday::ALL.iter().zip(&self.weekday_checkbuttons) {
+                    checkbutton.set_active(rule.days.contains(day));
+                }
+                widgets.start_hour_spin.set_value(rule.start.hour().into());
+                widgets
+                    .start_minute_spin
+                    .set_value(rule.start.minute().into());
+                widgets.end_hour_spin.set_value(rule.end.hour().into());
+                widgets.end_minute_spin.set_value(rule.end.minute().into());
+                SCHEDULE_PAGE
+            }
+            ProfileRule::PowerSource(rule) => {
+                let source_index = match rule.source {
+                    None => 0,
+                    Some(PowerSource::Ac) => 1,
+                    Some(PowerSource::Battery) => 2,
+                };
+                widgets.power_source_drop_down.set_selected(source_index);
+                widgets
+                    .filter_by_min_battery_checkbutton
+                    .set_active(rule.min_battery.is_some());
+                widgets
+                    .min_battery_spin
+                    .set_value(rule.min_battery.unwrap_or(0).into());
+                widgets
+                    .filter_by_max_battery_checkbutton
+                    .set_active(rule.max_battery.is_some());
+                widgets
+                    .max_battery_spin
+                    .set_value(rule.max_battery.unwrap_or(100).into());
+                POWER_SOURCE_PAGE
+            }
+            ProfileRule::Sensor(rule) => {
+                let sensor_index = match rule.sensor {
+                    ProfileSensor::Temperature => 0,
+                    ProfileSensor::BusyPercent => 1,
+                    ProfileSensor::PowerDraw => 2,
+                };
+                widgets.sensor_drop_down.set_selected(sensor_index);
+                widgets
+                    .temperature_key_entry
+                    .set_text(rule.temperature_key.as_deref().unwrap_or_default());
+                let comparison_index = match rule.comparison {
+                    SensorComparison::Above => 0,
+                    SensorComparison::Below => 1,
+                };
+                widgets
+                    .sensor_comparison_drop_down
+                    .set_selected(comparison_index);
+                widgets.sensor_threshold_spin.set_value(rule.threshold);
+                widgets
+                    .sensor_duration_spin
+                    .set_value(rule.duration_secs as f64);
+                widgets.sensor_hysteresis_spin.set_value(rule.hysteresis);
+                widgets
+                    .sensor_gpu_id_entry
+                    .set_text(rule.gpu_id.as_deref().unwrap_or_default());
+                SENSOR_PAGE
+            }
+            ProfileRule::Not(_) => unreachable!(),
+        };
+        widgets.stack.set_visible_child_name(page);
+
+        widgets
+            .filter_by_args_checkbutton
+            .set_active(self.args_buffer.length() > 0);
+        widgets
+            .filter_by_exe_checkbutton
+            .set_active(self.exe_buffer.length() > 0);
+        widgets
+            .filter_by_cwd_checkbutton
+            .set_active(self.cwd_buffer.length() > 0);
+        widgets
+            .gamemode_filter_by_process_checkbutton
+            .set_active(self.process_name_buffer.length() > 0);
+        widgets
+            .gamemode_filter_by_args_checkbutton
+            .set_active(self.args_buffer.length() > 0);
+        widgets.negate_checkbutton.set_active(negated);
+
+        self.update_sub_rules_list(widgets, sender);
+    }
+
+    fn update_sub_rules_list(
+        &self,
+        widgets: &ProfileRuleWindowWidgets,
+        sender: &ComponentSender<Self>,
+    ) {
+        let listbox = &widgets.sub_rules_listbox;
+        while let Some(row) = listbox.row_at_index(0) {
+            listbox.remove(&row);
+        }
+
+        for (index, rule) in self.sub_rules.iter().enumerate() {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+
+            let label = gtk::Label::builder()
+                .label(rule.to_string())
+                .halign(gtk::Align::Start)
+                .hexpand(true)
+                .ellipsize(pango::EllipsizeMode::End)
+                .margin_start(5)
+                .build();
+            row.append(&label);
+
+            let edit_button = gtk::Button::builder()
+                .icon_name("preferences-other-symbolic")
+                .tooltip_text("Edit Condition")
+                .build();
+            let edit_sender = sender.clone();
+            edit_button.connect_clicked(move |_| {
+                edit_sender.input(ProfileRuleWindowMsg::EditSubRule(index));
+            });
+            row.append(&edit_button);
+
+            let remove_button = gtk::Button::builder()
+                .icon_name("list-remove")
+                .tooltip_text("Remove Condition")
+                .build();
+            let remove_sender = sender.clone();
+            remove_button.connect_clicked(move |_| {
+                remove_sender.input(ProfileRuleWindowMsg::RemoveSubRule(index));
+            });
+            row.append(&remove_button);
+
+            listbox.append(&row);
+        }
+    }
+
+    fn edit_sub_rule(
+        &mut self,
+        widgets: &ProfileRuleWindowWidgets,
+        sender: &ComponentSender<Self>,
+        index: usize,
+    ) {
+        self.store_rule(widgets);
+        self.path.push(index);
+        let rule = rule_at_path(&mut self.rule, &self.path).clone();
+        self.set_rule(widgets, sender, &rule);
+    }
+
+    /// Writes the currently shown rule back into the whole rule
+    fn store_rule(&mut self, widgets: &ProfileRuleWindowWidgets) {
+        let rule = self.get_rule(widgets);
+        *rule_at_path(&mut self.rule, &self.path) = rule;
+    }
+
+    /// The whole rule, including the changes to the currently shown rule
+    fn get_full_rule(&self, widgets: &ProfileRuleWindowWidgets) -> ProfileRule {
+        let mut full_rule = self.rule.clone();
+        *rule_at_path(&mut full_rule, &self.path) = self.get_rule(widgets);
+        full_rule
+    }
+
+    /// The currently shown rule
     fn get_rule(&self, widgets: &ProfileRuleWindowWidgets) -> ProfileRule {
+        let rule = self.get_page_rule(widgets);
+        if widgets.negate_checkbutton.is_active() {
+            ProfileRule::Not(Box::new(rule))
+        } else {
+            rule
+        }
+    }
+
+    fn get_page_rule(&self, widgets: &ProfileRuleWindowWidgets) -> ProfileRule {
         let process_name = self.process_name_buffer.text();
         let process_args = self.args_buffer.text();
 
@@ -414,9 +968,25 @@ impl ProfileRuleWindow {
                 } else {
                     None
                 };
+                let exe = widgets
+                    .filter_by_exe_checkbutton
+                    .is_active()
+                    .then(|| self.exe_buffer.text().into());
+                let cwd = widgets
+                    .filter_by_cwd_checkbutton
+                    .is_active()
+                    .then(|| self.cwd_buffer.text().into());
+                let match_mode = match widgets.match_mode_drop_down.selected() {
+                    1 => Some(MatchMode::Glob),
+                    2 => Some(MatchMode::Regex),
+                    _ => None,
+                };
                 ProfileRule::Process(ProcessProfileRule {
                     name: process_name.as_str().into(),
                     args,
+                    exe,
+                    cwd,
+                    match_mode,
                 })
             }
             Some(GAMEMODE_PAGE) => {
@@ -433,15 +1003,110 @@ impl ProfileRuleWindow {
                     Some(ProcessProfileRule {
                         name: process_name.as_str().into(),
                         args,
+                        ..Default::default()
                     })
                 };
                 ProfileRule::Gamemode(rule)
             }
+            Some(SCHEDULE_PAGE) => {
+                let days = Weekday::ALL
+                    .into_iter()
+                    .zip(&self.weekday_checkbuttons)
+                    .filter(|(_, checkbutton)| checkbutton.is_active())
+                    .map(|(day, _)| day)
+                    .collect();
+                let time = |hour: &gtk::SpinButton, minute: &gtk::SpinButton| {
+                    TimeOfDay::new(hour.value_as_int() as u8, minute.value_as_int() as u8)
+                        .unwrap_or_default()
+                };
+                ProfileRule::Schedule(ScheduleProfileRule {
+                    days,
+                    start: time(&widgets.start_hour_spin, &widgets.start_minute_spin),
+                    end: time(&widgets.end_hour_spin, &widgets.end_minute_spin),
+                })
+            }
+            Some(POWER_SOURCE_PAGE) => {
+                let source = match widgets.power_source_drop_down.selected() {
+                    1 => Some(PowerSource::Ac),
+                    2 => Some(PowerSource::Battery),
+                    _ => None,
+                };
+                let battery = |checkbutton: &gtk::CheckButton, spin: &gtk::SpinButton| {
+                    checkbutton.is_active().then(|| spin.value_as_int() as u8)
+                };
+                ProfileRule::PowerSource(PowerSourceProfileRule {
+                    source,
+                    min_battery: battery(
+                        &widgets.filter_by_min_battery_checkbutton,
+                        &widgets.min_battery_spin,
+                    ),
+                    max_battery: battery(
+                        &widgets.filter_by_max_battery_checkbutton,
+                        &widgets.max_battery_spin,
+                    ),
+                })
+            }
+            Some(SENSOR_PAGE) => {
+                let sensor = match widgets.sensor_drop_down.selected() {
+                    1 => ProfileSensor::BusyPercent,
+                    2 => ProfileSensor::PowerDraw,
+                    _ => ProfileSensor::Temperature,
+                };
+                let comparison = match widgets.sensor_comparison_drop_down.selected() {
+                    1 => SensorComparison::Below,
+                    _ => SensorComparison::Above,
+                };
+                let optional_text = |entry: &gtk::Entry| {
+                    let text = entry.text();
+                    let text = text.trim();
+                    (!text.is_empty()).then(|| text.to_owned())
+                };
+                let temperature_key = if sensor == ProfileSensor::Temperature {
+                    optional_text(&widgets.temperature_key_entry)
+                } else {
+                    None
+                };
+                ProfileRule::Sensor(SensorProfileRule {
+                    gpu_id: optional_text(&widgets.sensor_gpu_id_entry),
+                    sensor,
+                    temperature_key,
+                    comparison,
+                    threshold: widgets.sensor_threshold_spin.value(),
+                    duration_secs: widgets.sensor_duration_spin.value_as_int() as u64,
+                    hysteresis: widgets.sensor_hysteresis_spin.value(),
+                })
+            }
+            Some(COMBINED_PAGE) => {
+                let rules = self.sub_rules.clone();
+                if widgets.combination_drop_down.selected() == 1 {
+                    ProfileRule::Any(rules)
+                } else {
+                    ProfileRule::All(rules)
+                }
+            }
             _ => unreachable!(),
         }
     }
 }
 
+/// Finds a nested rule by the indices of the `all`/`any` rule lists leading to it
+fn rule_at_path<'a>(rule: &'a mut ProfileRule, path: &[usize]) -> &'a mut ProfileRule {
+    let Some((index, rest)) = path.split_first() else {
+        return rule;
+    };
+    match rule {
+        ProfileRule::Not(inner) => rule_at_path(inner, path),
+        ProfileRule::All(rules) | ProfileRule::Any(rules) => rule_at_path(&mut rules[*index], rest),
+        ProfileRule::Process(_)
+        | ProfileRule::Gamemode(_)
+        | ProfileRule::Schedule(_)
+        | ProfileRule::PowerSource(_)
+        | ProfileRule::Sensor(_) => {
+            unreachable!("Rule path points inside of a rule without nested rules")
+        }
+    }
+}
+
 struct ProcessListItem(ProcessInfo);
 
 struct ProcessListItemWidgets {
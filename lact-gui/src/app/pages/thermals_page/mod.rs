@@ -38,6 +38,7 @@ pub struct ThermalsSettings {
 pub struct ThermalsPage {
     pub container: Box,
     pmfw_warning_label: Label,
+    fan_alert_label: Label,
     temperatures_label: Label,
     fan_speed_label: Label,
     pmfw_frame: PmfwFrame,
@@ -46,6 +47,9 @@ pub struct ThermalsPage {
     fan_control_mode_stack: Stack,
     fan_control_mode_stack_switcher: StackSwitcher,
     is_amd: Rc<AtomicBool>,
+    /// Multi-sensor settings are shown on the curve page, but cannot be edited as a whole,
+    /// so their mode is kept when applying
+    is_multi_sensor: Rc<AtomicBool>,
 
     overdrive_enabled: Option<bool>,
 }
@@ -65,6 +69,13 @@ impl ThermalsPage {
             .build();
         container.append(&pmfw_warning_label);
 
+        let fan_alert_label = Label::builder()
+            .halign(Align::Start)
+            .wrap(true)
+            .visible(false)
+            .build();
+        container.append(&fan_alert_label);
+
         let stats_section = PageSection::new("Statistics");
         let stats_grid = values_grid();
 
@@ -120,6 +131,7 @@ impl ThermalsPage {
 
         Self {
             pmfw_warning_label,
+            fan_alert_label,
             container,
             temperatures_label,
             fan_speed_label,
@@ -130,6 +142,7 @@ impl ThermalsPage {
             pmfw_frame,
             overdrive_enabled: system_info.amdgpu_overdrive_enabled,
             is_amd,
+            is_multi_sensor: Rc::new(AtomicBool::new(false)),
         }
     }
 
@@ -193,6 +206,16 @@ impl ThermalsPage {
             None => self.fan_speed_label.set_text("No fan detected"),
         }
 
+        match &stats.fan.alert {
+            Some(alert) => {
+                self.fan_alert_label.set_text(&format!(
+                    "Warning: {alert}. Fan control was given back to the firmware, apply the settings again to resume it."
+                ));
+                self.fan_alert_label.set_visible(true);
+            }
+            None => self.fan_alert_label.set_visible(false),
+        }
+
         if initial {
             self.fan_control_mode_stack_switcher.set_visible(true);
             self.fan_control_mode_stack_switcher
@@ -201,13 +224,17 @@ impl ThermalsPage {
             let child_name = match stats.fan.control_mode {
                 Some(mode) if stats.fan.control_enabled => match mode {
                     FanControlMode::Static => "static",
-                    FanControlMode::Curve => "curve",
+                    FanControlMode::Curve | FanControlMode::MultiSensor => "curve",
                 },
                 _ => "automatic",
             };
 
             self.fan_control_mode_stack
                 .set_visible_child_name(child_name);
+            self.is_multi_sensor.store(
+                stats.fan.control_mode == Some(FanControlMode::MultiSensor),
+                Ordering::SeqCst,
+            );
 
             if let Some(static_speed) = &stats.fan.static_speed {
                 self.fan_static_speed_adjustment
@@ -275,7 +302,12 @@ impl ThermalsPage {
                 "automatic" => (false, None),
                 "curve" => {
                     pmfw.zero_rpm = self.fan_curve_frame.get_zero_rpm();
-                    (true, Some(FanControlMode::Curve))
+                    let mode = if self.is_multi_sensor.load(Ordering::SeqCst) {
+                        FanControlMode::MultiSensor
+                    } else {
+                        FanControlMode::Curve
+                    };
+                    (true, Some(mode))
                 }
                 "static" => (true, Some(FanControlMode::Static)),
                 _ => unreachable!(),
@@ -353,3 +385,42 @@ fn show_fan_control_warning() {
         diag.hide();
     })
 }
+
+#[cfg(all(test, feature = "gtk-tests"))]
+mod tests {
+    use super::ThermalsPage;
+    use lact_client::schema::{DeviceStats, FanControlMode, FanStats, SystemInfo};
+
+    fn fan_stats(mode: FanControlMode) -> DeviceStats {
+        DeviceStats {
+            fan: FanStats {
+                control_enabled: true,
+                control_mode: Some(mode),
+                pwm_current: Some(128),
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn keep_multi_sensor_mode() {
+        gtk::init().unwrap();
+
+        let page = ThermalsPage::new(&SystemInfo {
+            version: String::new(),
+            commit: None,
+            profile: String::new(),
+            kernel_version: String::new(),
+            amdgpu_overdrive_enabled: None,
+        });
+
+        page.set_stats(&fan_stats(FanControlMode::MultiSensor), true);
+        let settings = page.get_thermals_settings().unwrap();
+        assert_eq!(Some(FanControlMode::MultiSensor), settings.mode);
+
+        page.set_stats(&fan_stats(FanControlMode::Curve), true);
+        let settings = page.get_thermals_settings().unwrap();
+        assert_eq!(Some(FanControlMode::Curve), settings.mode);
+    }
+}
@@ -1,6 +1,9 @@
 pub use clap;
 
-use clap::{Parser, Subcommand};
+use crate::{request::ClockspeedType, FanSetting};
+use amdgpu_sysfs::gpu_handle::{PerformanceLevel, PowerLevelKind};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 pub struct Args {
@@ -30,8 +33,15 @@ pub struct GuiArgs {
 #[derive(Parser)]
 #[command(author, version, about)]
 pub struct CliArgs {
-    #[arg(short, long)]
+    /// GPU to use. Can be omitted for setting changes if there is only one GPU
+    #[arg(short, long, global = true)]
     pub gpu_id: Option<String>,
+    /// Print output as JSON
+    #[arg(long, global = true)]
+    pub json: bool,
+    /// Confirm setting changes immediately instead of leaving them pending
+    #[arg(long, global = true)]
+    pub confirm: bool,
     #[command(subcommand)]
     pub subcommand: CliCommand,
 }
@@ -44,4 +54,344 @@ pub enum CliCommand {
     Info,
     /// Generate debug snapshot
     Snapshot,
+    /// Set or reset the power cap
+    #[command(subcommand)]
+    PowerCap(PowerCapCommand),
+    /// Set the performance level
+    PerformanceLevel {
+        #[arg(value_enum)]
+        level: CliPerformanceLevel,
+    },
+    /// Change clockspeed and voltage settings
+    #[command(subcommand)]
+    Clocks(ClocksCommand),
+    /// Change fan control settings
+    #[command(subcommand)]
+    Fan(FanCommand),
+    /// Change PMFW fan settings (RDNA3+)
+    #[command(subcommand)]
+    Pmfw(PmfwCommand),
+    /// Set the enabled power states
+    PowerStates {
+        #[arg(value_enum)]
+        kind: CliPowerLevelKind,
+        /// Indices of the power states to enable. Enables all states if empty
+        states: Vec<u8>,
+    },
+    /// Set the power profile mode
+    PowerProfileMode {
+        /// Index of the power profile mode. Resets to the default mode if omitted
+        index: Option<u16>,
+        /// Custom power profile mode heuristics, as a comma-separated list per component.
+        /// Empty values are left unchanged
+        #[arg(long = "custom-heuristics", value_parser = parse_heuristics)]
+        custom_heuristics: Vec<Heuristics>,
+    },
+    /// List the configuration changes that are waiting to be confirmed
+    Pending,
+    /// Confirm the pending configuration change. Applies to all GPUs if `--gpu-id` is not specified
+    Confirm,
+    /// Revert the pending configuration change. Applies to all GPUs if `--gpu-id` is not specified
+    Revert,
+    /// Work with configuration files
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Check a config file against the limits of the GPUs without applying anything.
+    /// The file can either be a full config, or the settings of a single GPU
+    Check { file: PathBuf },
+}
+
+#[derive(Subcommand)]
+pub enum PowerCapCommand {
+    /// Set the power cap in watts
+    Set { value: f64 },
+    /// Reset the power cap to the default value
+    Reset,
+}
+
+#[derive(Subcommand)]
+pub enum ClocksCommand {
+    /// Set a clockspeed or voltage value
+    Set {
+        #[arg(value_enum)]
+        kind: CliClockspeedType,
+        value: i32,
+        /// Power state the offset applies to (Nvidia only)
+        #[arg(long, default_value_t = 0)]
+        pstate: u32,
+    },
+    /// Reset a single clockspeed or voltage value to the default
+    Unset {
+        #[arg(value_enum)]
+        kind: CliClockspeedType,
+        /// Power state the offset applies to (Nvidia only)
+        #[arg(long, default_value_t = 0)]
+        pstate: u32,
+    },
+    /// Set multiple values at once, in the format `type[:pstate]=value`
+    Batch {
+        #[arg(required = true, value_parser = parse_clocks_assignment)]
+        values: Vec<ClocksAssignment>,
+    },
+    /// Reset all clockspeed and voltage settings
+    Reset,
+}
+
+#[derive(Subcommand)]
+pub enum FanCommand {
+    /// Let the firmware control the fan automatically
+    Auto,
+    /// Use a static fan speed
+    Static {
+        /// Fan speed in percent
+        #[arg(value_parser = clap::value_parser!(u8).range(0..=100))]
+        speed: u8,
+    },
+    /// Use a fan curve
+    Curve {
+        /// Curve points in the format `temperature=percent`. Keeps the current curve if empty
+        #[arg(value_parser = parse_curve_point)]
+        points: Vec<(i32, f32)>,
+        #[arg(long)]
+        spindown_delay_ms: Option<u64>,
+        #[arg(long)]
+        change_threshold: Option<u64>,
+        /// Exponential moving average weight (0-1) of new temperature readings
+        #[arg(long, conflicts_with = "moving_average_samples")]
+        ema_alpha: Option<f32>,
+        /// Number of temperature readings to average
+        #[arg(long)]
+        moving_average_samples: Option<usize>,
+        /// Minimum temperature rise in degrees before the fan speeds up
+        #[arg(long)]
+        hysteresis_up: Option<u64>,
+        /// Minimum temperature drop in degrees before the fan slows down
+        #[arg(long)]
+        hysteresis_down: Option<u64>,
+        /// Maximum PWM change (out of 255) per second
+        #[arg(long)]
+        max_pwm_slew_rate: Option<u32>,
+        /// Reset a setting to its default. Can be specified multiple times
+        #[arg(long, value_enum)]
+        clear: Vec<CliFanSetting>,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PmfwCommand {
+    /// Set PMFW options. Options that are not specified keep their current value
+    Set {
+        #[arg(long)]
+        acoustic_limit: Option<u32>,
+        #[arg(long)]
+        acoustic_target: Option<u32>,
+        #[arg(long)]
+        minimum_pwm: Option<u32>,
+        #[arg(long)]
+        target_temperature: Option<u32>,
+        #[arg(long)]
+        zero_rpm: Option<bool>,
+        #[arg(long)]
+        zero_rpm_threshold: Option<u32>,
+    },
+    /// Reset PMFW options to the defaults
+    Reset,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CliPerformanceLevel {
+    Auto,
+    Low,
+    High,
+    Manual,
+}
+
+impl From<CliPerformanceLevel> for PerformanceLevel {
+    fn from(value: CliPerformanceLevel) -> Self {
+        match value {
+            CliPerformanceLevel::Auto => PerformanceLevel::Auto,
+            CliPerformanceLevel::Low => PerformanceLevel::Low,
+            CliPerformanceLevel::High => PerformanceLevel::High,
+            CliPerformanceLevel::Manual => PerformanceLevel::Manual,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CliFanSetting {
+    TemperatureSmoothing,
+    HysteresisUp,
+    HysteresisDown,
+    MaxPwmSlewRate,
+    SensorCombination,
+}
+
+impl From<CliFanSetting> for FanSetting {
+    fn from(value: CliFanSetting) -> Self {
+        match value {
+            CliFanSetting::TemperatureSmoothing => FanSetting::TemperatureSmoothing,
+            CliFanSetting::HysteresisUp => FanSetting::HysteresisUp,
+            CliFanSetting::HysteresisDown => FanSetting::HysteresisDown,
+            CliFanSetting::MaxPwmSlewRate => FanSetting::MaxPwmSlewRate,
+            CliFanSetting::SensorCombination => FanSetting::SensorCombination,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CliPowerLevelKind {
+    Core,
+    Memory,
+}
+
+impl From<CliPowerLevelKind> for PowerLevelKind {
+    fn from(value: CliPowerLevelKind) -> Self {
+        match value {
+            CliPowerLevelKind::Core => PowerLevelKind::CoreClock,
+            CliPowerLevelKind::Memory => PowerLevelKind::MemoryClock,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum CliClockspeedType {
+    MaxCoreClock,
+    MaxMemoryClock,
+    MaxVoltage,
+    MinCoreClock,
+    MinMemoryClock,
+    MinVoltage,
+    VoltageOffset,
+    GpuClockOffset,
+    MemClockOffset,
+}
+
+impl CliClockspeedType {
+    pub fn with_pstate(self, pstate: u32) -> ClockspeedType {
+        match self {
+            Self::MaxCoreClock => ClockspeedType::MaxCoreClock,
+            Self::MaxMemoryClock => ClockspeedType::MaxMemoryClock,
+            Self::MaxVoltage => ClockspeedType::MaxVoltage,
+            Self::MinCoreClock => ClockspeedType::MinCoreClock,
+            Self::MinMemoryClock => ClockspeedType::MinMemoryClock,
+            Self::MinVoltage => ClockspeedType::MinVoltage,
+            Self::VoltageOffset => ClockspeedType::VoltageOffset,
+            Self::GpuClockOffset => ClockspeedType::GpuClockOffset(pstate),
+            Self::MemClockOffset => ClockspeedType::MemClockOffset(pstate),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClocksAssignment {
+    pub kind: CliClockspeedType,
+    pub pstate: u32,
+    pub value: i32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heuristics(pub Vec<Option<i32>>);
+
+fn parse_clocks_assignment(s: &str) -> Result<ClocksAssignment, String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid value '{s}', expected `type[:pstate]=value`"))?;
+    let (raw_type, pstate) = match key.split_once(':') {
+        Some((raw_type, raw_pstate)) => (
+            raw_type,
+            raw_pstate
+                .parse()
+                .map_err(|err| format!("invalid pstate '{raw_pstate}': {err}"))?,
+        ),
+        None => (key, 0),
+    };
+
+    Ok(ClocksAssignment {
+        kind: CliClockspeedType::from_str(raw_type, true)?,
+        pstate,
+        value: value
+            .parse()
+            .map_err(|err| format!("invalid value '{value}': {err}"))?,
+    })
+}
+
+fn parse_curve_point(s: &str) -> Result<(i32, f32), String> {
+    let (temp, speed) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid curve point '{s}', expected `temperature=percent`"))?;
+    let temp = temp
+        .parse()
+        .map_err(|err| format!("invalid temperature '{temp}': {err}"))?;
+    let speed: f32 = speed
+        .parse()
+        .map_err(|err| format!("invalid speed '{speed}': {err}"))?;
+    if !(0.0..=100.0).contains(&speed) {
+        return Err(format!("speed {speed} is not in range 0-100"));
+    }
+    Ok((temp, speed / 100.0))
+}
+
+fn parse_heuristics(s: &str) -> Result<Heuristics, String> {
+    s.split(',')
+        .map(|value| {
+            let value = value.trim();
+            if value.is_empty() {
+                Ok(None)
+            } else {
+                value
+                    .parse()
+                    .map(Some)
+                    .map_err(|err| format!("invalid heuristic value '{value}': {err}"))
+            }
+        })
+        .collect::<Result<_, _>>()
+        .map(Heuristics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_clocks_assignment, parse_curve_point, parse_heuristics, CliClockspeedType,
+        ClocksAssignment, Heuristics,
+    };
+
+    #[test]
+    fn parse_clocks_assignments() {
+        assert_eq!(
+            ClocksAssignment {
+                kind: CliClockspeedType::MaxCoreClock,
+                pstate: 0,
+                value: 2100
+            },
+            parse_clocks_assignment("max-core-clock=2100").unwrap()
+        );
+        assert_eq!(
+            ClocksAssignment {
+                kind: CliClockspeedType::GpuClockOffset,
+                pstate: 2,
+                value: -50
+            },
+            parse_clocks_assignment("gpu-clock-offset:2=-50").unwrap()
+        );
+        assert!(parse_clocks_assignment("max-core-clock").is_err());
+        assert!(parse_clocks_assignment("unknown=5").is_err());
+    }
+
+    #[test]
+    fn parse_curve_points() {
+        assert_eq!((60, 0.5), parse_curve_point("60=50").unwrap());
+        assert!(parse_curve_point("60=150").is_err());
+    }
+
+    #[test]
+    fn parse_heuristics_list() {
+        assert_eq!(
+            Heuristics(vec![Some(1), None, Some(-3)]),
+            parse_heuristics("1,,-3").unwrap()
+        );
+    }
 }
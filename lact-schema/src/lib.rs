@@ -18,6 +18,7 @@ use amdgpu_sysfs::{
     },
     hw_mon::Temperature,
 };
+use anyhow::anyhow;
 use indexmap::{IndexMap, IndexSet};
 use serde::{Deserialize, Serialize};
 use serde_with::skip_serializing_none;
@@ -37,6 +38,8 @@ pub enum FanControlMode {
     Static,
     #[default]
     Curve,
+    /// Separate curves for multiple temperature sensors
+    MultiSensor,
 }
 
 impl FromStr for FanControlMode {
@@ -46,6 +49,7 @@ impl FromStr for FanControlMode {
         match s {
             "curve" => Ok(Self::Curve),
             "static" => Ok(Self::Static),
+            "multi_sensor" => Ok(Self::MultiSensor),
             _ => Err("unknown fan control mode".to_string()),
         }
     }
@@ -57,6 +61,81 @@ pub fn default_fan_curve() -> FanCurveMap {
     [(40, 0.2), (50, 0.35), (60, 0.5), (70, 0.75), (80, 1.0)].into()
 }
 
+/// Fan curve for a single temperature sensor, used with `FanControlMode::MultiSensor`
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SensorCurve {
+    pub curve: FanCurveMap,
+    /// Weight of this sensor when using `SensorCombination::Weighted`
+    #[serde(default = "default_sensor_weight")]
+    pub weight: f32,
+}
+
+pub fn default_sensor_weight() -> f32 {
+    1.0
+}
+
+/// Settings for a single fan that differ from the rest of the fans on the GPU
+#[skip_serializing_none]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct FanOverride {
+    /// Curve used for this fan instead of the main curve
+    pub curve: Option<FanCurveMap>,
+    /// Added to the fan speed, from -1 to 1
+    pub speed_offset: Option<f32>,
+}
+
+impl FanOverride {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(curve) = &self.curve {
+            if curve.values().any(|speed| !(0.0..=1.0).contains(speed)) {
+                return Err(anyhow!("Fan speed percentage must be between 0 and 1"));
+            }
+        }
+        if self
+            .speed_offset
+            .is_some_and(|offset| !(-1.0..=1.0).contains(&offset))
+        {
+            return Err(anyhow!("Fan speed offset must be between -1 and 1"));
+        }
+        Ok(())
+    }
+}
+
+/// How fan speeds from multiple sensor curves are combined
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorCombination {
+    /// Use the highest fan speed
+    #[default]
+    Max,
+    /// Use the weighted average of the fan speeds
+    Weighted,
+}
+
+/// How temperature readings are averaged before being used with the fan curve
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TemperatureSmoothing {
+    /// Exponential moving average. `alpha` is the weight of the newest reading, from 0 to 1
+    Ema { alpha: f32 },
+    /// Simple moving average over the given number of readings
+    MovingAverage { samples: usize },
+}
+
+impl TemperatureSmoothing {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            Self::Ema { alpha } if !(*alpha > 0.0 && *alpha <= 1.0) => {
+                Err(anyhow!("EMA alpha must be greater than 0 and at most 1"))
+            }
+            Self::MovingAverage { samples: 0 } => {
+                Err(anyhow!("Moving average must use at least one sample"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Pong;
 
@@ -276,9 +355,64 @@ pub struct FanStats {
     pub speed_min: Option<u32>,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub temperature_smoothing: Option<TemperatureSmoothing>,
+    pub hysteresis_up: Option<u64>,
+    pub hysteresis_down: Option<u64>,
+    pub max_pwm_slew_rate: Option<u32>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub sensor_curves: IndexMap<String, SensorCurve>,
+    pub sensor_combination: Option<SensorCombination>,
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub fan_overrides: IndexMap<u32, FanOverride>,
+    /// Readings of the individual fans, when they can be enumerated
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fans: Vec<SingleFanStats>,
+    /// Set when custom fan control was stopped because of a problem
+    pub alert: Option<FanAlert>,
     // RDNA3+ params
     #[serde(default)]
     pub pmfw_info: PmfwInfo,
+    /// PMFW options set in the config, unlike `pmfw_info` which has the values used by the firmware
+    #[serde(default)]
+    pub pmfw_options: PmfwOptions,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SingleFanStats {
+    pub index: u32,
+    /// Speed in RPM
+    pub speed_current: Option<u32>,
+    pub pwm_current: Option<u8>,
+    /// PWM value the fan is being driven towards
+    pub pwm_target: Option<u8>,
+}
+
+/// A fan control problem, after which fan control was given back to the firmware
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FanAlert {
+    /// The fan was not spinning despite being set to a non-zero speed
+    Stall { fan: u32, temperature: f32 },
+    /// The temperature stayed above the safe limit
+    TemperatureRunaway { sensor: String, temperature: f32 },
+}
+
+impl fmt::Display for FanAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stall { fan, temperature } => {
+                write!(f, "Fan {fan} is not spinning at {temperature:.0}°C")
+            }
+            Self::TemperatureRunaway {
+                sensor,
+                temperature,
+            } => write!(
+                f,
+                "Temperature '{sensor}' is too high under custom fan control ({temperature:.0}°C)"
+            ),
+        }
+    }
 }
 
 #[skip_serializing_none]
@@ -326,6 +460,49 @@ pub struct PowerStats {
     pub cap_default: Option<f64>,
 }
 
+/// A recorded stats sample, with a subset of `DeviceStats`
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct StatsHistorySample {
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub temps: BTreeMap<String, f32>,
+    pub fan_speed: Option<u32>,
+    pub fan_pwm: Option<u8>,
+    pub gpu_clockspeed: Option<u64>,
+    pub vram_clockspeed: Option<u64>,
+    pub voltage: Option<u64>,
+    pub vram_used: Option<u64>,
+    pub power_average: Option<f64>,
+    pub power_current: Option<f64>,
+    pub power_cap: Option<f64>,
+    pub busy_percent: Option<u8>,
+}
+
+impl StatsHistorySample {
+    pub fn from_stats(timestamp: i64, stats: &DeviceStats) -> Self {
+        Self {
+            timestamp,
+            temps: stats
+                .temps
+                .iter()
+                .filter_map(|(key, temp)| Some((key.clone(), temp.current?)))
+                .collect(),
+            fan_speed: stats.fan.speed_current,
+            fan_pwm: stats.fan.pwm_current,
+            gpu_clockspeed: stats.clockspeed.gpu_clockspeed,
+            vram_clockspeed: stats.clockspeed.vram_clockspeed,
+            voltage: stats.voltage.gpu,
+            vram_used: stats.vram.used,
+            power_average: stats.power.average,
+            power_current: stats.power.current,
+            power_cap: stats.power.cap_current,
+            busy_percent: stats.busy_percent,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct PowerStates {
     pub core: Vec<PowerState>,
@@ -369,6 +546,25 @@ impl PmfwOptions {
     pub fn is_empty(&self) -> bool {
         *self == Self::default()
     }
+
+    /// Replaces the options that are set in `other`, leaving the rest as-is
+    pub fn merge(&mut self, other: Self) {
+        let Self {
+            acoustic_limit,
+            acoustic_target,
+            minimum_pwm,
+            target_temperature,
+            zero_rpm,
+            zero_rpm_threshold,
+        } = other;
+
+        self.acoustic_limit = acoustic_limit.or(self.acoustic_limit);
+        self.acoustic_target = acoustic_target.or(self.acoustic_target);
+        self.minimum_pwm = minimum_pwm.or(self.minimum_pwm);
+        self.target_temperature = target_temperature.or(self.target_temperature);
+        self.zero_rpm = zero_rpm.or(self.zero_rpm);
+        self.zero_rpm_threshold = zero_rpm_threshold.or(self.zero_rpm_threshold);
+    }
 }
 
 #[skip_serializing_none]
@@ -383,30 +579,135 @@ pub struct FanOptions<'a> {
     pub pmfw: PmfwOptions,
     pub spindown_delay_ms: Option<u64>,
     pub change_threshold: Option<u64>,
+    pub temperature_smoothing: Option<TemperatureSmoothing>,
+    pub hysteresis_up: Option<u64>,
+    pub hysteresis_down: Option<u64>,
+    pub max_pwm_slew_rate: Option<u32>,
+    pub sensor_curves: Option<IndexMap<String, SensorCurve>>,
+    pub sensor_combination: Option<SensorCombination>,
+    pub fan_overrides: Option<IndexMap<u32, FanOverride>>,
+    /// Settings to reset to their defaults. Values given in these options are applied afterwards
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub clear: Vec<FanSetting>,
+}
+
+/// Optional fan curve setting which can be cleared with `FanOptions::clear`
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FanSetting {
+    TemperatureSmoothing,
+    HysteresisUp,
+    HysteresisDown,
+    MaxPwmSlewRate,
+    SensorCombination,
+}
+
+/// Settings that were skipped, because the system did not stay up after they were applied
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CrashRecoveryInfo {
+    /// Unix timestamp in milliseconds of when the skipped settings were applied, or of when the GPU was reset
+    pub applied_at: i64,
+    /// GPUs which use their last known good settings instead of the clock and voltage settings from the config
+    pub gpu_ids: Vec<String>,
+}
+
+/// A GPU reset that was reported by the driver
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GpuResetEvent {
+    pub id: String,
+    /// Unix timestamp in milliseconds
+    pub timestamp: i64,
+    /// The uevent property which reported the reset, such as `WEDGED=none`
+    pub reason: String,
+    /// Whether the settings of the GPU were reverted
+    pub settings_reverted: bool,
+}
+
+/// Settings of a GPU that were changed outside of the daemon, and reapplied
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SettingsDriftInfo {
+    pub id: String,
+    /// How many times the settings were reapplied since the daemon was started
+    pub count: u64,
+    /// How many times reapplying the settings failed since the daemon was started
+    #[serde(default)]
+    pub failed_count: u64,
+    /// Unix timestamp in milliseconds of when the drift was last detected
+    pub last_detected_at: i64,
+    /// The settings that did not match the config the last time
+    pub last_settings: Vec<String>,
+    /// Set when the settings could not be reapplied the last time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// A setting that would not apply to the GPU
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    /// Path of the setting in the GPU config, such as `pmfw_options.acoustic_limit`
+    pub field: String,
+    pub message: String,
+}
+
+/// A configuration change that is waiting to be confirmed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PendingConfig {
+    pub id: String,
+    /// Seconds left until the change is reverted
+    pub seconds_left: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct ProfilesInfo {
     pub profiles: IndexMap<String, Option<ProfileRule>>,
     pub current_profile: Option<String>,
+    /// Active profile of every GPU, by GPU id
+    #[serde(default)]
+    pub gpu_profiles: IndexMap<String, Option<String>>,
     pub auto_switch: bool,
     pub watcher_state: Option<ProfileWatcherState>,
+    /// Profiles which inherit their settings from another profile
+    #[serde(default)]
+    pub inheritance: IndexMap<String, ProfileInheritance>,
 }
 
 impl PartialEq for ProfilesInfo {
     fn eq(&self, other: &Self) -> bool {
         self.profiles.as_slice() == other.profiles.as_slice()
             && self.current_profile == other.current_profile
+            && self.gpu_profiles.as_slice() == other.gpu_profiles.as_slice()
             && self.auto_switch == other.auto_switch
+            && self.inheritance.as_slice() == other.inheritance.as_slice()
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ProfileInheritance {
+    pub parent: String,
+    /// Names of the overridden settings per GPU id. All other settings are inherited from the parent.
+    /// Nested settings are separated with a `.`, e.g. `fan_control_settings.curve`.
+    pub overridden: IndexMap<String, Vec<String>>,
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(tag = "type", content = "filter", rename_all = "lowercase")]
 pub enum ProfileRule {
     Process(ProcessProfileRule),
     Gamemode(Option<ProcessProfileRule>),
+    /// Matches when all of the rules match
+    All(Vec<ProfileRule>),
+    /// Matches when at least one of the rules matches
+    Any(Vec<ProfileRule>),
+    /// Matches when the rule does not match
+    Not(Box<ProfileRule>),
+    /// Matches during a time window in local time
+    Schedule(ScheduleProfileRule),
+    /// Matches depending on the power source and battery charge
+    #[serde(rename = "power_source")]
+    PowerSource(PowerSourceProfileRule),
+    /// Matches when a GPU sensor value stays above or below a threshold
+    Sensor(SensorProfileRule),
 }
 
 impl Default for ProfileRule {
@@ -415,11 +716,65 @@ impl Default for ProfileRule {
     }
 }
 
+impl fmt::Display for ProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Process(rule) => write!(f, "process {rule}"),
+            Self::Gamemode(None) => write!(f, "gamemode is active"),
+            Self::Gamemode(Some(rule)) => write!(f, "gamemode is active for {rule}"),
+            Self::All(rules) => write_rule_list(f, "all", rules),
+            Self::Any(rules) => write_rule_list(f, "any", rules),
+            Self::Not(rule) => write!(f, "not ({rule})"),
+            Self::Schedule(rule) => write!(f, "{rule}"),
+            Self::PowerSource(rule) => write!(f, "{rule}"),
+            Self::Sensor(rule) => write!(f, "{rule}"),
+        }
+    }
+}
+
+fn write_rule_list(f: &mut fmt::Formatter<'_>, name: &str, rules: &[ProfileRule]) -> fmt::Result {
+    write!(f, "{name} of (")?;
+    for (i, rule) in rules.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        rule.fmt(f)?;
+    }
+    write!(f, ")")
+}
+
 #[skip_serializing_none]
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct ProcessProfileRule {
     pub name: Arc<str>,
     pub args: Option<String>,
+    /// Full path of the process executable
+    pub exe: Option<String>,
+    /// Working directory of the process
+    pub cwd: Option<String>,
+    /// How the filters are matched against the process. Defaults to [`MatchMode::Exact`]
+    pub match_mode: Option<MatchMode>,
+}
+
+impl fmt::Display for ProcessProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}'", self.name)?;
+        if let Some(args) = &self.args {
+            write!(f, " with arguments '{args}'")?;
+        }
+        if let Some(exe) = &self.exe {
+            write!(f, " with executable '{exe}'")?;
+        }
+        if let Some(cwd) = &self.cwd {
+            write!(f, " in directory '{cwd}'")?;
+        }
+        match self.match_mode.unwrap_or_default() {
+            MatchMode::Exact => (),
+            MatchMode::Glob => write!(f, " (glob)")?,
+            MatchMode::Regex => write!(f, " (regex)")?,
+        }
+        Ok(())
+    }
 }
 
 impl Default for ProcessProfileRule {
@@ -427,10 +782,269 @@ impl Default for ProcessProfileRule {
         Self {
             name: String::new().into(),
             args: None,
+            exe: None,
+            cwd: None,
+            match_mode: None,
         }
     }
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The name, executable and directory need to be equal, and the arguments need to be contained in the command line
+    #[default]
+    Exact,
+    /// Shell-style patterns with `*` and `?` wildcards that need to match the whole value
+    Glob,
+    /// Regular expressions that need to match a part of the value
+    Regex,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct ScheduleProfileRule {
+    /// Days of the week on which the time window starts. Every day is used when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub days: Vec<Weekday>,
+    pub start: TimeOfDay,
+    /// Windows which end before they start continue into the next day,
+    /// and windows which end when they start last for the whole day
+    pub end: TimeOfDay,
+}
+
+impl fmt::Display for ScheduleProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.days.is_empty() {
+            write!(f, "every day")?;
+        } else {
+            write!(f, "on ")?;
+            for (i, day) in self.days.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{day}")?;
+            }
+        }
+        write!(f, " from {} to {}", self.start, self.end)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl Weekday {
+    /// All days, starting from Monday
+    pub const ALL: [Self; 7] = [
+        Self::Monday,
+        Self::Tuesday,
+        Self::Wednesday,
+        Self::Thursday,
+        Self::Friday,
+        Self::Saturday,
+        Self::Sunday,
+    ];
+}
+
+impl fmt::Display for Weekday {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Monday => "Monday",
+            Self::Tuesday => "Tuesday",
+            Self::Wednesday => "Wednesday",
+            Self::Thursday => "Thursday",
+            Self::Friday => "Friday",
+            Self::Saturday => "Saturday",
+            Self::Sunday => "Sunday",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Time in the `HH:MM` format
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeOfDay {
+    hour: u8,
+    minute: u8,
+}
+
+impl TimeOfDay {
+    pub fn new(hour: u8, minute: u8) -> Option<Self> {
+        if hour < 24 && minute < 60 {
+            Some(Self { hour, minute })
+        } else {
+            None
+        }
+    }
+
+    pub fn hour(self) -> u8 {
+        self.hour
+    }
+
+    pub fn minute(self) -> u8 {
+        self.minute
+    }
+}
+
+impl FromStr for TimeOfDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split_once(':')
+            .and_then(|(hour, minute)| Self::new(hour.parse().ok()?, minute.parse().ok()?))
+            .ok_or_else(|| format!("Invalid time '{s}', expected the HH:MM format"))
+    }
+}
+
+impl TryFrom<String> for TimeOfDay {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<TimeOfDay> for String {
+    fn from(time: TimeOfDay) -> Self {
+        time.to_string()
+    }
+}
+
+impl fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}", self.hour, self.minute)
+    }
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PowerSourceProfileRule {
+    /// Any power source is allowed when not set
+    pub source: Option<PowerSource>,
+    /// Minimum battery charge in percent
+    pub min_battery: Option<u8>,
+    /// Maximum battery charge in percent
+    pub max_battery: Option<u8>,
+}
+
+impl fmt::Display for PowerSourceProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.source {
+            Some(PowerSource::Ac) => write!(f, "on AC power")?,
+            Some(PowerSource::Battery) => write!(f, "on battery power")?,
+            None => write!(f, "on any power source")?,
+        }
+        match (self.min_battery, self.max_battery) {
+            (Some(min), Some(max)) => write!(f, " with battery between {min}% and {max}%"),
+            (Some(min), None) => write!(f, " with battery at least {min}%"),
+            (None, Some(max)) => write!(f, " with battery at most {max}%"),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PowerSource {
+    Ac,
+    Battery,
+}
+
+#[skip_serializing_none]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct SensorProfileRule {
+    /// Every GPU is checked separately when not set, and the rule only applies to the GPUs where it matches
+    pub gpu_id: Option<String>,
+    pub sensor: ProfileSensor,
+    /// Temperature sensor name. The highest temperature is used when not set
+    pub temperature_key: Option<String>,
+    pub comparison: SensorComparison,
+    pub threshold: f64,
+    /// How long the value needs to stay past the threshold for the rule to start matching,
+    /// and to stay back past the threshold and hysteresis for the rule to stop matching
+    #[serde(default)]
+    pub duration_secs: u64,
+    /// How far the value needs to go back past the threshold for the rule to stop matching
+    #[serde(default)]
+    pub hysteresis: f64,
+}
+
+impl fmt::Display for SensorProfileRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.sensor, &self.temperature_key) {
+            (ProfileSensor::Temperature, Some(key)) => write!(f, "{key} temperature")?,
+            (sensor, _) => write!(f, "{sensor}")?,
+        }
+        if let Some(gpu_id) = &self.gpu_id {
+            write!(f, " of GPU {gpu_id}")?;
+        }
+        write!(f, " {} {}", self.comparison, self.threshold)?;
+        if self.duration_secs > 0 {
+            write!(f, " for {}s", self.duration_secs)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileSensor {
+    /// Temperature in degrees
+    #[default]
+    Temperature,
+    /// GPU usage in percent
+    BusyPercent,
+    /// Power usage in watts
+    PowerDraw,
+}
+
+impl fmt::Display for ProfileSensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Temperature => "temperature",
+            Self::BusyPercent => "GPU usage",
+            Self::PowerDraw => "power draw",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SensorComparison {
+    #[default]
+    Above,
+    Below,
+}
+
+impl fmt::Display for SensorComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Above => f.write_str("above"),
+            Self::Below => f.write_str("below"),
+        }
+    }
+}
+
+/// State of the system power supplies
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PowerSupplyState {
+    /// If the system is running on external power.
+    /// Systems without batteries are always considered to be on external power.
+    pub ac_online: bool,
+    /// Average charge of the system batteries in percent
+    pub battery_capacity: Option<u8>,
+}
+
 pub type ProcessMap = IndexMap<i32, ProcessInfo>;
 
 #[derive(Serialize, Deserialize, Clone, Default)]
@@ -438,6 +1052,8 @@ pub struct ProfileWatcherState {
     pub process_list: ProcessMap,
     pub gamemode_games: IndexSet<i32>,
     pub process_names_map: HashMap<Arc<str>, HashSet<i32>>,
+    #[serde(default)]
+    pub power_supply: PowerSupplyState,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -445,4 +1061,8 @@ pub struct ProfileWatcherState {
 pub struct ProcessInfo {
     pub name: Arc<str>,
     pub cmdline: Box<str>,
+    #[serde(default)]
+    pub exe: Option<Box<str>>,
+    #[serde(default)]
+    pub cwd: Option<Box<str>>,
 }
@@ -7,6 +7,7 @@ impl fmt::Debug for ProfileWatcherState {
             .field("process_list", &self.process_list.len())
             .field("gamemode_games", &self.gamemode_games.len())
             .field("process_names_map", &self.process_names_map.len())
+            .field("power_supply", &self.power_supply)
             .finish()
     }
 }
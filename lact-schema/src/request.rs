@@ -16,6 +16,20 @@ pub enum Request<'a> {
     DeviceStats {
         id: &'a str,
     },
+    SubscribeStats {
+        id: &'a str,
+        interval_ms: u64,
+    },
+    UnsubscribeStats,
+    GetStatsHistory {
+        id: &'a str,
+        /// Unix timestamp in milliseconds
+        since: Option<i64>,
+        /// Unix timestamp in milliseconds
+        until: Option<i64>,
+        /// Aggregate samples into buckets of this many milliseconds
+        resolution: Option<u64>,
+    },
     DeviceClocksInfo {
         id: &'a str,
     },
@@ -59,6 +73,18 @@ pub enum Request<'a> {
     VbiosDump {
         id: &'a str,
     },
+    /// Checks the settings of a GPU against its limits without applying them
+    ValidateGpuConfig {
+        id: &'a str,
+        config: serde_json::Value,
+    },
+    /// Checks the settings of every GPU in a full config, including the resolved settings of each profile
+    ValidateConfig {
+        config: serde_json::Value,
+        /// Only check the settings of this GPU
+        #[serde(default)]
+        gpu_id: Option<String>,
+    },
     ListProfiles {
         #[serde(default)]
         include_state: bool,
@@ -67,6 +93,9 @@ pub enum Request<'a> {
         name: Option<String>,
         #[serde(default)]
         auto_switch: bool,
+        /// Only set the profile of this GPU
+        #[serde(default)]
+        gpu_id: Option<String>,
     },
     CreateProfile {
         name: String,
@@ -89,11 +118,21 @@ pub enum Request<'a> {
     EnableOverdrive,
     DisableOverdrive,
     GenerateSnapshot,
-    ConfirmPendingConfig(ConfirmCommand),
+    ListPendingConfigs,
+    CrashRecoveryInfo,
+    ListGpuResets,
+    ListSettingsDrift,
+    ConfirmPendingConfig {
+        #[serde(flatten)]
+        command: ConfirmCommand,
+        /// Only confirm the change of this GPU. All pending changes are confirmed if not specified
+        #[serde(default)]
+        id: Option<String>,
+    },
     RestConfig,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
 #[serde(tag = "command", rename_all = "snake_case")]
 pub enum ConfirmCommand {
     Confirm,
@@ -136,16 +175,18 @@ pub enum ProfileBase {
     Empty,
     Default,
     Profile(String),
+    /// Inherit the settings of a profile, including its future changes
+    Inherit(String),
 }
 
 impl fmt::Display for ProfileBase {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let text = match self {
-            ProfileBase::Empty => "Empty",
-            ProfileBase::Default => "Default",
-            ProfileBase::Profile(name) => name,
-        };
-        text.fmt(f)
+        match self {
+            ProfileBase::Empty => "Empty".fmt(f),
+            ProfileBase::Default => "Default".fmt(f),
+            ProfileBase::Profile(name) => name.fmt(f),
+            ProfileBase::Inherit(name) => write!(f, "{name} (inherit)"),
+        }
     }
 }
 
@@ -1,4 +1,8 @@
-use crate::{FanControlMode, FanOptions, PmfwOptions, Pong, Request, Response};
+use crate::{
+    request::ConfirmCommand, FanControlMode, FanOptions, FanSetting, MatchMode, PmfwOptions, Pong,
+    PowerSource, PowerSourceProfileRule, ProcessProfileRule, ProfileRule, ProfileSensor, Request,
+    Response, ScheduleProfileRule, SensorComparison, SensorProfileRule, TimeOfDay, Weekday,
+};
 use anyhow::anyhow;
 use serde_json::json;
 use std::collections::BTreeMap;
@@ -13,6 +17,37 @@ fn ping_requset() {
     assert_eq!(request, Request::Ping);
 }
 
+#[test]
+fn confirm_pending_config_request() {
+    let value = r#"{
+        "command": "confirm_pending_config",
+        "args": {
+            "command": "revert"
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    assert_eq!(
+        request,
+        Request::ConfirmPendingConfig {
+            command: ConfirmCommand::Revert,
+            id: None,
+        }
+    );
+
+    let request = Request::ConfirmPendingConfig {
+        command: ConfirmCommand::Confirm,
+        id: Some("1002:687F-1043:0555-0000:0b:00.0".to_owned()),
+    };
+    let expected = json!({
+        "command": "confirm_pending_config",
+        "args": {
+            "command": "confirm",
+            "id": "1002:687F-1043:0555-0000:0b:00.0"
+        }
+    });
+    assert_eq!(serde_json::to_value(request).unwrap(), expected);
+}
+
 #[test]
 fn pong_response() {
     let expected_response = json!({
@@ -83,6 +118,168 @@ fn set_fan_clocks() {
         pmfw: PmfwOptions::default(),
         spindown_delay_ms: None,
         change_threshold: None,
+        ..Default::default()
+    });
+    assert_eq!(expected_request, request);
+}
+
+#[test]
+fn set_fan_control_clear_request() {
+    let value = r#"{
+        "command": "set_fan_control",
+        "args": {
+            "id": "123",
+            "enabled": true,
+            "mode": "curve",
+            "clear": ["hysteresis_up", "max_pwm_slew_rate"]
+        }
+    }"#;
+    let request: Request = serde_json::from_str(value).unwrap();
+    let expected_request = Request::SetFanControl(FanOptions {
+        id: "123",
+        enabled: true,
+        mode: Some(FanControlMode::Curve),
+        clear: vec![FanSetting::HysteresisUp, FanSetting::MaxPwmSlewRate],
+        ..Default::default()
     });
     assert_eq!(expected_request, request);
 }
+
+#[test]
+fn profile_rule_combinators() {
+    let rule = ProfileRule::All(vec![
+        ProfileRule::Process(ProcessProfileRule {
+            name: "blender".into(),
+            args: None,
+            ..Default::default()
+        }),
+        ProfileRule::Not(Box::new(ProfileRule::Process(ProcessProfileRule {
+            name: "obs".into(),
+            args: None,
+            ..Default::default()
+        }))),
+    ]);
+    let expected = json!({
+        "type": "all",
+        "filter": [
+            {
+                "type": "process",
+                "filter": { "name": "blender" }
+            },
+            {
+                "type": "not",
+                "filter": {
+                    "type": "process",
+                    "filter": { "name": "obs" }
+                }
+            }
+        ]
+    });
+
+    assert_eq!(expected, serde_json::to_value(&rule).unwrap());
+    assert_eq!(rule, serde_json::from_value(expected).unwrap());
+    assert_eq!(
+        "all of (process 'blender', not (process 'obs'))",
+        rule.to_string()
+    );
+}
+
+#[test]
+fn process_rule_match_mode() {
+    let rule = ProfileRule::Process(ProcessProfileRule {
+        name: "*.exe".into(),
+        exe: Some("/opt/games/*".into()),
+        match_mode: Some(MatchMode::Glob),
+        ..Default::default()
+    });
+    let expected = json!({
+        "type": "process",
+        "filter": {
+            "name": "*.exe",
+            "exe": "/opt/games/*",
+            "match_mode": "glob"
+        }
+    });
+
+    assert_eq!(expected, serde_json::to_value(&rule).unwrap());
+    assert_eq!(rule, serde_json::from_value(expected).unwrap());
+    assert_eq!(
+        "process '*.exe' with executable '/opt/games/*' (glob)",
+        rule.to_string()
+    );
+}
+
+#[test]
+fn schedule_rule() {
+    let rule = ProfileRule::Schedule(ScheduleProfileRule {
+        days: vec![Weekday::Saturday, Weekday::Sunday],
+        start: TimeOfDay::new(22, 0).unwrap(),
+        end: TimeOfDay::new(6, 30).unwrap(),
+    });
+    let expected = json!({
+        "type": "schedule",
+        "filter": {
+            "days": ["saturday", "sunday"],
+            "start": "22:00",
+            "end": "06:30"
+        }
+    });
+
+    assert_eq!(expected, serde_json::to_value(&rule).unwrap());
+    assert_eq!(rule, serde_json::from_value(expected).unwrap());
+    assert_eq!("on Saturday, Sunday from 22:00 to 06:30", rule.to_string());
+
+    assert!("24:00".parse::<TimeOfDay>().is_err());
+    assert!("9".parse::<TimeOfDay>().is_err());
+    assert_eq!(TimeOfDay::new(9, 5), "9:05".parse().ok());
+}
+
+#[test]
+fn power_source_rule() {
+    let rule = ProfileRule::PowerSource(PowerSourceProfileRule {
+        source: Some(PowerSource::Battery),
+        min_battery: None,
+        max_battery: Some(30),
+    });
+    let expected = json!({
+        "type": "power_source",
+        "filter": {
+            "source": "battery",
+            "max_battery": 30
+        }
+    });
+
+    assert_eq!(expected, serde_json::to_value(&rule).unwrap());
+    assert_eq!(rule, serde_json::from_value(expected).unwrap());
+    assert_eq!(
+        "on battery power with battery at most 30%",
+        rule.to_string()
+    );
+}
+
+#[test]
+fn sensor_rule() {
+    let rule = ProfileRule::Sensor(SensorProfileRule {
+        gpu_id: None,
+        sensor: ProfileSensor::Temperature,
+        temperature_key: Some("junction".to_owned()),
+        comparison: SensorComparison::Above,
+        threshold: 90.0,
+        duration_secs: 30,
+        hysteresis: 5.0,
+    });
+    let value = json!({
+        "type": "sensor",
+        "filter": {
+            "sensor": "temperature",
+            "temperature_key": "junction",
+            "comparison": "above",
+            "threshold": 90,
+            "duration_secs": 30,
+            "hysteresis": 5
+        }
+    });
+
+    assert_eq!(rule, serde_json::from_value(value).unwrap());
+    assert_eq!("junction temperature above 90 for 30s", rule.to_string());
+}